@@ -0,0 +1,154 @@
+//! Per-request tokenize cost of the hand-written lexer against the regex tokenizer it
+//! replaced. Run with `cargo bench --bench tokenize`.
+
+use std::collections::VecDeque;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use regex::Regex;
+use backend::error::ParseError;
+use backend::registry::Registry;
+use backend::tokens::{tokenize, Literal, Span, SpannedToken, Token};
+
+const INPUTS: &[(&str, &str)] = &[
+    ("number", "42"),
+    ("arithmetic", "1 + 2 * 3 - 4 / 5 ^ 6 % 7"),
+    ("functions", "sin(x)^2 + cos(x)^2 + average(1, 2.5, 3e2, .5)"),
+    ("graph", "graph(y = 3x^2 - 2x + ln(abs(x) + 1))"),
+    ("bitwise", "0xFF & ~0b1010 | 0o17 << 2 >> 1"),
+];
+
+/// The regex tokenizer as it was before the hand-written lexer, kept as the baseline.
+fn regex_tokenize(input: String) -> Result<VecDeque<SpannedToken>, ParseError> {
+    let plus_re = Regex::new(r"^\+").unwrap();
+    let minus_re = Regex::new(r"^-").unwrap();
+    let times_re = Regex::new(r"^\*").unwrap();
+    let divide_re = Regex::new(r"^/").unwrap();
+    let left_paren_re = Regex::new(r"^\(").unwrap();
+    let right_paren_re = Regex::new(r"^\)").unwrap();
+    let number_re = Regex::new(r"^(\d+\.?\d*|\.\d+)([eE][+-]?\d+)?").unwrap();
+    let modulus_re = Regex::new(r"^\%").unwrap();
+    let exponent_re = Regex::new(r"^\^").unwrap();
+    let function_re = Regex::new(r"^([a-z]+)\(").unwrap();
+    let comma_re = Regex::new(r"^,").unwrap();
+    let help_re = Regex::new(r"^help").unwrap();
+    let graph_re = Regex::new(r"^graph\(").unwrap();
+    let variable_re = Regex::new(r"^[a-z]+").unwrap();
+    let eq_re = Regex::new(r"^=").unwrap();
+    let radix_re = Regex::new(r"^0([xX][0-9a-fA-F]+|[bB][01]+|[oO][0-7]+)").unwrap();
+    let bit_and_re = Regex::new(r"^&").unwrap();
+    let bit_or_re = Regex::new(r"^\|").unwrap();
+    let bit_not_re = Regex::new(r"^~").unwrap();
+    let shift_left_re = Regex::new(r"^<<").unwrap();
+    let shift_right_re = Regex::new(r"^>>").unwrap();
+    let source = input.as_str();
+    let mut input = source.trim_start();
+    let mut tokens = VecDeque::new();
+    if input.trim_end().is_empty() {
+        return Err(ParseError::EmptyInput)
+    }
+    while !input.is_empty() {
+        let start = source.len() - input.len();
+        let token;
+        let length;
+        if plus_re.is_match(input) {
+            token = Token::Plus;
+            length = 1;
+        } else if minus_re.is_match(input) {
+            token = Token::Minus;
+            length = 1;
+        } else if times_re.is_match(input) {
+            token = Token::Times;
+            length = 1;
+        } else if divide_re.is_match(input) {
+            token = Token::Divide;
+            length = 1;
+        } else if modulus_re.is_match(input) {
+            token = Token::Modulus;
+            length = 1;
+        } else if exponent_re.is_match(input) {
+            token = Token::Exponent;
+            length = 1;
+        } else if left_paren_re.is_match(input) {
+            token = Token::LeftParen;
+            length = 1;
+        } else if right_paren_re.is_match(input) {
+            token = Token::RightParen;
+            length = 1;
+        } else if function_re.is_match(input) && Registry::builtin().contains(function_re.captures(input).unwrap().get(1).unwrap().as_str()) {
+            let capture = function_re.captures(input).unwrap();
+            let matching_word = capture.get(1).unwrap().as_str();
+            token = Token::FunctionCall(matching_word.to_string());
+            length = matching_word.len() + 1;
+        } else if bit_and_re.is_match(input) {
+            token = Token::BitAnd;
+            length = 1;
+        } else if bit_or_re.is_match(input) {
+            token = Token::BitOr;
+            length = 1;
+        } else if bit_not_re.is_match(input) {
+            token = Token::BitNot;
+            length = 1;
+        } else if shift_left_re.is_match(input) {
+            token = Token::ShiftLeft;
+            length = 2;
+        } else if shift_right_re.is_match(input) {
+            token = Token::ShiftRight;
+            length = 2;
+        } else if comma_re.is_match(input) {
+            token = Token::Comma;
+            length = 1;
+        } else if help_re.is_match(input) {
+            token = Token::Help;
+            length = 4;
+        } else if graph_re.is_match(input) {
+            token = Token::Graph;
+            length = 6;
+        } else if eq_re.is_match(input) {
+            token = Token::Equal;
+            length = 1;
+        } else if variable_re.is_match(input) {
+            let capture = variable_re.captures(input).unwrap();
+            let matching_word = capture.get(0).unwrap().as_str();
+            token = Token::Variable(matching_word.to_string());
+            length = matching_word.len();
+        } else if radix_re.is_match(input) {
+            let literal = &radix_re.captures(input).unwrap()[0];
+            let radix = match literal.as_bytes()[1] {
+                b'x' | b'X' => 16,
+                b'b' | b'B' => 2,
+                _ => 8,
+            };
+            length = literal.len();
+            match u64::from_str_radix(&literal[2..], radix) {
+                Ok(value) => token = Token::Integer(value.into()),
+                Err(_) => return Err(ParseError::InvalidNumber { span: Span::new(start, start + length) }),
+            }
+        } else if number_re.is_match(input) {
+            let value = number_re.captures(input).unwrap()[0].to_string();
+            length = value.len();
+            token = Token::Number(Literal::parse(&value).unwrap());
+        } else {
+            let character = input.chars().next().unwrap();
+            return Err(ParseError::UnexpectedCharacter { character, span: Span::new(start, start + character.len_utf8()) })
+        }
+        tokens.push_back(SpannedToken { token, span: Span::new(start, start + length) });
+        input = input[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+fn tokenize_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenize");
+    for (name, input) in INPUTS {
+        assert_eq!(regex_tokenize(input.to_string()), tokenize(input.to_string()));
+        group.bench_with_input(BenchmarkId::new("regex", name), input, |b, input| {
+            b.iter(|| regex_tokenize(black_box(input.to_string())))
+        });
+        group.bench_with_input(BenchmarkId::new("lexer", name), input, |b, input| {
+            b.iter(|| tokenize(black_box(input.to_string())))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, tokenize_benchmark);
+criterion_main!(benches);
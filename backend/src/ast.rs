@@ -1,15 +1,20 @@
 use std::collections::VecDeque;
-use crate::tokens::Token;
+use num_bigint::BigInt;
+use crate::error::ParseError;
+use crate::math::AngleMode;
+use crate::tokens::{Literal, Span, SpannedToken, Token};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ASTNode {
     BinaryNode(BinaryNode),
     UnaryNode(UnaryNode),
-    NumberNode(f64),
-    UnfinishedNode(UnfinishedNode),
+    NumberNode(Literal, Span),
+    IntegerNode(BigInt, Span),
     FunctionCall(FunctionCall),
-    Comma,
-    Variable(String),
+    Variable(String, Span),
+    /// A list literal `[a, b, c]`.
+    List(Vec<ASTNode>, Span),
+    Index(IndexNode),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -18,19 +23,38 @@ pub struct BinaryNode {
     pub left: Box<ASTNode>,
     pub right: Box<ASTNode>,
     pub operation: BinaryOperation,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct UnaryNode {
     pub priority: u64,
     pub child: Box<ASTNode>,
-    pub operation: UnaryOperation
+    pub operation: UnaryOperation,
+    pub span: Span,
+}
+
+/// `target[index]`, counting from 0 and from the end for negative indexes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexNode {
+    pub target: Box<ASTNode>,
+    pub index: Index,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Index {
+    Element(Box<ASTNode>),
+    /// `[start:end]`, the elements from `start` up to but not including `end`. Either bound
+    /// may be left out.
+    Slice(Option<Box<ASTNode>>, Option<Box<ASTNode>>),
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct FunctionCall {
     pub inputs: VecDeque<ASTNode>,
     pub operation: String,
+    pub span: Span,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -41,409 +65,472 @@ pub enum BinaryOperation {
     Divide,
     Exponent,
     Modulus,
+    Phasor,
+    BitAnd,
+    BitOr,
+    ShiftLeft,
+    ShiftRight,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum UnaryOperation {
     Negate,
-    Parens
+    Parens,
+    BitNot,
+    SquareRoot,
+    Not,
+    Factorial,
+    DoubleFactorial,
+    Percent,
+    /// An angle suffix such as `30deg`, converting to the session's angle mode.
+    Angle(AngleMode),
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum UnfinishedNode {
-    Plus,
-    Minus,
-    Times,
-    Divide,
-    Modulus,
-    Exponent,
-    LeftParen,
-    Negate,
-    FunctionCall(String),
+impl ASTNode {
+    /// The part of the input this node was parsed from.
+    pub fn span(&self) -> Span {
+        match self {
+            ASTNode::BinaryNode(a) => a.span,
+            ASTNode::UnaryNode(a) => a.span,
+            ASTNode::FunctionCall(a) => a.span,
+            ASTNode::Index(a) => a.span,
+            ASTNode::NumberNode(_, span) | ASTNode::IntegerNode(_, span) | ASTNode::Variable(_, span) | ASTNode::List(_, span) => *span,
+        }
+    }
+
+    pub fn children(&self) -> Vec<&ASTNode> {
+        match self {
+            ASTNode::BinaryNode(a) => vec![&a.left, &a.right],
+            ASTNode::UnaryNode(a) => vec![&a.child],
+            ASTNode::FunctionCall(a) => a.inputs.iter().collect(),
+            ASTNode::List(a, _) => a.iter().collect(),
+            ASTNode::Index(a) => match &a.index {
+                Index::Element(index) => vec![&a.target, index],
+                Index::Slice(start, end) => std::iter::once(&a.target).chain(start).chain(end).map(|a| a.as_ref()).collect(),
+            },
+            ASTNode::NumberNode(..) | ASTNode::IntegerNode(..) | ASTNode::Variable(..) => vec![],
+        }
+    }
+
+    /// Levels of nodes from this one down to its deepest leaf, counted without recursing so
+    /// any tree can be measured.
+    pub fn depth(&self) -> usize {
+        let mut deepest = 0;
+        let mut pending = vec![(self, 1)];
+        while let Some((node, depth)) = pending.pop() {
+            deepest = deepest.max(depth);
+            pending.extend(node.children().into_iter().map(|a| (a, depth + 1)));
+        }
+        deepest
+    }
 }
 
-pub fn build_ast(mut tokens: VecDeque<Token>) -> Result<ASTNode, String> {
-    let mut stack = vec![];
-    while !tokens.is_empty() {
-        let token = tokens.pop_front().unwrap();
+/// How deeply expressions may nest, in parentheses, operators or function calls, before
+/// parsing gives up. Parsing and evaluation recurse once per level on the Rust stack.
+pub const MAX_NESTING: usize = 1000;
+
+const NEGATE_PRIORITY: u64 = 9;
+const PARENS_PRIORITY: u64 = 10;
+
+impl BinaryOperation {
+    fn from_token(token: &Token) -> Option<BinaryOperation> {
         match token {
-            Token::Number(a) => {
-                if stack.len() > 0{
-                    let previous = stack.pop().unwrap();
-                    match &previous{
-                        ASTNode::UnfinishedNode(b) => {
-                            match b{
-                                UnfinishedNode::FunctionCall(_) => {
-                                    stack.push(previous);
-                                    stack.push(ASTNode::NumberNode(a));
-                                },
-                               _ => {
-                                stack.push(previous);
-                                stack.push(ASTNode::NumberNode(a));
-                                let _ = combine_finished_val(&mut stack);
-                               }
-                            }
-                        },
-                        _ => {
-                            stack.push(previous);
-                            stack.push(ASTNode::NumberNode(a));
-                            let _ = combine_finished_val(&mut stack);
-                        }
-                    }
-                } else {
-                    stack.push(ASTNode::NumberNode(a));
-                }
-            }
-            Token::Plus => {
-                stack.push(ASTNode::UnfinishedNode(UnfinishedNode::Plus))
-            }
-            Token::Minus => {
-                if stack.len() > 0 {
-                    let pred = stack.pop().unwrap();
-                    match pred{
-                        ASTNode::UnfinishedNode(_) => {
-                            stack.push(pred);
-                            stack.push(ASTNode::UnfinishedNode(UnfinishedNode::Negate))
-                        },
-                        _ => {
-                            stack.push(pred);
-                            stack.push(ASTNode::UnfinishedNode(UnfinishedNode::Minus))
-                        }
-                    }
-                } else {
-                    stack.push(ASTNode::UnfinishedNode(UnfinishedNode::Negate));
-                }
-            }
-            Token::Times => {
-                stack.push(ASTNode::UnfinishedNode(UnfinishedNode::Times))
+            Token::Plus => Some(BinaryOperation::Plus),
+            Token::Minus => Some(BinaryOperation::Minus),
+            Token::Times => Some(BinaryOperation::Times),
+            Token::Divide => Some(BinaryOperation::Divide),
+            Token::Modulus => Some(BinaryOperation::Modulus),
+            Token::Exponent => Some(BinaryOperation::Exponent),
+            Token::Phasor => Some(BinaryOperation::Phasor),
+            Token::BitAnd => Some(BinaryOperation::BitAnd),
+            Token::BitOr => Some(BinaryOperation::BitOr),
+            Token::ShiftLeft => Some(BinaryOperation::ShiftLeft),
+            Token::ShiftRight => Some(BinaryOperation::ShiftRight),
+            Token::Less => Some(BinaryOperation::Less),
+            Token::Greater => Some(BinaryOperation::Greater),
+            Token::LessEqual => Some(BinaryOperation::LessEqual),
+            Token::GreaterEqual => Some(BinaryOperation::GreaterEqual),
+            Token::EqualEqual => Some(BinaryOperation::Equal),
+            Token::NotEqual => Some(BinaryOperation::NotEqual),
+            Token::And => Some(BinaryOperation::And),
+            Token::Or => Some(BinaryOperation::Or),
+            _ => None,
+        }
+    }
+
+    /// Bitwise operators share levels with the arithmetic ones, as in Go and Swift:
+    /// `&` and shifts multiply, `|` adds. This avoids C's surprising `a & b == c`.
+    /// Comparisons sit below arithmetic and `and`/`or` below those, as in Python, so
+    /// `x + 1 <= 2x or x == 0` needs no parentheses. A phasor binds tighter than `*` so
+    /// `5∠30 * 2∠60` multiplies two phasors.
+    pub fn priority(&self) -> u64 {
+        match self {
+            BinaryOperation::Or => 1,
+            BinaryOperation::And => 2,
+            BinaryOperation::Less | BinaryOperation::Greater | BinaryOperation::LessEqual | BinaryOperation::GreaterEqual => 3,
+            BinaryOperation::Equal | BinaryOperation::NotEqual => 3,
+            BinaryOperation::Plus | BinaryOperation::Minus | BinaryOperation::BitOr => 4,
+            BinaryOperation::Times | BinaryOperation::Divide | BinaryOperation::Modulus => 5,
+            BinaryOperation::BitAnd | BinaryOperation::ShiftLeft | BinaryOperation::ShiftRight => 5,
+            BinaryOperation::Phasor => 6,
+            BinaryOperation::Exponent => 7,
+        }
+    }
+
+    fn is_right_associative(&self) -> bool {
+        *self == BinaryOperation::Exponent
+    }
+}
+
+/// One line of input: an expression to evaluate, a `name = expression` binding or a
+/// `name(a, b) = expression` function definition.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+    Expression(ASTNode),
+    Assignment { name: String, value: ASTNode, span: Span },
+    FunctionDefinition { name: String, parameters: Vec<String>, body: ASTNode, span: Span },
+}
+
+fn starts_operand(token: &Token) -> bool {
+    matches!(token, Token::Number(_) | Token::Integer(_) | Token::Variable(_) | Token::FunctionCall(_) | Token::LeftParen
+        | Token::SquareRoot | Token::BitNot | Token::Not | Token::LeftBracket)
+}
+
+/// Precedence-climbing parser over the token stream produced by `tokenize`.
+struct Parser {
+    tokens: VecDeque<SpannedToken>,
+    end: Span,
+    /// Calls to `parse_expression` in progress.
+    depth: usize,
+}
+
+pub fn build_ast(tokens: VecDeque<SpannedToken>) -> Result<ASTNode, ParseError> {
+    let end = tokens.back().map_or(Span::default(), |a| Span::new(a.span.end, a.span.end));
+    let mut parser = Parser { tokens, end, depth: 0 };
+    let ast = parser.parse_expression(0)?;
+    match parser.tokens.pop_front() {
+        // Long chains such as `1-1-1-...` or `5!!!...` nest without recursing in the parser.
+        None if ast.depth() > MAX_NESTING => Err(ParseError::TooDeep { span: ast.span() }),
+        None => Ok(ast),
+        Some(SpannedToken { token: Token::RightParen, span }) => Err(ParseError::UnbalancedParen { span }),
+        Some(SpannedToken { token: Token::RightBracket, span }) => Err(ParseError::UnbalancedBracket { span }),
+        Some(SpannedToken { token, span }) => Err(ParseError::UnexpectedToken { token, span }),
+    }
+}
+
+/// Parses `name = expression` as an assignment, `name(a, b) = expression` as a function
+/// definition and anything else as an expression.
+pub fn build_statement(mut tokens: VecDeque<SpannedToken>) -> Result<Statement, ParseError> {
+    if let Some((name, parameters, length)) = function_header(&tokens) {
+        let span = tokens[0].span;
+        let equal = tokens.drain(..length).next_back().unwrap().span;
+        if tokens.is_empty() {
+            return Err(ParseError::UnexpectedEnd { span: Span::new(equal.end, equal.end) });
+        }
+        return Ok(Statement::FunctionDefinition { name, parameters, body: build_ast(tokens)?, span });
+    }
+    if let (Some(Token::Variable(name)), Some(Token::Equal)) = (tokens.front().map(|a| &a.token), tokens.get(1).map(|a| &a.token)) {
+        let name = name.clone();
+        let span = tokens.pop_front().unwrap().span;
+        let equal = tokens.pop_front().unwrap().span;
+        if tokens.is_empty() {
+            return Err(ParseError::UnexpectedEnd { span: Span::new(equal.end, equal.end) });
+        }
+        return Ok(Statement::Assignment { name, value: build_ast(tokens)?, span });
+    }
+    Ok(Statement::Expression(build_ast(tokens)?))
+}
+
+/// The name a function definition defines, before it is known to the tokenizer. The
+/// input has to be tokenized again with this name so the body can call itself.
+pub fn defined_function(tokens: &VecDeque<SpannedToken>) -> Option<String> {
+    function_header(tokens).map(|a| a.0)
+}
+
+/// Matches `name(a, b) =` at the start of `tokens`, where `name(` may already be lexed as a
+/// function call. Returns the name, the parameters and the number of tokens in the header.
+fn function_header(tokens: &VecDeque<SpannedToken>) -> Option<(String, Vec<String>, usize)> {
+    let (name, mut index) = match (&tokens.front()?.token, tokens.get(1).map(|a| &a.token)) {
+        (Token::FunctionCall(name), _) => (name.clone(), 1),
+        (Token::Variable(name), Some(Token::LeftParen)) => (name.clone(), 2),
+        _ => return None,
+    };
+    let mut parameters = vec![];
+    if *tokens.get(index)? != Token::RightParen {
+        loop {
+            match &tokens.get(index)?.token {
+                Token::Variable(parameter) => parameters.push(parameter.clone()),
+                _ => return None,
             }
-            Token::Divide => {
-                stack.push(ASTNode::UnfinishedNode(UnfinishedNode::Divide))
+            match &tokens.get(index + 1)?.token {
+                Token::Comma => index += 2,
+                Token::RightParen => break,
+                _ => return None,
             }
-            Token::Modulus => {
-                stack.push(ASTNode::UnfinishedNode(UnfinishedNode::Modulus))
+        }
+        index += 1;
+    }
+    (*tokens.get(index + 1)? == Token::Equal).then_some((name, parameters, index + 2))
+}
+
+impl Parser {
+    /// Parses operators whose priority is at least `min_priority`. Left associative
+    /// operators parse their right side one level tighter so `a-b-c` groups as `(a-b)-c`,
+    /// while exponent reuses its own level so `2^3^2` groups as `2^(3^2)`.
+    fn parse_expression(&mut self, min_priority: u64) -> Result<ASTNode, ParseError> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(ParseError::TooDeep { span: self.tokens.front().map_or(self.end, |a| a.span) });
+        }
+        let mut left = self.parse_postfix()?;
+        while let Some((operation, implicit)) = self.peek_operation() {
+            let priority = operation.priority();
+            if priority < min_priority {
+                break;
             }
-            Token::Exponent => {
-                stack.push(ASTNode::UnfinishedNode(UnfinishedNode::Exponent))
+            if !implicit {
+                self.tokens.pop_front();
             }
-            Token::LeftParen => {
-                if stack.len() > 0 {
-                    let pred = stack.pop().unwrap();
-                    match pred.clone() {
-                        ASTNode::BinaryNode(_) | ASTNode::NumberNode(_) | ASTNode::Variable(_) | ASTNode::FunctionCall(_) => {
-                            stack.push(pred);
-                            stack.push(ASTNode::UnfinishedNode(UnfinishedNode::Times));
-                        },
-                        ASTNode::UnaryNode(a) => {
-                            match a.operation{
-                                UnaryOperation::Negate => {
-                                    stack.push(pred);
-                                },
-                                UnaryOperation::Parens => {
-                                    stack.push(pred);
-                                    stack.push(ASTNode::UnfinishedNode(UnfinishedNode::Times));
-                                },
-                            }
-                        },
-                        ASTNode::UnfinishedNode(_) => {
-                            stack.push(pred);
-                        },
-                        ASTNode::Comma => {
-                            stack.push(pred);
-                        }
-                    }
+            let next_priority = if operation.is_right_associative() { priority } else { priority + 1 };
+            let right = self.parse_expression(next_priority)?;
+            let span = left.span().to(right.span());
+            left = ASTNode::BinaryNode(BinaryNode {
+                priority,
+                left: Box::new(left),
+                right: Box::new(right),
+                operation,
+                span,
+            });
+        }
+        self.depth -= 1;
+        Ok(left)
+    }
+
+    /// Returns the next binary operation and whether it is an implied multiplication
+    /// such as `2x`, `3(4)`, `2sin(x)` or `2√3` that has no token of its own.
+    fn peek_operation(&self) -> Option<(BinaryOperation, bool)> {
+        match &self.tokens.front()?.token {
+            Token::Variable(_) | Token::FunctionCall(_) | Token::LeftParen | Token::SquareRoot => Some((BinaryOperation::Times, true)),
+            token => BinaryOperation::from_token(token).map(|operation| (operation, false)),
+        }
+    }
+
+    /// Parses an operand followed by any postfix `!`, `!!`, `%`, index `[i]` or angle suffix
+    /// `deg`, `rad` or `grad`, which bind tighter than every binary operator. `%` is a
+    /// percentage unless an operand follows it, see `is_percent`.
+    fn parse_postfix(&mut self) -> Result<ASTNode, ParseError> {
+        let mut child = self.parse_prefix()?;
+        loop {
+            let operation = match self.tokens.front().map(|a| &a.token) {
+                Some(Token::LeftBracket) => {
+                    child = self.parse_index(child)?;
+                    continue;
                 }
-                stack.push(ASTNode::UnfinishedNode(UnfinishedNode::LeftParen))
-            }
-            Token::RightParen => {
-                println!("{:?}", stack);
-                if stack.len() > 1 {
-                    let val = stack.pop().unwrap();
-                    let left = stack.pop().unwrap();
-                    match (val, left) {
-                        (ASTNode::UnfinishedNode(_), _) => {
-                            panic!();
-                        }
-                        (val, ASTNode::UnfinishedNode(UnfinishedNode::LeftParen)) => {
-                            stack.push(ASTNode::UnaryNode(UnaryNode {
-                                priority: 10,
-                                operation: UnaryOperation::Parens,
-                                child: Box::new(val),
-                            }));
-                        }
-                        (val, ASTNode::Comma) => {
-                            let mut children = VecDeque::new();
-                            children.push_back(val);
-                            while stack.len() > 1 {
-                                let val = stack.pop().unwrap();
-                                let left = stack.pop().unwrap();
-                                match left {
-                                    ASTNode::Comma => {
-                                        children.push_back(val);
-                                    }
-                                    ASTNode::UnfinishedNode(UnfinishedNode::FunctionCall(a)) => {
-                                        children.push_back(val);
-                                        stack.push(ASTNode::FunctionCall(FunctionCall {
-                                            inputs: children,
-                                            operation: a,
-                                        }));
-                                        match combine_finished_val(&mut stack) {
-                                            Ok(_) => {}
-                                            Err(e) => {
-                                                return Err(e)
-                                            }
-                                        }
-                                        break;
-                                    }
-                                    _ => {
-
-                                    }
-                                }
-                            }
-                        }
-                        (val, ASTNode::UnfinishedNode(UnfinishedNode::FunctionCall(a))) => {
-                            let mut children = VecDeque::new();
-                            children.push_back(val);
-                            stack.push(ASTNode::FunctionCall(FunctionCall {
-                                inputs: children,
-                                operation: a,
-                            }));
-                        }
-                        _ => {
-                            return Err("Syntax Error".to_string());
-                        }
-                    }
-                    match combine_finished_val(&mut stack){
-                        Ok(_) => {},
-                        Err(e) => {return Err(e);},
-                    }
+                Some(Token::Factorial) => UnaryOperation::Factorial,
+                Some(Token::DoubleFactorial) => UnaryOperation::DoubleFactorial,
+                Some(Token::Modulus) if self.is_percent()? => UnaryOperation::Percent,
+                Some(Token::Variable(a)) => match AngleMode::parse(a) {
+                    Some(unit) => UnaryOperation::Angle(unit),
+                    None => return Ok(child),
+                },
+                _ => return Ok(child),
+            };
+            let span = child.span().to(self.next()?.span);
+            child = ASTNode::UnaryNode(UnaryNode {
+                priority: NEGATE_PRIORITY,
+                child: Box::new(child),
+                operation,
+                span,
+            });
+        }
+    }
+
+    /// Whether the `%` next is a percentage, so `200 * 15%` and `15% - 3` take a percentage
+    /// while `7 % 3` and `7 % (x)` stay modulus. A sign written against the operand after it
+    /// makes a negative operand, so `7 % -3` is modulus too, but `7%-3` could be read either
+    /// way and is an error.
+    fn is_percent(&self) -> Result<bool, ParseError> {
+        let percent = self.tokens[0].span;
+        match (self.tokens.get(1), self.tokens.get(2)) {
+            (Some(sign), Some(operand)) if matches!(sign.token, Token::Plus | Token::Minus) && starts_operand(&operand.token) => {
+                match (percent.end == sign.span.start, sign.span.end == operand.span.start) {
+                    (true, true) => Err(ParseError::AmbiguousPercent { span: percent.to(operand.span) }),
+                    (false, true) => Ok(false),
+                    _ => Ok(true),
                 }
             }
-            Token::Comma => {
-                stack.push(ASTNode::Comma);
+            (next, _) => Ok(!next.is_some_and(|a| starts_operand(&a.token))),
+        }
+    }
+
+    /// Parses `[index]` or `[start:end]` after `target`.
+    fn parse_index(&mut self, target: ASTNode) -> Result<ASTNode, ParseError> {
+        let open = self.next()?.span;
+        let start = match self.tokens.front().map(|a| &a.token) {
+            Some(Token::Colon) => None,
+            _ => Some(Box::new(self.parse_expression(0)?)),
+        };
+        let index = match (self.tokens.front().map(|a| &a.token), start) {
+            (Some(Token::Colon), start) => {
+                self.tokens.pop_front();
+                let end = match self.tokens.front().map(|a| &a.token) {
+                    Some(Token::RightBracket) => None,
+                    _ => Some(Box::new(self.parse_expression(0)?)),
+                };
+                Index::Slice(start, end)
             }
-            Token::FunctionCall(a) => {
-                stack.push(ASTNode::UnfinishedNode(UnfinishedNode::FunctionCall(a)));
+            (_, start) => Index::Element(start.expect("an index without a colon starts with an expression")),
+        };
+        let close = self.expect_right_bracket(open)?;
+        Ok(ASTNode::Index(IndexNode { span: target.span().to(close), target: Box::new(target), index }))
+    }
+
+    fn next(&mut self) -> Result<SpannedToken, ParseError> {
+        self.tokens.pop_front().ok_or(ParseError::UnexpectedEnd { span: self.end })
+    }
+
+    fn parse_prefix(&mut self) -> Result<ASTNode, ParseError> {
+        let SpannedToken { token, span } = self.next()?;
+        match token {
+            Token::Number(a) => Ok(ASTNode::NumberNode(a, span)),
+            Token::Integer(a) => Ok(ASTNode::IntegerNode(a, span)),
+            Token::Variable(a) => Ok(ASTNode::Variable(a, span)),
+            Token::Minus => {
+                let child = self.parse_expression(BinaryOperation::Exponent.priority())?;
+                Ok(ASTNode::UnaryNode(UnaryNode {
+                    priority: NEGATE_PRIORITY,
+                    span: span.to(child.span()),
+                    child: Box::new(child),
+                    operation: UnaryOperation::Negate,
+                }))
             }
-            Token::Help => {
-                return Err("Don't embed help in expressions".to_string());
+            Token::BitNot => {
+                let child = self.parse_expression(BinaryOperation::Exponent.priority())?;
+                Ok(ASTNode::UnaryNode(UnaryNode {
+                    priority: NEGATE_PRIORITY,
+                    span: span.to(child.span()),
+                    child: Box::new(child),
+                    operation: UnaryOperation::BitNot,
+                }))
             }
-            Token::Variable(a) => {
-                stack.push(ASTNode::Variable(a));
-                let _ = combine_finished_val(&mut stack);
+            Token::SquareRoot => {
+                let child = self.parse_expression(BinaryOperation::Exponent.priority())?;
+                Ok(ASTNode::UnaryNode(UnaryNode {
+                    priority: NEGATE_PRIORITY,
+                    span: span.to(child.span()),
+                    child: Box::new(child),
+                    operation: UnaryOperation::SquareRoot,
+                }))
             }
-            Token::Equal | Token::Graph => {
-                return Err("I'll get io it".to_string());
+            Token::Not => {
+                let child = self.parse_expression(BinaryOperation::Less.priority())?;
+                Ok(ASTNode::UnaryNode(UnaryNode {
+                    priority: NEGATE_PRIORITY,
+                    span: span.to(child.span()),
+                    child: Box::new(child),
+                    operation: UnaryOperation::Not,
+                }))
             }
-        }
-    }
-    let a = stack.pop();
-    let _ = match a{
-        Some(b) => return Ok(b),
-        None => return Err("Syntax Error".to_owned()),
-    };
-}
-
-fn combine_finished_val(stack: &mut Vec<ASTNode>) -> Result<(), String> {
-    if stack.len() == 1 {
-        return Ok(())
-    }
-    let right= stack.pop().unwrap();
-    let op = stack.pop().unwrap();
-    match op {
-        ASTNode::UnfinishedNode(UnfinishedNode::LeftParen) | ASTNode::Comma => {
-            stack.push(op);
-            stack.push(right);
-            Ok(())
-        }
-        ASTNode::UnfinishedNode(op) => {
-            if stack.len() == 0 {
-                match op {
-                    UnfinishedNode::Negate => {
-                        stack.push(ASTNode::UnaryNode(UnaryNode {
-                            priority: 9,
-                            child: Box::new(right),
-                            operation: UnaryOperation::Negate,
-                        }));
-                        Ok(())
-                    }
-                    UnfinishedNode::FunctionCall(a) => {
-                        stack.push(ASTNode::UnfinishedNode(UnfinishedNode::FunctionCall(a)));
-                        stack.push(right);
-                        Ok(())
-                    }
-                    UnfinishedNode::Plus => {
-                        stack.push(right);
-                        Ok(())
-                    }
-                    _ => {
-                        Err(format!("Invalid sequence: {:?}", stack))
+            Token::Plus => self.parse_expression(BinaryOperation::Exponent.priority()),
+            Token::LeftParen => {
+                let child = self.parse_expression(0)?;
+                let close = self.expect_right_paren(span)?;
+                Ok(ASTNode::UnaryNode(UnaryNode {
+                    priority: PARENS_PRIORITY,
+                    child: Box::new(child),
+                    operation: UnaryOperation::Parens,
+                    span: span.to(close),
+                }))
+            }
+            Token::LeftBracket => {
+                let mut elements = vec![];
+                if self.tokens.front().is_some_and(|a| *a == Token::RightBracket) {
+                    return Ok(ASTNode::List(elements, span.to(self.next()?.span)));
+                }
+                loop {
+                    elements.push(self.parse_expression(0)?);
+                    match self.tokens.pop_front() {
+                        Some(SpannedToken { token: Token::Comma, .. }) => {}
+                        Some(SpannedToken { token: Token::RightBracket, span: close }) => return Ok(ASTNode::List(elements, span.to(close))),
+                        Some(SpannedToken { token, span }) => return Err(ParseError::UnexpectedToken { token, span }),
+                        None => return Err(ParseError::UnbalancedBracket { span }),
                     }
                 }
-            } else {
-                let left = stack.pop().unwrap();
-                match left {
-                    ASTNode::UnfinishedNode(_) => {
-                        match op {
-                            UnfinishedNode::Minus => {
-                                stack.push(left);
-                                stack.push(ASTNode::UnaryNode(UnaryNode {
-                                    priority: 9,
-                                    child: Box::new(right),
-                                    operation: UnaryOperation::Negate
-                                }));
-                            },
-                            UnfinishedNode::Negate => {
-                                stack.push(left);
-                                stack.push(ASTNode::UnaryNode(UnaryNode {
-                                    priority: 9,
-                                    child: Box::new(right),
-                                    operation: UnaryOperation::Negate
-                                }));
-                            },
-                            _ => {
-                                return Err(format!("Invalid sequence: {:?}", stack))
+            }
+            Token::FunctionCall(operation) => {
+                let mut inputs = VecDeque::new();
+                let close;
+                if self.tokens.front().is_some_and(|a| *a == Token::RightParen) {
+                    close = self.next()?.span;
+                } else {
+                    loop {
+                        inputs.push_back(self.parse_expression(0)?);
+                        match self.tokens.pop_front() {
+                            Some(SpannedToken { token: Token::Comma, .. }) => {}
+                            Some(SpannedToken { token: Token::RightParen, span }) => {
+                                close = span;
+                                break;
                             }
+                            Some(SpannedToken { token, span }) => return Err(ParseError::UnexpectedToken { token, span }),
+                            None => return Err(ParseError::UnbalancedParen { span }),
                         }
-                        return combine_finished_val(stack);
                     }
-                    _ => {
-                        stack.push(apply_priority(match op {
-                            UnfinishedNode::Plus => {
-                                ASTNode::BinaryNode(BinaryNode {
-                                    priority: 1,
-                                    left: Box::new(left),
-                                    right: Box::new(right),
-                                    operation: BinaryOperation::Plus,
-                                })
-                            }
-                            UnfinishedNode::Minus => {
-                                ASTNode::BinaryNode(BinaryNode {
-                                    priority: 1,
-                                    left: Box::new(left),
-                                    right: Box::new(right),
-                                    operation: BinaryOperation::Minus,
-                                })
-                            }
-                            UnfinishedNode::Times => {
-                                ASTNode::BinaryNode(BinaryNode {
-                                    priority: 2,
-                                    left: Box::new(left),
-                                    right: Box::new(right),
-                                    operation: BinaryOperation::Times,
-                                })
-                            }
-                            UnfinishedNode::Exponent => {
-                                ASTNode::BinaryNode(BinaryNode {
-                                    priority: 3,
-                                    left: Box::new(left),
-                                    right: Box::new(right),
-                                    operation: BinaryOperation::Exponent,
-                                })
-                            }
-                            UnfinishedNode::Modulus => {
-                                ASTNode::BinaryNode(BinaryNode {
-                                    priority: 2,
-                                    left: Box::new(left),
-                                    right: Box::new(right),
-                                    operation: BinaryOperation::Modulus,
-                                })
-                            }
-                            UnfinishedNode::Divide => {
-                                ASTNode::BinaryNode(BinaryNode {
-                                    priority: 2,
-                                    left: Box::new(left),
-                                    right: Box::new(right),
-                                    operation: BinaryOperation::Divide,
-                                })
-                            }
-                            _ => {
-                                panic!();
-                            }
-                        }));
-                        return combine_finished_val(stack);
-                    }
-                }
-            }
-        }
-        ASTNode::NumberNode(a) => {
-            match right {
-                ASTNode::Variable(_) => {
-                    stack.push(ASTNode::BinaryNode(BinaryNode {
-                        priority: 2,
-                        left: Box::new(ASTNode::NumberNode(a)),
-                        right: Box::new(right),
-                        operation: BinaryOperation::Times,
-                    }));
-                    Ok(())
-                }
-                _ => {
-                    panic!();
                 }
+                Ok(ASTNode::FunctionCall(FunctionCall { inputs, operation, span: span.to(close) }))
             }
-        }
-        ASTNode::BinaryNode(a) => {
-            stack.push(apply_priority(ASTNode::BinaryNode(BinaryNode {
-                priority: 2,
-                left: Box::new(ASTNode::BinaryNode(a)),
-                right: Box::new(right),
-                operation: BinaryOperation::Times,
-            })));
-            Ok(())
-        }
-        _ => {
-            return Err("Syntax Error".to_string())
+            token => Err(ParseError::UnexpectedToken { token, span }),
         }
     }
-}
 
-fn apply_priority(node: ASTNode) -> ASTNode {
-    return match node.clone() {
-        ASTNode::BinaryNode(a) => {
-            match *a.left {
-                ASTNode::BinaryNode(b) => {
-                    if a.priority > b.priority {
-                        ASTNode::BinaryNode(BinaryNode {
-                            priority: b.priority,
-                            left: Box::new(*b.left),
-                            right: Box::new(apply_priority(ASTNode::BinaryNode(BinaryNode {
-                                priority: a.priority,
-                                left: Box::new(*b.right),
-                                right: Box::new(*a.right),
-                                operation: a.operation,
-                            }))),
-                            operation: b.operation,
-                        })
-                    } else {
-                        node
-                    }
-                }
-                _ => {
-                    node
-                }
-            }
+    fn expect_right_paren(&mut self, open: Span) -> Result<Span, ParseError> {
+        match self.tokens.pop_front() {
+            Some(SpannedToken { token: Token::RightParen, span }) => Ok(span),
+            Some(SpannedToken { token, span }) => Err(ParseError::UnexpectedToken { token, span }),
+            None => Err(ParseError::UnbalancedParen { span: open }),
         }
-        ASTNode::UnaryNode(_) | ASTNode::NumberNode(_) | ASTNode::Variable(_) | ASTNode::UnfinishedNode(_) | ASTNode::Comma | ASTNode::FunctionCall(_) => {
-            node
+    }
+
+    fn expect_right_bracket(&mut self, open: Span) -> Result<Span, ParseError> {
+        match self.tokens.pop_front() {
+            Some(SpannedToken { token: Token::RightBracket, span }) => Ok(span),
+            Some(SpannedToken { token, span }) => Err(ParseError::UnexpectedToken { token, span }),
+            None => Err(ParseError::UnbalancedBracket { span: open }),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::VecDeque;
-    use crate::ast::{ASTNode, BinaryNode, BinaryOperation, build_ast, FunctionCall, UnaryNode, UnaryOperation};
-    use crate::tokens::tokenize;
+    use num_bigint::BigInt;
+    use std::collections::{HashSet, VecDeque};
+    use crate::ast::{ASTNode, BinaryNode, BinaryOperation, build_ast, build_statement, defined_function, FunctionCall, Index, IndexNode, Statement, UnaryNode, UnaryOperation, MAX_NESTING};
+    use crate::environment::EVALUATION_STACK_SIZE;
+    use crate::error::ParseError;
+    use crate::registry::Registry;
+    use crate::tokens::{Span, Token, tokenize, tokenize_with_functions};
+
+    fn integer(value: u64, span: Span) -> ASTNode {
+        ASTNode::IntegerNode(BigInt::from(value), span)
+    }
 
     #[test]
     fn addition_ast() {
         let tokens = tokenize("1+2".to_string());
         let ast = build_ast(tokens.unwrap());
         assert_eq!(ast, Ok(ASTNode::BinaryNode(BinaryNode {
-            priority: 1,
-            left: Box::new(ASTNode::NumberNode(1.0)),
-            right: Box::new(ASTNode::NumberNode(2.0)),
+            priority: 4,
+            left: Box::new(integer(1, Span::new(0, 1))),
+            right: Box::new(integer(2, Span::new(2, 3))),
             operation: BinaryOperation::Plus,
+            span: Span::new(0, 3),
         })));
     }
 
@@ -452,10 +539,11 @@ mod tests {
         let tokens = tokenize("1-2".to_string());
         let ast = build_ast(tokens.unwrap());
         assert_eq!(ast, Ok(ASTNode::BinaryNode(BinaryNode {
-            priority: 1,
-            left: Box::new(ASTNode::NumberNode(1.0)),
-            right: Box::new(ASTNode::NumberNode(2.0)),
+            priority: 4,
+            left: Box::new(integer(1, Span::new(0, 1))),
+            right: Box::new(integer(2, Span::new(2, 3))),
             operation: BinaryOperation::Minus,
+            span: Span::new(0, 3),
         })));
     }
 
@@ -464,10 +552,11 @@ mod tests {
         let tokens = tokenize("1 * 2".to_string());
         let ast = build_ast(tokens.unwrap());
         assert_eq!(ast, Ok(ASTNode::BinaryNode(BinaryNode {
-            priority: 2,
-            left: Box::new(ASTNode::NumberNode(1.0)),
-            right: Box::new(ASTNode::NumberNode(2.0)),
+            priority: 5,
+            left: Box::new(integer(1, Span::new(0, 1))),
+            right: Box::new(integer(2, Span::new(4, 5))),
             operation: BinaryOperation::Times,
+            span: Span::new(0, 5),
         })));
     }
 
@@ -476,22 +565,25 @@ mod tests {
         let tokens = tokenize("1 + 2 * 3 + 4".to_string());
         let ast = build_ast(tokens.unwrap());
         assert_eq!(ast, Ok(ASTNode::BinaryNode(BinaryNode {
-            priority: 1,
+            priority: 4,
             left: Box::new(
                 ASTNode::BinaryNode(BinaryNode {
-                    priority: 1,
-                    left: Box::new(ASTNode::NumberNode(1.0)),
+                    priority: 4,
+                    left: Box::new(integer(1, Span::new(0, 1))),
                     right: Box::new(ASTNode::BinaryNode(BinaryNode {
-                        priority: 2,
-                        left: Box::new(ASTNode::NumberNode(2.0)),
-                        right: Box::new(ASTNode::NumberNode(3.0)),
+                        priority: 5,
+                        left: Box::new(integer(2, Span::new(4, 5))),
+                        right: Box::new(integer(3, Span::new(8, 9))),
                         operation: BinaryOperation::Times,
+                        span: Span::new(4, 9),
                     })),
                     operation: BinaryOperation::Plus,
+                    span: Span::new(0, 9),
                 })
             ),
-            right: Box::new(ASTNode::NumberNode(4.0)),
+            right: Box::new(integer(4, Span::new(12, 13))),
             operation: BinaryOperation::Plus,
+            span: Span::new(0, 13),
         })));
     }
 
@@ -500,34 +592,40 @@ mod tests {
         let tokens = tokenize(" 1 + 2 * 3 + ( 4 - 5 ) * 6 ".to_string());
         let ast = build_ast(tokens.unwrap());
         assert_eq!(ast, Ok(ASTNode::BinaryNode(BinaryNode {
-            priority: 1,
+            priority: 4,
             left: Box::new(ASTNode::BinaryNode(BinaryNode {
-                priority: 1,
-                left: Box::new(ASTNode::NumberNode(1.0)),
+                priority: 4,
+                left: Box::new(integer(1, Span::new(1, 2))),
                 right: Box::new(ASTNode::BinaryNode(BinaryNode {
-                    priority: 2,
-                    left: Box::new(ASTNode::NumberNode(2.0)),
-                    right: Box::new(ASTNode::NumberNode(3.0)),
+                    priority: 5,
+                    left: Box::new(integer(2, Span::new(5, 6))),
+                    right: Box::new(integer(3, Span::new(9, 10))),
                     operation: BinaryOperation::Times,
+                    span: Span::new(5, 10),
                 })),
                 operation: BinaryOperation::Plus,
+                span: Span::new(1, 10),
             })),
             right: Box::new(ASTNode::BinaryNode(BinaryNode {
-                priority: 2,
+                priority: 5,
                 left: Box::new(ASTNode::UnaryNode(UnaryNode {
                     priority: 10,
                     child: Box::new(ASTNode::BinaryNode(BinaryNode {
-                        priority: 1,
-                        left: Box::new(ASTNode::NumberNode(4.0)),
-                        right: Box::new(ASTNode::NumberNode(5.0)),
+                        priority: 4,
+                        left: Box::new(integer(4, Span::new(15, 16))),
+                        right: Box::new(integer(5, Span::new(19, 20))),
                         operation: BinaryOperation::Minus,
+                        span: Span::new(15, 20),
                     })),
                     operation: UnaryOperation::Parens,
+                    span: Span::new(13, 22),
                 })),
-                right: Box::new(ASTNode::NumberNode(6.0)),
+                right: Box::new(integer(6, Span::new(25, 26))),
                 operation: BinaryOperation::Times,
+                span: Span::new(13, 26),
             })),
             operation: BinaryOperation::Plus,
+            span: Span::new(1, 26),
         })));
     }
 
@@ -536,29 +634,34 @@ mod tests {
         let input = build_ast(tokenize("-2".to_string()).unwrap()).unwrap();
         assert_eq!(input, ASTNode::UnaryNode(UnaryNode {
             priority: 9,
-            child: Box::new(ASTNode::NumberNode(2.0)),
+            child: Box::new(integer(2, Span::new(1, 2))),
             operation: UnaryOperation::Negate,
+            span: Span::new(0, 2),
         }));
         let input = build_ast(tokenize("--2".to_string()).unwrap()).unwrap();
         assert_eq!(input, ASTNode::UnaryNode(UnaryNode {
             priority: 9,
             child: Box::new(ASTNode::UnaryNode(UnaryNode {
                 priority: 9,
-                child: Box::new(ASTNode::NumberNode(2.0)),
+                child: Box::new(integer(2, Span::new(2, 3))),
                 operation: UnaryOperation::Negate,
+                span: Span::new(1, 3),
             })),
             operation: UnaryOperation::Negate,
+            span: Span::new(0, 3),
         }));
         let input = build_ast(tokenize("3*-2".to_string()).unwrap()).unwrap();
         assert_eq!(input, ASTNode::BinaryNode(BinaryNode {
-            priority: 2,
-            left: Box::new(ASTNode::NumberNode(3.0)),
+            priority: 5,
+            left: Box::new(integer(3, Span::new(0, 1))),
             right: Box::new(ASTNode::UnaryNode(UnaryNode {
                 priority: 9,
-                child: Box::new(ASTNode::NumberNode(2.0)),
+                child: Box::new(integer(2, Span::new(3, 4))),
                 operation: UnaryOperation::Negate,
+                span: Span::new(2, 4),
             })),
             operation: BinaryOperation::Times,
+            span: Span::new(0, 4),
         }));
     }
 
@@ -567,10 +670,11 @@ mod tests {
         let tokens = tokenize("10^2".to_string());
         let ast = build_ast(tokens.unwrap());
         assert_eq!(ast, Ok(ASTNode::BinaryNode(BinaryNode {
-            priority: 3,
-            left: Box::new(ASTNode::NumberNode(10.0)),
-            right: Box::new(ASTNode::NumberNode(2.0)),
+            priority: 7,
+            left: Box::new(integer(10, Span::new(0, 2))),
+            right: Box::new(integer(2, Span::new(3, 4))),
             operation: BinaryOperation::Exponent,
+            span: Span::new(0, 4),
         })));
     }
 
@@ -579,11 +683,15 @@ mod tests {
         let tokens = tokenize("10%5".to_string());
         let ast = build_ast(tokens.unwrap()).unwrap();
         assert_eq!(ast, ASTNode::BinaryNode(BinaryNode {
-            priority: 2,
-            left: Box::new(ASTNode::NumberNode(10.0)),
-            right: Box::new(ASTNode::NumberNode(5.0)),
+            priority: 5,
+            left: Box::new(integer(10, Span::new(0, 2))),
+            right: Box::new(integer(5, Span::new(3, 4))),
             operation: BinaryOperation::Modulus,
+            span: Span::new(0, 4),
         }));
+        let ast = build_ast(tokenize("7 % -3".to_string()).unwrap()).unwrap();
+        assert!(matches!(ast, ASTNode::BinaryNode(BinaryNode { operation: BinaryOperation::Modulus, .. })));
+        assert_eq!(build_ast(tokenize("7%-3".to_string()).unwrap()), Err(ParseError::AmbiguousPercent { span: Span::new(1, 4) }));
     }
 
     #[test]
@@ -591,19 +699,21 @@ mod tests {
         let tokens = tokenize("average(1)".to_string()).unwrap();
         let ast = build_ast(tokens).unwrap();
         let mut children = VecDeque::new();
-        children.push_front(ASTNode::NumberNode(1.0));
+        children.push_front(integer(1, Span::new(8, 9)));
         assert_eq!(ast, ASTNode::FunctionCall(FunctionCall {
             inputs: children,
             operation: "average".to_string(),
+            span: Span::new(0, 10),
         }));
         let ast = build_ast(tokenize("average(1,2,3)".to_string()).unwrap()).unwrap();
         let mut children = VecDeque::new();
-        children.push_front(ASTNode::NumberNode(1.0));
-        children.push_front(ASTNode::NumberNode(2.0));
-        children.push_front(ASTNode::NumberNode(3.0));
+        children.push_back(integer(1, Span::new(8, 9)));
+        children.push_back(integer(2, Span::new(10, 11)));
+        children.push_back(integer(3, Span::new(12, 13)));
         assert_eq!(ast, ASTNode::FunctionCall(FunctionCall {
             inputs: children,
             operation: "average".to_string(),
+            span: Span::new(0, 14),
         }));
     }
 
@@ -612,25 +722,29 @@ mod tests {
         let tokens = tokenize("2x+5y-10".to_string()).unwrap();
         let ast = build_ast(tokens).unwrap();
         assert_eq!(ast, ASTNode::BinaryNode(BinaryNode {
-            priority: 1,
+            priority: 4,
             left: Box::new(ASTNode::BinaryNode(BinaryNode {
-                priority: 1,
+                priority: 4,
                 left: Box::new(ASTNode::BinaryNode(BinaryNode {
-                    priority: 2,
-                    left: Box::new(ASTNode::NumberNode(2.0)),
-                    right: Box::new(ASTNode::Variable("x".to_string())),
+                    priority: 5,
+                    left: Box::new(integer(2, Span::new(0, 1))),
+                    right: Box::new(ASTNode::Variable("x".to_string(), Span::new(1, 2))),
                     operation: BinaryOperation::Times,
+                    span: Span::new(0, 2),
                 })),
                 right: Box::new(ASTNode::BinaryNode(BinaryNode {
-                    priority: 2,
-                    left: Box::new(ASTNode::NumberNode(5.0)),
-                    right: Box::new(ASTNode::Variable("y".to_string())),
+                    priority: 5,
+                    left: Box::new(integer(5, Span::new(3, 4))),
+                    right: Box::new(ASTNode::Variable("y".to_string(), Span::new(4, 5))),
                     operation: BinaryOperation::Times,
+                    span: Span::new(3, 5),
                 })),
                 operation: BinaryOperation::Plus,
+                span: Span::new(0, 5),
             })),
-            right: Box::new(ASTNode::NumberNode(10.0)),
+            right: Box::new(integer(10, Span::new(6, 8))),
             operation: BinaryOperation::Minus,
+            span: Span::new(0, 8),
         }))
     }
 
@@ -639,8 +753,8 @@ mod tests {
         let tokens = tokenize("1+ln(2)".to_string()).unwrap();
         let ast = build_ast(tokens).unwrap();
         let mut children = VecDeque::new();
-        children.push_front(ASTNode::NumberNode(2.));
-        assert_eq!(ast, ASTNode::BinaryNode(BinaryNode{ priority: 1, left: Box::new(ASTNode::NumberNode(1.)), right: Box::new(ASTNode::FunctionCall(FunctionCall{ inputs: children, operation: "ln".to_string() })), operation: BinaryOperation::Plus}))
+        children.push_front(integer(2, Span::new(5, 6)));
+        assert_eq!(ast, ASTNode::BinaryNode(BinaryNode{ priority: 4, left: Box::new(integer(1, Span::new(0, 1))), right: Box::new(ASTNode::FunctionCall(FunctionCall{ inputs: children, operation: "ln".to_string(), span: Span::new(2, 7) })), operation: BinaryOperation::Plus, span: Span::new(0, 7)}))
     }
 
     #[test]
@@ -648,7 +762,221 @@ mod tests {
         let tokens = tokenize("factorial(8)".to_string()).unwrap();
         let ast = build_ast(tokens).unwrap();
         let mut children = VecDeque::new();
-        children.push_front(ASTNode::NumberNode(8.));
-        assert_eq!(ast, ASTNode::FunctionCall(FunctionCall { inputs: children, operation: "factorial".to_string() }))
+        children.push_front(integer(8, Span::new(10, 11)));
+        assert_eq!(ast, ASTNode::FunctionCall(FunctionCall { inputs: children, operation: "factorial".to_string(), span: Span::new(0, 12) }))
+    }
+
+    #[test]
+    fn exponent_is_right_associative() {
+        let ast = build_ast(tokenize("2^3^2".to_string()).unwrap()).unwrap();
+        assert_eq!(ast, ASTNode::BinaryNode(BinaryNode {
+            priority: 7,
+            left: Box::new(integer(2, Span::new(0, 1))),
+            right: Box::new(ASTNode::BinaryNode(BinaryNode {
+                priority: 7,
+                left: Box::new(integer(3, Span::new(2, 3))),
+                right: Box::new(integer(2, Span::new(4, 5))),
+                operation: BinaryOperation::Exponent,
+                span: Span::new(2, 5),
+            })),
+            operation: BinaryOperation::Exponent,
+            span: Span::new(0, 5),
+        }));
+    }
+
+    #[test]
+    fn subtraction_is_left_associative() {
+        let ast = build_ast(tokenize("1-2-3".to_string()).unwrap()).unwrap();
+        assert_eq!(ast, ASTNode::BinaryNode(BinaryNode {
+            priority: 4,
+            left: Box::new(ASTNode::BinaryNode(BinaryNode {
+                priority: 4,
+                left: Box::new(integer(1, Span::new(0, 1))),
+                right: Box::new(integer(2, Span::new(2, 3))),
+                operation: BinaryOperation::Minus,
+                span: Span::new(0, 3),
+            })),
+            right: Box::new(integer(3, Span::new(4, 5))),
+            operation: BinaryOperation::Minus,
+            span: Span::new(0, 5),
+        }));
+    }
+
+    #[test]
+    fn negate_binds_looser_than_exponent() {
+        let ast = build_ast(tokenize("-2^2".to_string()).unwrap()).unwrap();
+        assert_eq!(ast, ASTNode::UnaryNode(UnaryNode {
+            priority: 9,
+            child: Box::new(ASTNode::BinaryNode(BinaryNode {
+                priority: 7,
+                left: Box::new(integer(2, Span::new(1, 2))),
+                right: Box::new(integer(2, Span::new(3, 4))),
+                operation: BinaryOperation::Exponent,
+                span: Span::new(1, 4),
+            })),
+            operation: UnaryOperation::Negate,
+            span: Span::new(0, 4),
+        }));
+        let ast = build_ast(tokenize("2^-1".to_string()).unwrap()).unwrap();
+        assert_eq!(ast, ASTNode::BinaryNode(BinaryNode {
+            priority: 7,
+            left: Box::new(integer(2, Span::new(0, 1))),
+            right: Box::new(ASTNode::UnaryNode(UnaryNode {
+                priority: 9,
+                child: Box::new(integer(1, Span::new(3, 4))),
+                operation: UnaryOperation::Negate,
+                span: Span::new(2, 4),
+            })),
+            operation: BinaryOperation::Exponent,
+            span: Span::new(0, 4),
+        }));
+    }
+
+    #[test]
+    fn nested_parens_and_functions() {
+        let ast = build_ast(tokenize("max(1, min(2, (3)))".to_string()).unwrap()).unwrap();
+        let mut inner = VecDeque::new();
+        inner.push_back(integer(2, Span::new(11, 12)));
+        inner.push_back(ASTNode::UnaryNode(UnaryNode {
+            priority: 10,
+            child: Box::new(integer(3, Span::new(15, 16))),
+            operation: UnaryOperation::Parens,
+            span: Span::new(14, 17),
+        }));
+        let mut outer = VecDeque::new();
+        outer.push_back(integer(1, Span::new(4, 5)));
+        outer.push_back(ASTNode::FunctionCall(FunctionCall { inputs: inner, operation: "min".to_string(), span: Span::new(7, 18) }));
+        assert_eq!(ast, ASTNode::FunctionCall(FunctionCall { inputs: outer, operation: "max".to_string(), span: Span::new(0, 19) }));
+        assert!(build_ast(tokenize("((((1))))".to_string()).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn implicit_multiplication_ast() {
+        let ast = build_ast(tokenize("2(3)".to_string()).unwrap()).unwrap();
+        assert_eq!(ast, ASTNode::BinaryNode(BinaryNode {
+            priority: 5,
+            left: Box::new(integer(2, Span::new(0, 1))),
+            right: Box::new(ASTNode::UnaryNode(UnaryNode {
+                priority: 10,
+                child: Box::new(integer(3, Span::new(2, 3))),
+                operation: UnaryOperation::Parens,
+                span: Span::new(1, 4),
+            })),
+            operation: BinaryOperation::Times,
+            span: Span::new(0, 4),
+        }));
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        for input in ["1+", "(1", "1)", "*2", "1 2", "max(1,", "max(1 2)", "()", "3*/4"] {
+            assert!(build_ast(tokenize(input.to_string()).unwrap()).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn errors_point_at_offending_token() {
+        let error = build_ast(tokenize("1 + * 2".to_string()).unwrap()).unwrap_err();
+        assert_eq!(error, ParseError::UnexpectedToken { token: Token::Times, span: Span::new(4, 5) });
+        let error = build_ast(tokenize("2 * (3 + 4".to_string()).unwrap()).unwrap_err();
+        assert_eq!(error, ParseError::UnbalancedParen { span: Span::new(4, 5) });
+        let error = build_ast(tokenize("(1))".to_string()).unwrap()).unwrap_err();
+        assert_eq!(error, ParseError::UnbalancedParen { span: Span::new(3, 4) });
+        let error = build_ast(tokenize("1 +".to_string()).unwrap()).unwrap_err();
+        assert_eq!(error, ParseError::UnexpectedEnd { span: Span::new(3, 3) });
+    }
+
+    #[test]
+    fn bitwise_operators_ast() {
+        let ast = build_ast(tokenize("1 | 0xF0 & 3".to_string()).unwrap()).unwrap();
+        assert_eq!(ast, ASTNode::BinaryNode(BinaryNode {
+            priority: 4,
+            left: Box::new(integer(1, Span::new(0, 1))),
+            right: Box::new(ASTNode::BinaryNode(BinaryNode {
+                priority: 5,
+                left: Box::new(integer(0xF0, Span::new(4, 8))),
+                right: Box::new(integer(3, Span::new(11, 12))),
+                operation: BinaryOperation::BitAnd,
+                span: Span::new(4, 12),
+            })),
+            operation: BinaryOperation::BitOr,
+            span: Span::new(0, 12),
+        }));
+        let ast = build_ast(tokenize("~1".to_string()).unwrap()).unwrap();
+        assert_eq!(ast, ASTNode::UnaryNode(UnaryNode {
+            priority: 9,
+            child: Box::new(integer(1, Span::new(1, 2))),
+            operation: UnaryOperation::BitNot,
+            span: Span::new(0, 2),
+        }));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn lists_and_indexes_ast() {
+        let ast = build_ast(tokenize("[1, x][-1]".to_string()).unwrap()).unwrap();
+        assert_eq!(ast, ASTNode::Index(IndexNode {
+            target: Box::new(ASTNode::List(vec![
+                integer(1, Span::new(1, 2)),
+                ASTNode::Variable("x".to_string(), Span::new(4, 5)),
+            ], Span::new(0, 6))),
+            index: Index::Element(Box::new(ASTNode::UnaryNode(UnaryNode {
+                priority: 9,
+                child: Box::new(integer(1, Span::new(8, 9))),
+                operation: UnaryOperation::Negate,
+                span: Span::new(7, 9),
+            }))),
+            span: Span::new(0, 10),
+        }));
+        let ast = build_ast(tokenize("v[:2]".to_string()).unwrap()).unwrap();
+        assert!(matches!(ast, ASTNode::Index(IndexNode { index: Index::Slice(None, Some(_)), .. })));
+        let ast = build_ast(tokenize("[][1:]".to_string()).unwrap()).unwrap();
+        assert!(matches!(ast, ASTNode::Index(IndexNode { index: Index::Slice(Some(_), None), .. })));
+        assert_eq!(build_ast(tokenize("[1, 2".to_string()).unwrap()), Err(ParseError::UnbalancedBracket { span: Span::new(0, 1) }));
+        assert_eq!(build_ast(tokenize("1]".to_string()).unwrap()), Err(ParseError::UnbalancedBracket { span: Span::new(1, 2) }));
+        assert_eq!(build_ast(tokenize("v[]".to_string()).unwrap()), Err(ParseError::UnexpectedToken { token: Token::RightBracket, span: Span::new(2, 3) }));
+    }
+
+    #[test]
+    fn assignment_statement() {
+        let statement = build_statement(tokenize("a = 5".to_string()).unwrap()).unwrap();
+        assert_eq!(statement, Statement::Assignment { name: "a".to_string(), value: integer(5, Span::new(4, 5)), span: Span::new(0, 1) });
+        let statement = build_statement(tokenize("3a + 1".to_string()).unwrap()).unwrap();
+        assert!(matches!(statement, Statement::Expression(ASTNode::BinaryNode(_))));
+        assert_eq!(build_statement(tokenize("a =".to_string()).unwrap()), Err(ParseError::UnexpectedEnd { span: Span::new(3, 3) }));
+        assert_eq!(build_statement(tokenize("1 = a".to_string()).unwrap()), Err(ParseError::UnexpectedToken { token: Token::Equal, span: Span::new(2, 3) }));
+    }
+
+    #[test]
+    fn function_definition_statement() {
+        let statement = build_statement(tokenize("f(x) = x".to_string()).unwrap()).unwrap();
+        assert_eq!(statement, Statement::FunctionDefinition { name: "f".to_string(), parameters: vec!["x".to_string()],
+            body: ASTNode::Variable("x".to_string(), Span::new(7, 8)), span: Span::new(0, 1) });
+        let tokens = tokenize_with_functions("area(w, h) = w h".to_string(), &Registry::builtin(), &HashSet::from(["area".to_string()])).unwrap();
+        assert_eq!(defined_function(&tokens), Some("area".to_string()));
+        let Statement::FunctionDefinition { name, parameters, .. } = build_statement(tokens).unwrap() else { panic!() };
+        assert_eq!((name.as_str(), parameters), ("area", vec!["w".to_string(), "h".to_string()]));
+        let statement = build_statement(tokenize("k() = 4".to_string()).unwrap()).unwrap();
+        assert!(matches!(statement, Statement::FunctionDefinition { parameters, .. } if parameters.is_empty()));
+        assert_eq!(build_statement(tokenize("f(x) =".to_string()).unwrap()), Err(ParseError::UnexpectedEnd { span: Span::new(6, 6) }));
+        assert_eq!(build_statement(tokenize("f(2) = 3".to_string()).unwrap()), Err(ParseError::UnexpectedToken { token: Token::Equal, span: Span::new(5, 6) }));
+        assert_eq!(defined_function(&tokenize("f(x) + 1".to_string()).unwrap()), None);
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let parse = |input: String| build_ast(tokenize(input).unwrap());
+        let [parens, chain, factorials, shallow] = std::thread::scope(|scope| {
+            let thread = std::thread::Builder::new().stack_size(EVALUATION_STACK_SIZE);
+            thread.spawn_scoped(scope, || [
+                parse("(".repeat(200000) + "1" + &")".repeat(200000)),
+                parse("1-".repeat(200000) + "1"),
+                parse("5".to_string() + &"!".repeat(200000)),
+                parse("(".repeat(MAX_NESTING - 1) + "1" + &")".repeat(MAX_NESTING - 1)),
+            ]).unwrap().join().unwrap()
+        });
+        assert_eq!(parens, Err(ParseError::TooDeep { span: Span::new(1000, 1001) }));
+        assert_eq!(chain, Err(ParseError::TooDeep { span: Span::new(0, 400001) }));
+        assert_eq!(factorials, Err(ParseError::TooDeep { span: Span::new(0, 200001) }));
+        assert_eq!(shallow.map(|a| a.depth()), Ok(MAX_NESTING));
+    }
+}
@@ -0,0 +1,73 @@
+use std::f64::consts::{E, PI, TAU};
+
+/// A name that is always defined unless a variable of the same name shadows it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Constant {
+    pub name: &'static str,
+    pub value: f64,
+    /// SI unit of the value, empty for pure numbers.
+    pub unit: &'static str,
+    pub description: &'static str,
+    /// Heading the constant is listed under in help.
+    pub category: &'static str,
+}
+
+const MATH: &str = "Math constants";
+const PHYSICS: &str = "Physical constants (CODATA 2018, SI units)";
+
+/// Prefix of the physical constants, so short names like `c` and `h` stay free for
+/// variables.
+pub const PHYSICS_NAMESPACE: &str = "phys.";
+
+pub const CONSTANTS: &[Constant] = &[
+    Constant { name: "pi", value: PI, unit: "", description: "ratio of a circle's circumference to its diameter, also π", category: MATH },
+    Constant { name: "π", value: PI, unit: "", description: "pi", category: MATH },
+    Constant { name: "e", value: E, unit: "", description: "base of the natural logarithm", category: MATH },
+    Constant { name: "tau", value: TAU, unit: "", description: "2 pi", category: MATH },
+    Constant { name: "phi", value: 1.618033988749895, unit: "", description: "golden ratio", category: MATH },
+    Constant { name: "inf", value: f64::INFINITY, unit: "", description: "infinity", category: MATH },
+    Constant { name: "phys.c", value: 299792458., unit: "m/s", description: "speed of light in vacuum", category: PHYSICS },
+    Constant { name: "phys.G", value: 6.67430e-11, unit: "m³/(kg s²)", description: "Newtonian constant of gravitation", category: PHYSICS },
+    Constant { name: "phys.h", value: 6.62607015e-34, unit: "J s", description: "Planck constant", category: PHYSICS },
+    Constant { name: "phys.k_B", value: 1.380649e-23, unit: "J/K", description: "Boltzmann constant", category: PHYSICS },
+    Constant { name: "phys.N_A", value: 6.02214076e23, unit: "1/mol", description: "Avogadro constant", category: PHYSICS },
+];
+
+/// The imaginary unit, which like the constants is defined until a variable shadows it.
+pub const IMAGINARY_UNIT: &str = "i";
+
+pub fn constant(name: &str) -> Option<&'static Constant> {
+    CONSTANTS.iter().find(|a| a.name == name)
+}
+
+/// Every constant with its value and unit, grouped like `Registry::help`.
+pub fn help() -> String {
+    let mut lines = vec![];
+    let mut category = "";
+    for constant in CONSTANTS.iter().filter(|a| a.name != "π") {
+        if constant.category != category {
+            category = constant.category;
+            lines.push(format!("{}:", category));
+        }
+        let value = match constant.value.abs() {
+            a if a.is_finite() && !(1e-4..1e6).contains(&a) => format!("{:e}", constant.value),
+            _ => constant.value.to_string(),
+        };
+        lines.push(format!("  {} = {} {}", constant.name, [value.as_str(), constant.unit].join(" ").trim_end(), constant.description));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constants::{constant, help};
+
+    #[test]
+    fn constants_have_units() {
+        assert_eq!(constant("phys.c").unwrap().unit, "m/s");
+        assert!(constant("c").is_none() && constant("h").is_none());
+        assert_eq!(constant("π").unwrap().value, constant("pi").unwrap().value);
+        assert!(constant("x").is_none());
+        assert!(help().contains("\n  phys.k_B = 1.380649e-23 J/K Boltzmann constant"));
+    }
+}
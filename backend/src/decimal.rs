@@ -0,0 +1,639 @@
+use std::num::NonZeroU64;
+use std::str::FromStr;
+use bigdecimal::{BigDecimal, Context, RoundingMode};
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use crate::constants::Constant;
+use crate::math::{most_common, AngleMode};
+
+/// Significant digits when decimal mode is asked for without a precision, as in IEEE decimal128.
+pub const DEFAULT_PRECISION: u64 = 34;
+/// Most significant digits a request can ask for.
+pub const MAX_PRECISION: u64 = 1000;
+/// Largest power of ten a decimal reaches before floating point takes over. Adding decimals
+/// lines up their digits, so this is what keeps `x + 1` cheap for any decimal `x`.
+pub const MAX_EXPONENT: i64 = 100_000;
+/// Digits carried past the requested precision so rounding errors stay out of the result.
+const GUARD_DIGITS: u64 = 10;
+/// Largest magnitude the trig functions reduce, floating point takes over past it.
+const MAX_ARGUMENT: f64 = 1e9;
+
+/// `x` rounded to `precision` significant digits without trailing zeros.
+pub fn round(x: &BigDecimal, precision: u64) -> BigDecimal {
+    x.with_prec(precision).normalized()
+}
+
+/// `x` in plain digits, or in scientific notation like `3e-40` when it is tiny or would
+/// need zeros past its last significant digit to reach the decimal point.
+pub fn format(x: &BigDecimal) -> String {
+    let x = x.normalized();
+    let exponent = exponent(&x);
+    if x.is_zero() || (exponent >= -6 && (exponent <= 21 || x.fractional_digit_count() >= 0)) {
+        return x.to_plain_string();
+    }
+    let (digits, _) = x.as_bigint_and_exponent();
+    let digits = digits.magnitude().to_string();
+    let sign = if x.is_negative() { "-" } else { "" };
+    match digits.split_at(1) {
+        (first, "") => format!("{}{}e{}", sign, first, exponent - 1),
+        (first, rest) => format!("{}{}.{}e{}", sign, first, rest, exponent - 1),
+    }
+}
+
+/// The shortest decimal that reads back as `x`, so `0.1` is exactly one tenth.
+pub fn from_f64(x: f64) -> Option<BigDecimal> {
+    if !x.is_finite() {
+        return None;
+    }
+    BigDecimal::from_str(&x.to_string()).ok()
+}
+
+/// `x` as an exact fraction.
+pub fn to_rational(x: &BigDecimal) -> BigRational {
+    let (digits, scale) = x.as_bigint_and_exponent();
+    let power = BigInt::from(10).pow(scale.unsigned_abs() as u32);
+    if scale < 0 { BigRational::from_integer(digits * power) } else { BigRational::new(digits, power) }
+}
+
+/// `a / b` to `digits` significant digits, `b` must not be zero.
+pub fn div(a: &BigDecimal, b: &BigDecimal, digits: u64) -> BigDecimal {
+    let (a_digits, a_scale) = a.as_bigint_and_exponent();
+    let (b_digits, b_scale) = b.as_bigint_and_exponent();
+    // Enough zeros on the numerator that the whole number quotient has digits to spare.
+    let shift = (digits + 2 + b.digits()).saturating_sub(a.digits());
+    let quotient = a_digits * BigInt::from(10).pow(shift as u32) / b_digits;
+    BigDecimal::new(quotient, a_scale - b_scale + shift as i64).with_prec(digits)
+}
+
+/// Position of the leading digit, so `x` lies in [10^(e - 1), 10^e).
+fn exponent(x: &BigDecimal) -> i64 {
+    x.digits() as i64 - x.fractional_digit_count()
+}
+
+/// Whether `x` is within `MAX_EXPONENT`, so arithmetic on it stays cheap.
+pub fn in_range(x: &BigDecimal) -> bool {
+    x.is_zero() || exponent(x).abs() <= MAX_EXPONENT
+}
+
+/// log10 |x| to about the precision of an `f64`, for nonzero `x` of any size.
+fn log10_magnitude(x: &BigDecimal) -> f64 {
+    let (digits, scale) = x.with_prec(17).as_bigint_and_exponent();
+    digits.magnitude().to_f64().unwrap_or(f64::NAN).log10() - scale as f64
+}
+
+/// Whether `term` no longer changes the first `digits` digits of `sum`.
+fn negligible(term: &BigDecimal, sum: &BigDecimal, digits: u64) -> bool {
+    term.is_zero() || (!sum.is_zero() && exponent(term) + (digits as i64) < exponent(sum))
+}
+
+/// Sums `first` and each following term, `next(previous, n)` for n = 1, 2, ..., until the terms
+/// stop mattering.
+fn series(first: BigDecimal, digits: u64, next: impl Fn(&BigDecimal, u64) -> BigDecimal) -> BigDecimal {
+    let mut term = first.clone();
+    let mut sum = first;
+    for n in 1.. {
+        term = next(&term, n).with_prec(digits);
+        if negligible(&term, &sum, digits) {
+            break;
+        }
+        sum += &term;
+    }
+    sum.with_prec(digits)
+}
+
+/// Number of decimal digits in `n`.
+fn digit_count(n: u64) -> u64 {
+    n.checked_ilog10().map_or(1, |a| a as u64 + 1)
+}
+
+/// Working precision for a function of `x` whose result is about as small as `x` near zero,
+/// where subtracting from 1 would otherwise cancel its leading digits.
+fn working(x: &BigDecimal, precision: u64) -> u64 {
+    precision + GUARD_DIGITS + (-exponent(x)).max(0) as u64
+}
+
+/// Whether `x` is so small that f(x) = x for an odd function like `sinh` to `precision` digits.
+fn is_tiny(x: &BigDecimal, precision: u64) -> bool {
+    x.is_zero() || exponent(x) < -((precision + GUARD_DIGITS) as i64)
+}
+
+/// Taylor series of atan(z), or of atanh(z) when `hyperbolic`, for |z| well under 1.
+fn arctan_series(z: &BigDecimal, hyperbolic: bool, digits: u64) -> BigDecimal {
+    let z_squared = (z * z).with_prec(digits);
+    let mut power = z.clone();
+    let mut sum = z.clone();
+    for k in 1u64.. {
+        power = (&power * &z_squared).with_prec(digits);
+        let term = div(&power, &BigDecimal::from(2 * k + 1), digits);
+        if negligible(&term, &sum, digits) {
+            break;
+        }
+        if hyperbolic || k % 2 == 0 {
+            sum += term;
+        } else {
+            sum -= term;
+        }
+    }
+    sum.with_prec(digits)
+}
+
+/// π from Machin's formula, 16 atan(1/5) - 4 atan(1/239).
+pub fn pi(digits: u64) -> BigDecimal {
+    let fifth = arctan_series(&BigDecimal::new(BigInt::from(2), 1), false, digits + 2);
+    let inverse_239 = arctan_series(&div(&BigDecimal::one(), &BigDecimal::from(239), digits + 2), false, digits + 2);
+    (fifth * BigDecimal::from(16) - inverse_239 * BigDecimal::from(4)).with_prec(digits)
+}
+
+/// ln 2 = 2 atanh(1/3).
+fn ln_2(digits: u64) -> BigDecimal {
+    (arctan_series(&div(&BigDecimal::one(), &BigDecimal::from(3), digits + 2), true, digits + 2) * BigDecimal::from(2)).with_prec(digits)
+}
+
+/// ln 10 = 3 ln 2 + ln 1.25, and ln 1.25 = 2 atanh(1/9).
+fn ln_10(digits: u64) -> BigDecimal {
+    let ln_5_4 = arctan_series(&div(&BigDecimal::one(), &BigDecimal::from(9), digits + 2), true, digits + 2) * BigDecimal::from(2);
+    (ln_2(digits + 2) * BigDecimal::from(3) + ln_5_4).with_prec(digits)
+}
+
+/// e^x as 10^k e^r with |r| <= ln(10) / 2, or `None` when 10^k is past `MAX_EXPONENT`.
+fn exp_digits(x: &BigDecimal, digits: u64) -> Option<BigDecimal> {
+    let k = (x.to_f64()? / std::f64::consts::LN_10).round();
+    if k.abs() > MAX_EXPONENT as f64 {
+        return None;
+    }
+    let k = k as i64;
+    let r = (x - ln_10(digits + digit_count(k.unsigned_abs())) * BigDecimal::from(k)).with_prec(digits);
+    let power = series(BigDecimal::one(), digits, |term, n| div(&(term * &r), &BigDecimal::from(n), digits));
+    Some(power * BigDecimal::new(BigInt::one(), -k))
+}
+
+/// ln x for x > 0. Away from 1, x = m 10^e 2^j with m near 1, otherwise x itself is near 1,
+/// and ln m = 2 atanh((m - 1) / (m + 1)).
+fn ln_digits(x: &BigDecimal, digits: u64) -> BigDecimal {
+    let one = BigDecimal::one();
+    if (x - &one).abs() < BigDecimal::new(BigInt::from(5), 1) {
+        let z = div(&(x - &one), &(x + &one), digits);
+        return (arctan_series(&z, true, digits) * BigDecimal::from(2)).with_prec(digits);
+    }
+    let e = exponent(x) - 1;
+    let m = x * BigDecimal::new(BigInt::one(), e);
+    let j = m.to_f64().unwrap_or(1.).log2().round() as i64;
+    let m = div(&m, &BigDecimal::from(1u64 << j), digits);
+    let z = div(&(&m - &one), &(&m + &one), digits);
+    let reduced = arctan_series(&z, true, digits) * BigDecimal::from(2);
+    let powers = ln_2(digits) * BigDecimal::from(j) + ln_10(digits + digit_count(e.unsigned_abs())) * BigDecimal::from(e);
+    (reduced + powers).with_prec(digits)
+}
+
+fn sqrt_digits(x: &BigDecimal, digits: u64) -> Option<BigDecimal> {
+    x.sqrt_with_context(&Context::new(NonZeroU64::new(digits)?, RoundingMode::HalfEven))
+}
+
+/// sin x and cos x from x = r + q π/2 with |r| <= π/4.
+fn sin_cos(x: &BigDecimal, digits: u64) -> Option<(BigDecimal, BigDecimal)> {
+    let estimate = x.to_f64()?;
+    if estimate.abs() > MAX_ARGUMENT {
+        return None;
+    }
+    let q = (estimate / std::f64::consts::FRAC_PI_2).round() as i64;
+    let half_pi = pi(digits + digit_count(q.unsigned_abs())) * BigDecimal::new(BigInt::from(5), 1);
+    let r = (x - half_pi * BigDecimal::from(q)).with_prec(digits);
+    let r_squared = (&r * &r).with_prec(digits);
+    let sin = series(r.clone(), digits, |term, n| -div(&(term * &r_squared), &BigDecimal::from(2 * n * (2 * n + 1)), digits));
+    let cos = series(BigDecimal::one(), digits, |term, n| -div(&(term * &r_squared), &BigDecimal::from((2 * n - 1) * 2 * n), digits));
+    Some(match q.rem_euclid(4) {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    })
+}
+
+/// atan x, using atan x = π/2 - atan(1/x) for |x| > 1 and
+/// atan x = 2 atan(x / (1 + √(1 + x²))) until the series converges quickly.
+fn atan_digits(x: &BigDecimal, digits: u64) -> BigDecimal {
+    let one = BigDecimal::one();
+    if x.abs() > one {
+        let half_pi = pi(digits) * BigDecimal::new(BigInt::from(5), 1);
+        let reduced = atan_digits(&div(&one, x, digits), digits);
+        return if x.is_positive() { half_pi - reduced } else { -half_pi - reduced };
+    }
+    let mut x = x.clone();
+    let mut doublings = 0;
+    while x.abs() > BigDecimal::new(BigInt::one(), 1) {
+        let root = sqrt_digits(&(&one + (&x * &x)), digits).unwrap_or_default();
+        x = div(&x, &(&one + root), digits);
+        doublings += 1;
+    }
+    (arctan_series(&x, false, digits) * BigDecimal::from(1u64 << doublings)).with_prec(digits)
+}
+
+/// How far from a multiple of ½ a result of arguments as large as `scale` may be and still
+/// be one, the counterpart of `math::snap` at `precision` digits.
+fn tolerance(scale: &BigDecimal, precision: u64) -> BigDecimal {
+    scale * BigDecimal::new(BigInt::one(), precision as i64 - 1)
+}
+
+/// Moves `x` onto the nearest multiple of ½ when it is within the rounding error of
+/// arguments as large as `scale`, so `sin(30deg)` is exactly 0.5.
+pub fn snap(x: &BigDecimal, scale: &BigDecimal, precision: u64) -> BigDecimal {
+    let nearest = (x * BigDecimal::from(2)).with_scale_round(0, RoundingMode::HalfEven) * BigDecimal::new(BigInt::from(5), 1);
+    if (x - &nearest).abs() <= tolerance(scale, precision) {
+        nearest
+    } else {
+        x.clone()
+    }
+}
+
+/// `angle` in `from` units as `to` units.
+pub fn convert(angle: &BigDecimal, from: AngleMode, to: AngleMode, precision: u64) -> BigDecimal {
+    if from == to {
+        return angle.clone();
+    }
+    let digits = precision + GUARD_DIGITS;
+    let half_turn = |mode: AngleMode| match mode {
+        AngleMode::Radians => pi(digits),
+        AngleMode::Degrees => BigDecimal::from(180),
+        AngleMode::Gradians => BigDecimal::from(200),
+    };
+    div(&(angle * half_turn(to)), &half_turn(from), digits)
+}
+
+/// A built-in constant to `precision` digits. The math constants are computed, the rest are
+/// exact in their shortest decimal form, and `inf` has none.
+pub fn constant(constant: &Constant, precision: u64) -> Option<BigDecimal> {
+    let digits = precision + GUARD_DIGITS;
+    let value = match constant.name {
+        "pi" | "π" => pi(digits),
+        "tau" => pi(digits) * BigDecimal::from(2),
+        "e" => exp_digits(&BigDecimal::one(), digits)?,
+        "phi" => (sqrt_digits(&BigDecimal::from(5), digits)? + BigDecimal::one()) * BigDecimal::new(BigInt::from(5), 1),
+        _ => from_f64(constant.value)?,
+    };
+    Some(round(&value, precision))
+}
+
+/// `base^exponent`. Whole exponents work for any base and others need a base of at least 0.
+pub fn pow(base: &BigDecimal, exponent: &BigDecimal, precision: u64) -> Option<BigDecimal> {
+    let one = BigDecimal::one();
+    if exponent.is_integer() {
+        let n = exponent.to_i64()?;
+        if base.is_zero() {
+            return match n {
+                0 => Some(one),
+                n if n > 0 => Some(BigDecimal::zero()),
+                _ => None,
+            };
+        }
+        if (n as f64 * log10_magnitude(base)).abs() > MAX_EXPONENT as f64 {
+            return None;
+        }
+        let digits = precision + GUARD_DIGITS + digit_count(n.unsigned_abs());
+        let (mut result, mut square, mut k) = (one.clone(), base.clone(), n.unsigned_abs());
+        while k > 0 {
+            if k & 1 == 1 {
+                result = (&result * &square).with_prec(digits);
+            }
+            square = (&square * &square).with_prec(digits);
+            k >>= 1;
+        }
+        return Some(if n < 0 { div(&one, &result, digits) } else { result });
+    }
+    if base.is_zero() && exponent.is_positive() {
+        return Some(BigDecimal::zero());
+    }
+    if !base.is_positive() {
+        return None;
+    }
+    // exp only reduces exponents below 10^9, so ten more digits of the logarithm are enough.
+    let digits = precision + GUARD_DIGITS;
+    exp_digits(&(exponent * ln_digits(base, digits + 10)).with_prec(digits + 10), digits)
+}
+
+pub fn sqrt(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    sqrt_digits(&x[0], precision + GUARD_DIGITS)
+}
+
+pub fn exp(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    exp_digits(&x[0], precision + GUARD_DIGITS)
+}
+
+pub fn ln(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    Some(ln_digits(&x[0], precision + GUARD_DIGITS))
+}
+
+pub fn log10(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    let digits = precision + GUARD_DIGITS;
+    Some(div(&ln_digits(&x[0], digits), &ln_10(digits), digits))
+}
+
+pub fn log2(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    let digits = precision + GUARD_DIGITS;
+    Some(div(&ln_digits(&x[0], digits), &ln_2(digits), digits))
+}
+
+/// `log(x, base)` with base 10 when left out.
+pub fn log(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    let digits = precision + GUARD_DIGITS;
+    let base = x.get(1).map_or_else(|| ln_10(digits), |a| ln_digits(a, digits));
+    Some(div(&ln_digits(&x[0], digits), &base, digits))
+}
+
+pub fn sin(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    Some(sin_cos(&x[0], precision + GUARD_DIGITS)?.0)
+}
+
+pub fn cos(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    Some(sin_cos(&x[0], precision + GUARD_DIGITS)?.1)
+}
+
+/// `a / b` for a ratio of the sine and cosine of `x`, or `None` at a pole, where `b` is zero
+/// to within the precision of `x`, so floating point reports it the way it always has.
+fn trig_ratio(a: &BigDecimal, b: &BigDecimal, x: &BigDecimal, precision: u64) -> Option<BigDecimal> {
+    if b.abs() <= tolerance(&x.abs(), precision) {
+        return None;
+    }
+    Some(div(a, b, precision + GUARD_DIGITS))
+}
+
+pub fn tan(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    let (sin, cos) = sin_cos(&x[0], precision + GUARD_DIGITS)?;
+    trig_ratio(&sin, &cos, &x[0], precision)
+}
+
+pub fn sec(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    let (_, cos) = sin_cos(&x[0], precision + GUARD_DIGITS)?;
+    trig_ratio(&BigDecimal::one(), &cos, &x[0], precision)
+}
+
+pub fn csc(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    let (sin, _) = sin_cos(&x[0], precision + GUARD_DIGITS)?;
+    trig_ratio(&BigDecimal::one(), &sin, &x[0], precision)
+}
+
+pub fn cot(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    let (sin, cos) = sin_cos(&x[0], precision + GUARD_DIGITS)?;
+    trig_ratio(&cos, &sin, &x[0], precision)
+}
+
+/// asin x = atan(x / √(1 - x²)) for |x| <= 1.
+pub fn asin(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    let digits = precision + GUARD_DIGITS;
+    let one = BigDecimal::one();
+    if x[0].abs() == one {
+        return Some(pi(digits) * BigDecimal::new(BigInt::from(5), 1) * x[0].signum());
+    }
+    let root = sqrt_digits(&(&one - &x[0] * &x[0]), digits)?;
+    Some(atan_digits(&div(&x[0], &root, digits), digits))
+}
+
+/// acos x = 2 atan(√((1 - x) / (1 + x))), which keeps its digits near x = 1.
+pub fn acos(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    let digits = precision + GUARD_DIGITS;
+    let one = BigDecimal::one();
+    if x[0] == -&one {
+        return Some(pi(digits));
+    }
+    let root = sqrt_digits(&div(&(&one - &x[0]), &(&one + &x[0]), digits), digits)?;
+    Some(atan_digits(&root, digits) * BigDecimal::from(2))
+}
+
+pub fn atan(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    Some(atan_digits(&x[0], precision + GUARD_DIGITS))
+}
+
+pub fn atan2(n: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    let digits = precision + GUARD_DIGITS;
+    let (y, x) = (&n[0], &n[1]);
+    let half_pi = || pi(digits) * BigDecimal::new(BigInt::from(5), 1);
+    Some(match (x.sign(), y.is_negative()) {
+        (num_bigint::Sign::Plus, _) => atan_digits(&div(y, x, digits), digits),
+        (num_bigint::Sign::Minus, negative) => {
+            let angle = atan_digits(&div(y, x, digits), digits);
+            if negative { angle - pi(digits) } else { angle + pi(digits) }
+        }
+        (num_bigint::Sign::NoSign, _) if y.is_zero() => BigDecimal::zero(),
+        (num_bigint::Sign::NoSign, true) => -half_pi(),
+        (num_bigint::Sign::NoSign, false) => half_pi(),
+    })
+}
+
+pub fn hypot(n: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    sqrt_digits(&n.iter().map(|a| a * a).sum(), precision + GUARD_DIGITS)
+}
+
+pub fn sinh(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    if is_tiny(&x[0], precision) {
+        return Some(x[0].clone());
+    }
+    let digits = working(&x[0], precision);
+    let (up, down) = (exp_digits(&x[0], digits)?, exp_digits(&-&x[0], digits)?);
+    Some((up - down) * BigDecimal::new(BigInt::from(5), 1))
+}
+
+pub fn cosh(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    let digits = precision + GUARD_DIGITS;
+    let (up, down) = (exp_digits(&x[0], digits)?, exp_digits(&-&x[0], digits)?);
+    Some((up + down) * BigDecimal::new(BigInt::from(5), 1))
+}
+
+pub fn tanh(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    if is_tiny(&x[0], precision) {
+        return Some(x[0].clone());
+    }
+    // tanh is ±1 to every digit kept once e^(2|x|) is past 10^(digits + 1).
+    if x[0].abs() > BigDecimal::from(precision + GUARD_DIGITS) * BigDecimal::from(2) {
+        return Some(x[0].signum());
+    }
+    let digits = working(&x[0], precision);
+    let (up, down) = (exp_digits(&x[0], digits)?, exp_digits(&-&x[0], digits)?);
+    Some(div(&(&up - &down), &(up + down), digits))
+}
+
+/// asinh x = ln(|x| + √(x² + 1)) with the sign of x.
+pub fn asinh(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    if is_tiny(&x[0], precision) {
+        return Some(x[0].clone());
+    }
+    let digits = working(&x[0], precision);
+    let magnitude = x[0].abs();
+    let root = sqrt_digits(&(&magnitude * &magnitude + BigDecimal::one()), digits)?;
+    Some(ln_digits(&(magnitude + root), digits) * x[0].signum())
+}
+
+/// acosh x = ln(x + √(x² - 1)) for x >= 1.
+pub fn acosh(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    let digits = working(&(&x[0] - BigDecimal::one()), precision);
+    let root = sqrt_digits(&(&x[0] * &x[0] - BigDecimal::one()), digits)?;
+    Some(ln_digits(&(&x[0] + root), digits))
+}
+
+/// atanh x = ln((1 + x) / (1 - x)) / 2 for |x| < 1.
+pub fn atanh(x: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    if is_tiny(&x[0], precision) {
+        return Some(x[0].clone());
+    }
+    let digits = working(&x[0], precision);
+    let one = BigDecimal::one();
+    let ratio = div(&(&one + &x[0]), &(&one - &x[0]), digits);
+    Some(ln_digits(&ratio, digits) * BigDecimal::new(BigInt::from(5), 1))
+}
+
+pub fn floor(x: &[BigDecimal], _: u64) -> Option<BigDecimal> {
+    Some(x[0].with_scale_round(0, RoundingMode::Floor))
+}
+
+pub fn ceil(x: &[BigDecimal], _: u64) -> Option<BigDecimal> {
+    Some(x[0].with_scale_round(0, RoundingMode::Ceiling))
+}
+
+/// `round(x, digits)` with halves away from zero like `f64::round`. `math::round_digits`
+/// has already limited `digits`.
+pub fn round_digits(x: &[BigDecimal], _: u64) -> Option<BigDecimal> {
+    let digits = x.get(1).map_or(Some(0), |a| a.to_i64())?;
+    if digits >= x[0].fractional_digit_count() {
+        return Some(x[0].clone());
+    }
+    Some(x[0].with_scale_round(digits, RoundingMode::HalfUp))
+}
+
+pub fn sign(x: &[BigDecimal], _: u64) -> Option<BigDecimal> {
+    Some(x[0].signum())
+}
+
+pub fn abs(x: &[BigDecimal], _: u64) -> Option<BigDecimal> {
+    Some(x[0].abs())
+}
+
+pub fn sum(n: &[BigDecimal], _: u64) -> Option<BigDecimal> {
+    Some(n.iter().sum())
+}
+
+pub fn average(n: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    Some(div(&n.iter().sum(), &BigDecimal::from(n.len() as u64), precision + GUARD_DIGITS))
+}
+
+pub fn median(n: &[BigDecimal], _: u64) -> Option<BigDecimal> {
+    let mut v = n.to_vec();
+    v.sort();
+    let middle = v.len() / 2;
+    if v.len().is_multiple_of(2) {
+        return Some((&v[middle - 1] + &v[middle]).half());
+    }
+    Some(v[middle].clone())
+}
+
+pub fn mode(n: &[BigDecimal], _: u64) -> Option<BigDecimal> {
+    let mut v = n.to_vec();
+    v.sort();
+    Some(most_common(&v))
+}
+
+/// Population standard deviation, like `math::standard_deviation`.
+pub fn standard_deviation(n: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    sqrt_digits(&squared_deviations(n, 0, precision + GUARD_DIGITS), precision + GUARD_DIGITS)
+}
+
+pub fn sample_standard_deviation(n: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    sqrt_digits(&squared_deviations(n, 1, precision + GUARD_DIGITS), precision + GUARD_DIGITS)
+}
+
+pub fn variance(n: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    Some(squared_deviations(n, 0, precision + GUARD_DIGITS))
+}
+
+pub fn sample_variance(n: &[BigDecimal], precision: u64) -> Option<BigDecimal> {
+    Some(squared_deviations(n, 1, precision + GUARD_DIGITS))
+}
+
+/// The sum of squared distances from the mean over the count less `lost`, so 0 for a
+/// population variance and 1 for a sample variance.
+fn squared_deviations(n: &[BigDecimal], lost: u64, digits: u64) -> BigDecimal {
+    let mean = div(&n.iter().sum(), &BigDecimal::from(n.len() as u64), digits);
+    let squares: BigDecimal = n.iter().map(|a| (a - &mean).square()).sum();
+    div(&squares, &BigDecimal::from(n.len() as u64 - lost), digits)
+}
+
+pub fn max(n: &[BigDecimal], _: u64) -> Option<BigDecimal> {
+    n.iter().max().cloned()
+}
+
+pub fn min(n: &[BigDecimal], _: u64) -> Option<BigDecimal> {
+    n.iter().min().cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use bigdecimal::BigDecimal;
+    use crate::decimal::{exp, ln, pi, pow, round, sin};
+    use crate::environment::Environment;
+    use crate::error::EvalError;
+    use crate::eval::tests::{evaluate, show};
+    use crate::math::AngleMode;
+    use crate::tokens::Span;
+    use crate::value::{NumberMode, Value};
+
+    #[test]
+    fn functions_are_correct_to_the_last_digit() {
+        let decimal = |digits: &str| BigDecimal::from_str(digits).unwrap();
+        let one = [decimal("1")];
+        assert_eq!(round(&pi(60), 50), decimal("3.1415926535897932384626433832795028841971693993751"));
+        assert_eq!(round(&exp(&one, 50).unwrap(), 50), decimal("2.7182818284590452353602874713526624977572470937000"));
+        assert_eq!(round(&ln(&[decimal("2")], 50).unwrap(), 50), decimal("0.69314718055994530941723212145817656807550013436026"));
+        assert_eq!(round(&ln(&[decimal("1e-300")], 30).unwrap(), 30), decimal("-690.775527898213705205397436405"));
+        assert_eq!(round(&sin(&[decimal("1e-40")], 20).unwrap(), 20), decimal("1e-40"));
+        assert_eq!(round(&sin(&[decimal("1000")], 30).unwrap(), 30), decimal("0.826879540532002560255887429109"));
+        assert_eq!(round(&pow(&decimal("2"), &decimal("0.5"), 30).unwrap(), 30), decimal("1.41421356237309504880168872421"));
+        assert_eq!(pow(&decimal("0.1"), &decimal("-3"), 20), Some(decimal("1000")));
+    }
+
+    #[test]
+    fn decimal_mode_arithmetic_is_exact() {
+        let mut env = Environment::new();
+        env.set_number_mode(NumberMode::Decimal { precision: 50 });
+        assert_eq!(show("0.1 + 0.2", &env), Ok("0.3".to_string()));
+        assert_eq!(show("0.1 + 0.2 == 0.3", &env), Ok("true".to_string()));
+        assert_eq!(show("1/3", &env), Ok("0.33333333333333333333333333333333333333333333333333".to_string()));
+        assert_eq!(show("1.00000000000000000001 - 1", &env), Ok("1e-20".to_string()));
+        assert_eq!(show("12345678901234567891", &env), Ok("12345678901234567891".to_string()));
+        assert_eq!(show("1e400 + 1e-400", &env), Ok("inf".to_string()));
+        assert_eq!(show("10^60/7", &env), Ok("1.4285714285714285714285714285714285714285714285714e59".to_string()));
+        assert_eq!(show("-7.5 % 2 + 2^-3 + 50%", &env), Ok("-0.875".to_string()));
+        assert_eq!(show("3e-40 * 2", &env), Ok("6e-40".to_string()));
+        assert_eq!(show("20!/3 + 0.5", &env), Ok("810967336058880000.5".to_string()));
+        env.set_number_mode(NumberMode::Decimal { precision: 5 });
+        assert_eq!(show("2/3 + 1", &env), Ok("1.6667".to_string()));
+    }
+
+    #[test]
+    fn decimal_mode_functions() {
+        let mut env = Environment::new();
+        env.set_number_mode(NumberMode::Decimal { precision: 50 });
+        assert_eq!(show("sqrt(2)", &env), Ok("1.4142135623730950488016887242096980785696718753769".to_string()));
+        assert_eq!(show("2^0.5 - √2", &env), Ok("0".to_string()));
+        assert_eq!(show("exp(1) - e + ln(e^2)", &env), Ok("2".to_string()));
+        assert_eq!(show("pi", &env), Ok("3.1415926535897932384626433832795028841971693993751".to_string()));
+        assert_eq!(show("log10(1000) + log(8, 2) + sin(pi)", &env), Ok("6".to_string()));
+        assert_eq!(show("atanh(0.5)", &env), Ok("0.54930614433405484569762261846126285232374527891137".to_string()));
+        assert_eq!(show("round(2.675, 2) + floor(-0.5)", &env), Ok("1.68".to_string()));
+        assert!(matches!(evaluate("gamma(0.5)", &env), Ok(Value::Number(_))));
+        env.set_angle_mode(AngleMode::Degrees);
+        assert_eq!(show("sin(30) + cos(90) + asin(1) + 100grad", &env), Ok("180.5".to_string()));
+    }
+
+    #[test]
+    fn huge_decimals_fall_back_to_floating_point() {
+        let mut env = Environment::new();
+        env.set_number_mode(NumberMode::Decimal { precision: 50 });
+        assert_eq!(show("2^(10^5) / 2^99999", &env), Ok("2".to_string()));
+        assert_eq!(show("0.9999999^(10^6) > 0.9", &env), Ok("true".to_string()));
+        assert_eq!(show("2^(10^18) + 1", &env), Ok("inf".to_string()));
+        assert_eq!(show("(10^300)^(10^18)", &env), Ok("inf".to_string()));
+        assert_eq!(show("exp(10^7) + 1", &env), Ok("inf".to_string()));
+        assert_eq!(show("1e-300^400 * 1e-300^400", &env), Ok("0".to_string()));
+        assert_eq!(show("round(1.5, 1e15)", &env), Err(EvalError::DomainError { name: "round".to_string(), span: Span::new(0, 16) }));
+        assert_eq!(show("round(2.675, 1000) + round(1.5, -5)", &env), Ok("2.675".to_string()));
+    }
+}
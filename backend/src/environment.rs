@@ -0,0 +1,196 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
+use crate::ast::{ASTNode, MAX_NESTING};
+use crate::math::AngleMode;
+use crate::registry::Registry;
+use crate::tokens::Span;
+use crate::value::{ComplexForm, NumberMode, Value};
+
+/// How many user function calls may be nested before evaluation gives up.
+pub const MAX_CALL_DEPTH: usize = 256;
+
+/// How many levels of nesting the bodies of the user function calls in progress may add up
+/// to. Each call may nest less than `MAX_NESTING` deep, but recursion could stack those.
+pub const MAX_CALL_NESTING: usize = 4 * MAX_NESTING;
+
+/// Evaluation recurses on the Rust stack, so callers run it on a thread with this much
+/// stack, enough for `MAX_CALL_DEPTH` calls nesting `MAX_CALL_NESTING` levels even in debug
+/// builds.
+pub const EVALUATION_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// A function defined with `name(a, b) = body`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserFunction {
+    pub parameters: Vec<String>,
+    pub body: ASTNode,
+    /// The body as it was written, for listing definitions.
+    pub text: String,
+}
+
+/// Variables bound with `name = expression` and functions defined with
+/// `name(x) = expression`, kept per session by the server. Calling a user function
+/// evaluates its body in a scope whose parent is the session, so parameters shadow
+/// session variables without changing them. Built-in functions come from a shared
+/// `Registry`, which embedding code can replace or extend per environment.
+#[derive(Clone, Debug)]
+pub struct Environment<'a> {
+    variables: HashMap<String, Value>,
+    functions: HashMap<String, UserFunction>,
+    registry: Arc<Registry>,
+    angle_mode: AngleMode,
+    number_mode: NumberMode,
+    complex_form: ComplexForm,
+    parent: Option<&'a Environment<'a>>,
+    depth: usize,
+    nesting: usize,
+    /// Where the first NaN of the evaluation in progress came from, see `note_nan`.
+    nan: OnceLock<Span>,
+}
+
+impl Default for Environment<'_> {
+    fn default() -> Self {
+        Environment { variables: HashMap::new(), functions: HashMap::new(), registry: Registry::builtin(), angle_mode: AngleMode::default(), number_mode: NumberMode::default(), complex_form: ComplexForm::default(), parent: None, depth: 0, nesting: 0, nan: OnceLock::new() }
+    }
+}
+
+impl<'a> Environment<'a> {
+    pub fn new() -> Environment<'a> {
+        Environment::default()
+    }
+
+    pub fn with_registry(registry: Arc<Registry>) -> Environment<'a> {
+        Environment { registry, ..Environment::default() }
+    }
+
+    /// The built-in functions callable from this scope.
+    pub fn registry(&self) -> &Registry {
+        match self.parent {
+            Some(parent) => parent.registry(),
+            None => &self.registry,
+        }
+    }
+
+    /// The unit trig functions use in this scope, which is always the session's.
+    pub fn angle_mode(&self) -> AngleMode {
+        match self.parent {
+            Some(parent) => parent.angle_mode(),
+            None => self.angle_mode,
+        }
+    }
+
+    pub fn set_angle_mode(&mut self, mode: AngleMode) {
+        self.angle_mode = mode;
+    }
+
+    /// How literals are represented in this scope, which is always the session's.
+    pub fn number_mode(&self) -> NumberMode {
+        match self.parent {
+            Some(parent) => parent.number_mode(),
+            None => self.number_mode,
+        }
+    }
+
+    pub fn set_number_mode(&mut self, mode: NumberMode) {
+        self.number_mode = mode;
+    }
+
+    /// How complex results are shown, which is always the session's.
+    pub fn complex_form(&self) -> ComplexForm {
+        match self.parent {
+            Some(parent) => parent.complex_form(),
+            None => self.complex_form,
+        }
+    }
+
+    pub fn set_complex_form(&mut self, form: ComplexForm) {
+        self.complex_form = form;
+    }
+
+    /// The registry for adding functions to this environment, copied first if it is shared.
+    pub fn registry_mut(&mut self) -> &mut Registry {
+        Arc::make_mut(&mut self.registry)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.variables.get(name).or_else(|| self.parent?.get(name))
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.variables.insert(name.to_string(), value);
+    }
+
+    pub fn function(&self, name: &str) -> Option<&UserFunction> {
+        self.functions.get(name).or_else(|| self.parent?.function(name))
+    }
+
+    pub fn define(&mut self, name: &str, function: UserFunction) {
+        self.functions.insert(name.to_string(), function);
+    }
+
+    /// Names of the user functions callable from this scope, for the tokenizer, which looks
+    /// the built-in ones up in `registry`.
+    pub fn user_function_names(&self) -> HashSet<String> {
+        let mut names: HashSet<String> = self.functions.keys().cloned().collect();
+        if let Some(parent) = self.parent {
+            names.extend(parent.user_function_names());
+        }
+        names
+    }
+
+    /// Number of user function calls this scope is nested in.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Levels of nesting in the bodies of the user function calls this scope is nested in.
+    pub fn nesting(&self) -> usize {
+        self.nesting
+    }
+
+    /// Notes that the sub-expression at `span` gave NaN, unless one before it has. Inside a
+    /// user function this does nothing, as the body's spans are in another text, and the call
+    /// is noted instead.
+    pub fn note_nan(&self, span: Span) {
+        if self.parent.is_none() {
+            let _ = self.nan.set(span);
+        }
+    }
+
+    /// The span noted by `note_nan` since the last call, if any.
+    pub fn take_nan(&mut self) -> Option<Span> {
+        self.nan.take()
+    }
+
+    /// A scope for the body of a user function called from this one, nesting `nesting` levels
+    /// deep. Its parent is the session rather than the caller, so a body sees its parameters
+    /// and the session's variables but never its caller's parameters.
+    pub fn call_scope(&self, nesting: usize) -> Environment<'_> {
+        let mut session = self;
+        while let Some(parent) = session.parent {
+            session = parent;
+        }
+        Environment { parent: Some(session), depth: self.depth + 1, nesting: self.nesting + nesting, ..Environment::default() }
+    }
+
+    /// Removes every variable and function, returning how many there were.
+    pub fn clear(&mut self) -> usize {
+        let count = self.variables.len() + self.functions.len();
+        self.variables.clear();
+        self.functions.clear();
+        count
+    }
+
+    /// All bindings sorted by name.
+    pub fn variables(&self) -> Vec<(&String, &Value)> {
+        let mut variables: Vec<(&String, &Value)> = self.variables.iter().collect();
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        variables
+    }
+
+    /// All user functions sorted by name.
+    pub fn functions(&self) -> Vec<(&String, &UserFunction)> {
+        let mut functions: Vec<(&String, &UserFunction)> = self.functions.iter().collect();
+        functions.sort_by(|a, b| a.0.cmp(b.0));
+        functions
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt::{Display, Formatter};
+use crate::ast::MAX_NESTING;
+use crate::tokens::{Span, Token};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseError {
+    EmptyInput,
+    UnexpectedCharacter { character: char, span: Span },
+    InvalidNumber { span: Span },
+    UnexpectedToken { token: Token, span: Span },
+    UnexpectedEnd { span: Span },
+    UnbalancedParen { span: Span },
+    UnbalancedBracket { span: Span },
+    InvalidGraph { span: Span },
+    /// Nesting deeper than `ast::MAX_NESTING`.
+    TooDeep { span: Span },
+    /// `a%-b`, which could be a remainder or a percentage.
+    AmbiguousPercent { span: Span },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EvalError {
+    UnknownFunction { name: String, span: Span },
+    ArityMismatch { name: String, min: usize, max: Option<usize>, found: usize, span: Span },
+    DomainError { name: String, span: Span },
+    UndefinedVariable { name: String, span: Span },
+    DivisionByZero { span: Span },
+    NotAnInteger { span: Span },
+    Unsupported { name: String, mode: String, span: Span },
+    TypeMismatch { expected: &'static str, found: &'static str, span: Span },
+    /// An error inside the body of a user function, reported at the call.
+    InFunction { name: String, source: Box<EvalError>, span: Span },
+    RecursionLimit { name: String, limit: usize, span: Span },
+    /// Recursive calls whose bodies together nest deeper than `environment::MAX_CALL_NESTING`.
+    NestingLimit { name: String, limit: usize, span: Span },
+    ReservedName { name: String, span: Span },
+    IndexOutOfRange { index: i64, length: usize, span: Span },
+    /// Lists combined element by element that differ in length, as in `[1, 2] + [1, 2, 3]`.
+    LengthMismatch { left: usize, right: usize, span: Span },
+    NotSquare { rows: usize, columns: usize, span: Span },
+    /// Matrices, as rows by columns, that an operation can't combine, such as a 2x3 times a
+    /// 2x3.
+    ShapeMismatch { left: (usize, usize), right: (usize, usize), span: Span },
+}
+
+/// Anything that can go wrong between receiving the text and producing a result.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    Parse(ParseError),
+    Eval(EvalError),
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::EmptyInput => Span::default(),
+            ParseError::UnexpectedCharacter { span, .. }
+            | ParseError::InvalidNumber { span }
+            | ParseError::UnexpectedToken { span, .. }
+            | ParseError::UnexpectedEnd { span }
+            | ParseError::UnbalancedParen { span }
+            | ParseError::UnbalancedBracket { span }
+            | ParseError::InvalidGraph { span }
+            | ParseError::TooDeep { span }
+            | ParseError::AmbiguousPercent { span } => *span,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParseError::EmptyInput => "empty_input",
+            ParseError::UnexpectedCharacter { .. } => "unexpected_character",
+            ParseError::InvalidNumber { .. } => "invalid_number",
+            ParseError::UnexpectedToken { .. } => "unexpected_token",
+            ParseError::UnexpectedEnd { .. } => "unexpected_end",
+            ParseError::UnbalancedParen { .. } => "unbalanced_paren",
+            ParseError::UnbalancedBracket { .. } => "unbalanced_bracket",
+            ParseError::InvalidGraph { .. } => "invalid_graph",
+            ParseError::TooDeep { .. } => "too_deep",
+            ParseError::AmbiguousPercent { .. } => "ambiguous_percent",
+        }
+    }
+}
+
+impl EvalError {
+    pub fn span(&self) -> Span {
+        match self {
+            EvalError::UnknownFunction { span, .. }
+            | EvalError::ArityMismatch { span, .. }
+            | EvalError::DomainError { span, .. }
+            | EvalError::UndefinedVariable { span, .. }
+            | EvalError::DivisionByZero { span }
+            | EvalError::NotAnInteger { span }
+            | EvalError::Unsupported { span, .. }
+            | EvalError::TypeMismatch { span, .. }
+            | EvalError::InFunction { span, .. }
+            | EvalError::RecursionLimit { span, .. }
+            | EvalError::NestingLimit { span, .. }
+            | EvalError::ReservedName { span, .. }
+            | EvalError::IndexOutOfRange { span, .. }
+            | EvalError::LengthMismatch { span, .. }
+            | EvalError::NotSquare { span, .. }
+            | EvalError::ShapeMismatch { span, .. } => *span,
+        }
+    }
+
+    /// The error itself, or for one inside a user function the error its body gave.
+    pub fn cause(&self) -> &EvalError {
+        match self {
+            EvalError::InFunction { source, .. } => source.cause(),
+            e => e,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EvalError::UnknownFunction { .. } => "unknown_function",
+            EvalError::ArityMismatch { .. } => "arity_mismatch",
+            EvalError::DomainError { .. } => "domain_error",
+            EvalError::UndefinedVariable { .. } => "undefined_variable",
+            EvalError::DivisionByZero { .. } => "division_by_zero",
+            EvalError::NotAnInteger { .. } => "not_an_integer",
+            EvalError::Unsupported { .. } => "unsupported",
+            EvalError::TypeMismatch { .. } => "type_mismatch",
+            EvalError::InFunction { source, .. } => source.kind(),
+            EvalError::RecursionLimit { .. } => "recursion_limit",
+            EvalError::NestingLimit { .. } => "nesting_limit",
+            EvalError::ReservedName { .. } => "reserved_name",
+            EvalError::IndexOutOfRange { .. } => "index_out_of_range",
+            EvalError::LengthMismatch { .. } => "length_mismatch",
+            EvalError::NotSquare { .. } => "not_square",
+            EvalError::ShapeMismatch { .. } => "shape_mismatch",
+        }
+    }
+}
+
+impl Error {
+    pub fn span(&self) -> Span {
+        match self {
+            Error::Parse(e) => e.span(),
+            Error::Eval(e) => e.span(),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Parse(e) => e.kind(),
+            Error::Eval(e) => e.kind(),
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Error {
+        Error::Parse(e)
+    }
+}
+
+impl From<EvalError> for Error {
+    fn from(e: EvalError) -> Error {
+        Error::Eval(e)
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::EmptyInput => f.write_str("Nothing to calculate"),
+            ParseError::UnexpectedCharacter { character, .. } => write!(f, "Syntax Error: unexpected character '{}'", character),
+            ParseError::InvalidNumber { .. } => f.write_str("Syntax Error: number is too large"),
+            ParseError::UnexpectedToken { token: Token::Help, .. } => f.write_str("Don't embed help in expressions"),
+            ParseError::UnexpectedToken { token, .. } => write!(f, "Syntax Error: unexpected '{}'", token),
+            ParseError::UnexpectedEnd { .. } => f.write_str("Syntax Error: expression ends too early"),
+            ParseError::UnbalancedParen { .. } => f.write_str("Syntax Error: unbalanced parenthesis"),
+            ParseError::UnbalancedBracket { .. } => f.write_str("Syntax Error: unbalanced bracket"),
+            ParseError::InvalidGraph { .. } => f.write_str("Graph must look like graph(y=...) with y on one side"),
+            ParseError::TooDeep { .. } => write!(f, "Syntax Error: expression is nested more than {} levels deep", MAX_NESTING),
+            ParseError::AmbiguousPercent { .. } => f.write_str("Syntax Error: write a % -b for a remainder or a% - b for a percentage"),
+        }
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::UnknownFunction { name, .. } => write!(f, "Unknown function: {}", name),
+            EvalError::ArityMismatch { name, min, max, found, .. } => {
+                match max {
+                    Some(max) if max == min => write!(f, "{} takes {} argument(s) but was given {}", name, min, found),
+                    Some(max) => write!(f, "{} takes {} to {} arguments but was given {}", name, min, max, found),
+                    None => write!(f, "{} takes at least {} argument(s) but was given {}", name, min, found),
+                }
+            }
+            EvalError::DomainError { name, .. } => write!(f, "Invalid input for {}", name),
+            EvalError::UndefinedVariable { name, .. } => write!(f, "Unknown variable: {}", name),
+            EvalError::DivisionByZero { .. } => f.write_str("Division by zero"),
+            EvalError::NotAnInteger { .. } => f.write_str("Expected a whole number"),
+            EvalError::Unsupported { name, mode, .. } => write!(f, "{} is not available in {} mode", name, mode),
+            EvalError::TypeMismatch { expected, found, .. } => write!(f, "Expected a {} but found a {}", expected, found),
+            EvalError::InFunction { name, source, .. } => write!(f, "{} (in {})", source, name),
+            EvalError::RecursionLimit { name, limit, .. } => write!(f, "{} was called more than {} levels deep", name, limit),
+            EvalError::NestingLimit { name, limit, .. } => write!(f, "Calls to {} nest more than {} levels deep", name, limit),
+            EvalError::ReservedName { name, .. } => write!(f, "{} is a built-in function and can't be redefined", name),
+            EvalError::IndexOutOfRange { index, length, .. } => write!(f, "Index {} is out of range for a list of {} element(s)", index, length),
+            EvalError::LengthMismatch { left, right, .. } => write!(f, "Lists have different lengths, {} and {}", left, right),
+            EvalError::NotSquare { rows, columns, .. } => write!(f, "Expected a square matrix but found a {}x{} matrix", rows, columns),
+            EvalError::ShapeMismatch { left, right, .. } => write!(f, "A {}x{} matrix and a {}x{} matrix don't fit together here", left.0, left.1, right.0, right.1),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(e) => e.fmt(f),
+            Error::Eval(e) => e.fmt(f),
+        }
+    }
+}
+
+/// Renders the input with a caret line under the characters covered by `span`.
+pub fn point_at(input: &str, span: Span) -> String {
+    let start = input.get(..span.start).map_or(0, |a| a.chars().count());
+    let width = input.get(span.start..span.end).map_or(0, |a| a.chars().count()).max(1);
+    format!("{}\n{}{}", input, " ".repeat(start), "^".repeat(width))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::point_at;
+    use crate::tokens::Span;
+
+    #[test]
+    fn caret_points_at_span() {
+        assert_eq!(point_at("1 + * 2", Span::new(4, 5)), "1 + * 2\n    ^");
+        assert_eq!(point_at("foo(1)", Span::new(0, 6)), "foo(1)\n^^^^^^");
+        assert_eq!(point_at("1 +", Span::new(3, 3)), "1 +\n   ^");
+    }
+}
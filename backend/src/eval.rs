@@ -1,161 +1,908 @@
-use crate::{ast::{ASTNode, BinaryOperation, FunctionCall, UnaryOperation}, math::{average, factorial, max, median, min, standard_deviation, sum, validate}};
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use crate::{ast::{ASTNode, BinaryOperation, FunctionCall, Index, IndexNode, UnaryOperation}, constants::{constant, IMAGINARY_UNIT}, decimal, environment::{Environment, UserFunction, MAX_CALL_DEPTH, MAX_CALL_NESTING}, error::EvalError, math::{bit_and, bit_not, bit_or, double_factorial, exact_factorial, factorial, factorial_domain, is_integer, shift_left, shift_right, snap_complex}, list::{flatten, list}, matrix, registry::{FunctionEntry, Implementation}, tokens::Span, value::{NumberMode, Value}};
 
-pub const FUNCTIONS: &'static [&'static str] = &["sum", "average", "sin", "cos", "tan", "asin", "acos", "atan", "sec", "csc", "cot", "ln", "factorial", "mean", "median", "mode", "average", "avg", "abs", "max", "min", "std"];
-
-enum Function{
-    OneToOne(fn(f64) -> f64),
-    MultiToOne(fn(Vec<f64>) -> f64),
+pub fn evaluate_ast(ast: ASTNode, env: &Environment) -> Result<Value, EvalError> {
+    let span = ast.span();
+    let value = evaluate_node(ast, env)?;
+    // Children are evaluated first, so the first NaN noted came from inputs that were not NaN.
+    if matches!(value, Value::Number(a) if a.is_nan()) {
+        env.note_nan(span);
+    }
+    Ok(value)
 }
 
-pub fn evaluate_ast(ast: ASTNode) -> Result<f64, String> {
+fn evaluate_node(ast: ASTNode, env: &Environment) -> Result<Value, EvalError> {
     match ast {
         ASTNode::BinaryNode(a) => {
-            match (evaluate_ast(*a.left), evaluate_ast(*a.right)) {
-                (Ok(left_result), Ok(right_result)) => {
-                    match a.operation {
-                        BinaryOperation::Plus => {
-                            return Ok(left_result + right_result);
-                        }
-                        BinaryOperation::Minus => {
-                            return Ok(left_result - right_result);
-                        }
-                        BinaryOperation::Times => {
-                            return Ok(left_result * right_result);
-                        }
-                        BinaryOperation::Divide => {
-                            return Ok(left_result / right_result);
-                        }
-                        BinaryOperation::Exponent => {
-                            return Ok(left_result.powf(right_result));
-                        }
-                        BinaryOperation::Modulus => {
-                            return Ok(left_result % right_result)
-                        },
+            let (left_span, right_span) = (a.left.span(), a.right.span());
+            let operation = a.operation;
+            let left_result = evaluate_ast(*a.left, env)?;
+            // `and` and `or` only evaluate their right side when the left does not decide the result.
+            let logical = operation == BinaryOperation::And || operation == BinaryOperation::Or;
+            if logical && left_result.clone().boolean(left_span)? == (operation == BinaryOperation::Or) {
+                return Ok(left_result);
+            }
+            let right_result = evaluate_ast(*a.right, env)?;
+            match operation {
+                BinaryOperation::And | BinaryOperation::Or => Ok(Value::Boolean(right_result.boolean(right_span)?)),
+                BinaryOperation::Equal | BinaryOperation::NotEqual => {
+                    if left_result.type_name() != right_result.type_name() {
+                        return Err(EvalError::TypeMismatch { expected: left_result.type_name(), found: right_result.type_name(), span: right_span });
                     }
+                    Ok(Value::Boolean((left_result == right_result) == (operation == BinaryOperation::Equal)))
                 }
-                //reduncant eror handeling and logging
-                (Ok(_), Err(e)) => {
-                    println!("Error: {}", e);
-                    return Err(e);
-                },
-                (Err(e), Ok(_)) => {
-                    println!("Error: {}", e);
-                    return Err(e);
-                },
-                (Err(e), Err(e2)) => {
-                    println!("Errors: {} and {}", e, e2);
-                    return Err(e)
-                },
+                _ => evaluate_binary(&operation, left_result, right_result, left_span, right_span, env),
             }
-
         }
         ASTNode::UnaryNode(a) => {
-            match evaluate_ast(*a.child){
-                Ok(child) => {
-                    match a.operation {
-                        UnaryOperation::Negate => {
-                            return Ok(-1.0 * child);
-                        }
-                        UnaryOperation::Parens => {
-                            return Ok(child)
-                        }
-                    }
-                },
-                Err(e) => {
-                    println!("Error: {}", e);
-                    return Err(e)
+            let (span, node_span) = (a.child.span(), a.span);
+            let child = evaluate_ast(*a.child, env)?;
+            evaluate_unary(&a.operation, child, span, node_span, env)
+        }
+        ASTNode::NumberNode(a, _) => match env.number_mode() {
+            // Literals out of the range of an `f64` stay floating point, their digits would make every later step slow.
+            _ if !a.value.is_normal() && !a.digits.is_zero() => Ok(Value::Number(a.value)),
+            NumberMode::Rational => Ok(Value::Rational(decimal::to_rational(&a.digits))),
+            NumberMode::Decimal { precision } => Ok(Value::Decimal(decimal::round(&a.digits, precision))),
+            NumberMode::Float => Ok(Value::Number(a.value)),
+        },
+        ASTNode::IntegerNode(a, _) => match env.number_mode() {
+            NumberMode::Rational => Ok(Value::Rational(BigRational::from_integer(a))),
+            NumberMode::Decimal { precision } => Ok(Value::Decimal(decimal::round(&BigDecimal::from(a), precision))),
+            NumberMode::Float => Ok(Value::Number(a.to_f64().unwrap_or(f64::INFINITY))),
+        },
+        ASTNode::FunctionCall(a) => evaluate_function(a, env),
+        ASTNode::List(elements, _) => elements.into_iter().map(|a| evaluate_ast(a, env)).collect::<Result<_, _>>().map(Value::from_elements),
+        ASTNode::Index(a) => evaluate_index(a, env),
+        ASTNode::Variable(name, span) => match (env.get(&name), constant(&name)) {
+            (Some(value), _) => Ok(value.clone()),
+            (None, Some(constant)) => match decimal_mode(env).and_then(|precision| decimal::constant(constant, precision)) {
+                Some(value) => Ok(Value::Decimal(value)),
+                None => Ok(Value::Number(constant.value)),
+            },
+            (None, None) if name == IMAGINARY_UNIT => Ok(Value::Complex(Complex64::i())),
+            (None, None) => Err(EvalError::UndefinedVariable { name, span }),
+        },
+    }
+}
+
+/// `left operation right` for the arithmetic, comparison, bitwise and `∠` operators, element
+/// by element for lists, where a single number on one side goes with every element.
+fn evaluate_binary(operation: &BinaryOperation, left: Value, right: Value, left_span: Span, right_span: Span, env: &Environment) -> Result<Value, EvalError> {
+    if matches!(left, Value::Matrix(_)) || matches!(right, Value::Matrix(_)) {
+        return evaluate_matrix(operation, left, right, left_span, right_span, env);
+    }
+    if matches!(left, Value::List(_)) || matches!(right, Value::List(_)) {
+        return broadcast(vec![(left, left_span), (right, right_span)], |mut values| {
+            let (right, _) = values.pop().unwrap();
+            let (left, _) = values.pop().unwrap();
+            evaluate_binary(operation, left, right, left_span, right_span, env)
+        });
+    }
+    match operation {
+        BinaryOperation::Phasor => {
+            let magnitude = left.number(left_span)?;
+            let angle = env.angle_mode().to_radians(right.number(right_span)?);
+            Ok(Value::from_complex(snap_complex(Complex64::from_polar(magnitude, angle), magnitude.abs() * angle.abs().max(1.))))
+        }
+        _ if divides_fraction_by_zero(operation, &left, &right) => Err(EvalError::DivisionByZero { span: right_span }),
+        _ => match evaluate_exact(operation, &left, &right, env.number_mode()) {
+            Some(result) => Ok(result),
+            None => evaluate_arithmetic(operation.clone(), left.number(left_span)?, right.number(right_span)?, left_span, right_span),
+        },
+    }
+}
+
+/// `left operation right` where either side is a matrix. `*` is the matrix product, also of a
+/// matrix and a list as a vector, and `^` a whole power of a square matrix. Other operators
+/// work element by element, on matrices of the same shape or a matrix and a single number.
+fn evaluate_matrix(operation: &BinaryOperation, left: Value, right: Value, left_span: Span, right_span: Span, env: &Environment) -> Result<Value, EvalError> {
+    let span = left_span.to(right_span);
+    let each = |left, right| evaluate_binary(operation, left, right, left_span, right_span, env);
+    match (operation, left, right) {
+        (BinaryOperation::Times, left @ (Value::Matrix(_) | Value::List(_)), right @ (Value::Matrix(_) | Value::List(_))) => {
+            matrix::multiply(left, right, env.number_mode(), span)
+        }
+        (BinaryOperation::Exponent, Value::Matrix(rows), exponent) => matrix::power(rows, exponent, env.number_mode(), left_span, right_span),
+        (_, Value::Matrix(a), Value::Matrix(b)) if matrix::shape(&a) != matrix::shape(&b) => {
+            Err(EvalError::ShapeMismatch { left: matrix::shape(&a), right: matrix::shape(&b), span })
+        }
+        (_, Value::Matrix(a), Value::Matrix(b)) => map_matrix(a.into_iter().zip(b).map(|(a, b)| a.into_iter().zip(b).collect()).collect(), |(a, b)| each(a, b)),
+        (_, Value::List(_), Value::Matrix(_)) => Err(EvalError::TypeMismatch { expected: "matrix", found: "list", span: left_span }),
+        (_, Value::Matrix(_), Value::List(_)) => Err(EvalError::TypeMismatch { expected: "matrix", found: "list", span: right_span }),
+        (_, Value::Matrix(a), b) => map_matrix(a, |a| each(a, b.clone())),
+        (_, a, Value::Matrix(b)) => map_matrix(b, |b| each(a.clone(), b)),
+        _ => unreachable!("one side is a matrix"),
+    }
+}
+
+/// A matrix of `function` applied to each element of `rows`.
+fn map_matrix<T>(rows: Vec<Vec<T>>, function: impl Fn(T) -> Result<Value, EvalError>) -> Result<Value, EvalError> {
+    let mut results = Vec::new();
+    for row in rows {
+        results.push(row.into_iter().map(&function).collect::<Result<_, _>>()?);
+    }
+    Ok(Value::Matrix(results))
+}
+
+/// `operation child` for a prefix or postfix operator, applied to each element of a list or
+/// matrix.
+fn evaluate_unary(operation: &UnaryOperation, child: Value, span: Span, node_span: Span, env: &Environment) -> Result<Value, EvalError> {
+    match child {
+        Value::List(elements) => return elements.into_iter().map(|a| evaluate_unary(operation, a, span, node_span, env)).collect::<Result<_, _>>().map(Value::List),
+        Value::Matrix(rows) => return map_matrix(rows, |a| evaluate_unary(operation, a, span, node_span, env)),
+        _ => {}
+    }
+    match *operation {
+        UnaryOperation::Negate => match child {
+            Value::Integer(a) => Ok(Value::Integer(-a)),
+            Value::Rational(a) => Ok(Value::Rational(-a)),
+            Value::Decimal(a) => Ok(Value::Decimal(-a)),
+            Value::Complex(a) => Ok(Value::Complex(-a)),
+            child => Ok(Value::Number(-child.number(span)?)),
+        },
+        UnaryOperation::Parens => Ok(child),
+        UnaryOperation::BitNot => Ok(Value::Number(bit_not(whole_number(child.number(span)?, span)?))),
+        UnaryOperation::SquareRoot => match decimal_mode(env).and_then(|precision| Some((decimal::sqrt(&[child.decimal(precision)?], precision)?, precision))) {
+            Some((root, precision)) => Ok(Value::Decimal(decimal::round(&root, precision))),
+            None => match child {
+                Value::Complex(a) => Ok(Value::from_complex(a.sqrt())),
+                child => match child.number(span)? {
+                    a if a < 0. => Ok(Value::Complex(Complex64::new(0., (-a).sqrt()))),
+                    a => Ok(Value::Number(a.sqrt())),
                 },
+            },
+        },
+        UnaryOperation::Not => Ok(Value::Boolean(!child.boolean(span)?)),
+        UnaryOperation::Factorial => {
+            let child = child.number(span)?;
+            factorial_domain(&[child], "factorial", node_span)?;
+            match exact_factorial(&[child]) {
+                Some(a) => Ok(Value::Integer(a)),
+                None => Ok(Value::Number(factorial(child))),
             }
         }
-        ASTNode::NumberNode(a) => {
-            return Ok(a);
-        }
-        ASTNode::UnfinishedNode(a) => {
-            return Err(format!("Syntax Error: {:?}", a).to_owned())
-        }
-        ASTNode::FunctionCall(a) => {
-            return evaluate_function(a)
-        }
-        ASTNode::Comma => {
-            return Err("Syntax Error, stray comma?".to_string());
-        }
-        ASTNode::Variable(_) => {
-            return Err("Syntax Error".to_owned());
-        }
-    }
-}
-
-pub fn evaluate_function(function_call: FunctionCall) -> Result<f64, String> {
-    let func;
-    if function_call.operation == "average" || function_call.operation == "avg" || function_call.operation == "mean" {
-        func = Function::MultiToOne(average);
-    } else if function_call.operation == "std" {
-        func = Function::MultiToOne(standard_deviation);
-    } else if function_call.operation == "median" {
-        func = Function::MultiToOne(median);
-    } else if function_call.operation == "min" {
-        func = Function::MultiToOne(min);
-    } else if function_call.operation == "max" {
-        func = Function::MultiToOne(max);
-    } else if function_call.operation == "sum" {
-        func = Function::MultiToOne(sum);
-    } else if function_call.operation == "sin" {
-        func = Function::OneToOne(f64::sin);
-    } else if function_call.operation == "cos" {
-        func = Function::OneToOne(f64::cos);
-    } else if function_call.operation == "tan" {
-        func = Function::OneToOne(f64::tan);
-    } else if function_call.operation == "asin" {
-        func = Function::OneToOne(f64::asin);
-    } else if function_call.operation == "acos" {
-        func = Function::OneToOne(f64::acos);
-    } else if function_call.operation == "atan" {
-        func = Function::OneToOne(f64::atan);
-    } else if function_call.operation == "sec" {
-        func = Function::OneToOne(|x| 1. / f64::cos(x));
-    } else if function_call.operation == "cot" {
-        func = Function::OneToOne(|x| 1. / f64::tan(x));
-    } else if function_call.operation == "csc" {
-        func = Function::OneToOne(|x| 1. / f64::sin(x));
-    } else if function_call.operation == "ln" {
-        func = Function::OneToOne(|x| x.ln());
-    } else if function_call.operation == "abs" {
-        func = Function::OneToOne(|x| x.abs());
-    } else if function_call.operation == "factorial" {
-        func = Function::OneToOne(factorial);
-    } else {
-        return Err(format!("Unknown function: {:?}", function_call.operation));
+        UnaryOperation::DoubleFactorial => {
+            let child = child.number(span)?;
+            if child < 0. || child.fract() != 0. {
+                return Err(EvalError::DomainError { name: "double factorial".to_string(), span: node_span });
+            }
+            Ok(Value::Number(double_factorial(child)))
+        }
+        UnaryOperation::Percent => match child {
+            Value::Rational(a) => Ok(Value::Rational(a / BigInt::from(100))),
+            Value::Decimal(a) => Ok(Value::Decimal(a * BigDecimal::new(BigInt::from(1), 2))),
+            Value::Complex(a) => Ok(Value::Complex(a / 100.)),
+            child => Ok(Value::Number(child.number(span)? / 100.)),
+        },
+        UnaryOperation::Angle(unit) => match decimal_mode(env).and_then(|precision| Some((child.decimal(precision)?, precision))) {
+            Some((angle, precision)) => Ok(Value::Decimal(decimal::round(&decimal::convert(&angle, unit, env.angle_mode(), precision), precision))),
+            None => match child {
+                Value::Complex(a) => Ok(Value::from_complex(a * unit.convert(1., env.angle_mode()))),
+                child => Ok(Value::Number(unit.convert(child.number(span)?, env.angle_mode()))),
+            },
+        },
     }
-    let mut paramaters = Vec::new();
-    for child in function_call.inputs {
-        match evaluate_ast(child) {
-            Ok(a) => {
-                paramaters.push(a);
+}
+
+/// Largest number of bits `^` builds exactly, about 300000 digits.
+const MAX_EXACT_BITS: u64 = 1 << 20;
+
+/// The working precision in decimal mode.
+fn decimal_mode(env: &Environment) -> Option<u64> {
+    match env.number_mode() {
+        NumberMode::Decimal { precision } => Some(precision),
+        _ => None,
+    }
+}
+
+/// `left operation right` in decimal mode, or without rounding when one side is an exact
+/// fraction or integer and the other is exact too, or a whole `Number`. `None` falls back to
+/// floating point.
+fn evaluate_exact(operation: &BinaryOperation, left: &Value, right: &Value, mode: NumberMode) -> Option<Value> {
+    if matches!(left, Value::Complex(_)) || matches!(right, Value::Complex(_)) {
+        return complex_arithmetic(operation, left.complex()?, right.complex()?);
+    }
+    if let NumberMode::Decimal { precision } = mode {
+        return decimal_arithmetic(operation, left.decimal(precision)?, right.decimal(precision)?, precision);
+    }
+    let is = |f: fn(&Value) -> bool| f(left) || f(right);
+    if is(|a| matches!(a, Value::Rational(_))) {
+        return rational_arithmetic(operation, left.rational()?, right.rational()?);
+    }
+    if is(|a| matches!(a, Value::Integer(_))) {
+        return exact_arithmetic(operation, left.integer()?, right.integer()?).map(Value::Integer);
+    }
+    None
+}
+
+/// Whether `left operation right` divides an exact fraction by zero, as `1/0` and `0^-1` do
+/// in exact mode. Fractions have no infinity to give instead.
+fn divides_fraction_by_zero(operation: &BinaryOperation, left: &Value, right: &Value) -> bool {
+    if !matches!(left, Value::Rational(_)) && !matches!(right, Value::Rational(_)) {
+        return false;
+    }
+    match (operation, left.rational(), right.rational()) {
+        (BinaryOperation::Divide | BinaryOperation::Modulus, Some(_), Some(b)) => b.is_zero(),
+        (BinaryOperation::Exponent, Some(a), Some(b)) => a.is_zero() && b.is_negative(),
+        _ => false,
+    }
+}
+
+/// Rational mode arithmetic and comparisons. Powers need a whole exponent.
+fn rational_arithmetic(operation: &BinaryOperation, a: BigRational, b: BigRational) -> Option<Value> {
+    let result = match operation {
+        BinaryOperation::Plus => a + b,
+        BinaryOperation::Minus => a - b,
+        BinaryOperation::Times => a * b,
+        BinaryOperation::Divide if !b.is_zero() => a / b,
+        BinaryOperation::Modulus if !b.is_zero() => &a - &b * (&a / &b).trunc(),
+        BinaryOperation::Exponent if b.is_integer() && !(a.is_zero() && b.is_negative()) => {
+            let exponent = b.to_integer().to_i32()?;
+            let bits = a.numer().bits() + a.denom().bits();
+            if bits.saturating_mul(exponent.unsigned_abs() as u64) > MAX_EXACT_BITS {
+                return None;
+            }
+            a.pow(exponent)
+        }
+        BinaryOperation::Less => return Some(Value::Boolean(a < b)),
+        BinaryOperation::Greater => return Some(Value::Boolean(a > b)),
+        BinaryOperation::LessEqual => return Some(Value::Boolean(a <= b)),
+        BinaryOperation::GreaterEqual => return Some(Value::Boolean(a >= b)),
+        _ => return None,
+    };
+    Some(Value::Rational(result))
+}
+
+/// Arithmetic with a complex operand. Complex numbers have no order, so comparisons are
+/// left to report a type error.
+fn complex_arithmetic(operation: &BinaryOperation, a: Complex64, b: Complex64) -> Option<Value> {
+    match operation {
+        BinaryOperation::Plus => Some(Value::from_complex(a + b)),
+        BinaryOperation::Minus => Some(Value::from_complex(a - b)),
+        BinaryOperation::Times => Some(Value::from_complex(a * b)),
+        BinaryOperation::Divide => Some(Value::from_complex(a / b)),
+        BinaryOperation::Exponent => Some(complex_power(a, b)),
+        _ => None,
+    }
+}
+
+/// The principal value of `base^exponent`, exact for small whole exponents.
+fn complex_power(base: Complex64, exponent: Complex64) -> Value {
+    if exponent.im == 0. && exponent.re.fract() == 0. && exponent.re.abs() <= i32::MAX as f64 {
+        return Value::from_complex(base.powi(exponent.re as i32));
+    }
+    if base == Complex64::from(0.) {
+        return Value::from_complex(if exponent.re > 0. { base } else { Complex64::from(f64::NAN) });
+    }
+    let result = base.powc(exponent);
+    Value::from_complex(snap_complex(result, result.norm() * (exponent * base.ln()).norm().max(1.)))
+}
+
+/// Decimal mode arithmetic and comparisons, rounded to `precision` significant digits, or
+/// `None` past `decimal::MAX_EXPONENT` so floating point takes over.
+fn decimal_arithmetic(operation: &BinaryOperation, a: BigDecimal, b: BigDecimal, precision: u64) -> Option<Value> {
+    if !decimal::in_range(&a) || !decimal::in_range(&b) {
+        return None;
+    }
+    let result = match operation {
+        BinaryOperation::Plus => a + b,
+        BinaryOperation::Minus => a - b,
+        BinaryOperation::Times => a * b,
+        BinaryOperation::Divide if !b.is_zero() => decimal::div(&a, &b, precision),
+        BinaryOperation::Modulus if !b.is_zero() => a % b,
+        BinaryOperation::Exponent => decimal::pow(&a, &b, precision)?,
+        BinaryOperation::Less => return Some(Value::Boolean(a < b)),
+        BinaryOperation::Greater => return Some(Value::Boolean(a > b)),
+        BinaryOperation::LessEqual => return Some(Value::Boolean(a <= b)),
+        BinaryOperation::GreaterEqual => return Some(Value::Boolean(a >= b)),
+        _ => return None,
+    };
+    let result = decimal::round(&result, precision);
+    decimal::in_range(&result).then_some(Value::Decimal(result))
+}
+
+/// `a operation b` on exact integers, or `None` where the result isn't a whole number or
+/// would be unreasonably large, so floating point takes over.
+fn exact_arithmetic(operation: &BinaryOperation, a: BigInt, b: BigInt) -> Option<BigInt> {
+    let zero = BigInt::zero();
+    match operation {
+        BinaryOperation::Plus => Some(a + b),
+        BinaryOperation::Minus => Some(a - b),
+        BinaryOperation::Times => Some(a * b),
+        BinaryOperation::Divide if b != zero && &a % &b == zero => Some(a / b),
+        BinaryOperation::Modulus if b != zero => Some(a % b),
+        BinaryOperation::Exponent if b >= zero => {
+            let exponent = b.to_u32()?;
+            (a.bits().saturating_mul(exponent as u64) <= MAX_EXACT_BITS).then(|| a.pow(exponent))
+        }
+        _ => None,
+    }
+}
+
+fn evaluate_arithmetic(operation: BinaryOperation, left_result: f64, right_result: f64, left_span: Span, right_span: Span) -> Result<Value, EvalError> {
+    match operation {
+        BinaryOperation::Plus => Ok(Value::Number(left_result + right_result)),
+        BinaryOperation::Minus => Ok(Value::Number(left_result - right_result)),
+        BinaryOperation::Times => Ok(Value::Number(left_result * right_result)),
+        BinaryOperation::Divide => Ok(Value::Number(left_result / right_result)),
+        // A negative base to a fractional power is complex, as in (-8)^(1/3).
+        BinaryOperation::Exponent if left_result < 0. && right_result.is_finite() && right_result.fract() != 0. => {
+            Ok(complex_power(Complex64::from(left_result), Complex64::from(right_result)))
+        }
+        BinaryOperation::Exponent => Ok(Value::Number(left_result.powf(right_result))),
+        BinaryOperation::Modulus => Ok(Value::Number(left_result % right_result)),
+        BinaryOperation::Less => Ok(Value::Boolean(left_result < right_result)),
+        BinaryOperation::Greater => Ok(Value::Boolean(left_result > right_result)),
+        BinaryOperation::LessEqual => Ok(Value::Boolean(left_result <= right_result)),
+        BinaryOperation::GreaterEqual => Ok(Value::Boolean(left_result >= right_result)),
+        BinaryOperation::BitAnd | BinaryOperation::BitOr | BinaryOperation::ShiftLeft | BinaryOperation::ShiftRight => {
+            whole_number(left_result, left_span)?;
+            whole_number(right_result, right_span)?;
+            let operands = vec![left_result, right_result];
+            match operation {
+                BinaryOperation::BitAnd => Ok(Value::Number(bit_and(operands))),
+                BinaryOperation::BitOr => Ok(Value::Number(bit_or(operands))),
+                _ if right_result < 0. => Err(EvalError::DomainError { name: "shift".to_string(), span: right_span }),
+                BinaryOperation::ShiftLeft => Ok(Value::Number(shift_left(operands))),
+                _ => Ok(Value::Number(shift_right(operands))),
             }
-            Err(e) => {
-                return Err(format!("Syntax Error: {:?}", e));
+        }
+        BinaryOperation::And | BinaryOperation::Or | BinaryOperation::Equal | BinaryOperation::NotEqual | BinaryOperation::Phasor => {
+            unreachable!("logical operators and ∠ are evaluated by evaluate_ast")
+        }
+    }
+}
+
+pub fn evaluate_function(function_call: FunctionCall, env: &Environment) -> Result<Value, EvalError> {
+    let Some(entry) = env.registry().get(&function_call.operation) else {
+        return match env.function(&function_call.operation) {
+            Some(function) => call_user_function(function_call, function, env),
+            None => Err(EvalError::UnknownFunction { name: function_call.operation, span: function_call.span }),
+        };
+    };
+    match entry.implementation {
+        Implementation::Conditional => return evaluate_if(function_call, env),
+        Implementation::Map => return evaluate_map(function_call, env),
+        _ => {}
+    }
+    let mut values = Vec::new();
+    for child in function_call.inputs {
+        let span = child.span();
+        values.push((evaluate_ast(child, env)?, span));
+    }
+    call_entry(entry, &function_call.operation, values, function_call.span, env)
+}
+
+/// Applies a built-in function, called as `called_as` at `span`, to evaluated arguments.
+fn call_entry(entry: &FunctionEntry, called_as: &str, mut values: Vec<(Value, Span)>, span: Span, env: &Environment) -> Result<Value, EvalError> {
+    if let Implementation::Values(function) = entry.implementation {
+        entry.check_arity(called_as, values.len(), span)?;
+        return function(values.into_iter().map(|(a, _)| a).collect(), env.number_mode(), span);
+    }
+    // Functions of any number of arguments, like the statistics, take the elements of lists
+    // as arguments. Others apply to each element, so sin([0, π]) is a list.
+    if values.iter().any(|(a, _)| matches!(a, Value::List(_) | Value::Matrix(_))) {
+        if entry.max_arity.is_some() {
+            return broadcast(values, |values| call_entry(entry, called_as, values, span, env));
+        }
+        let mut elements = Vec::new();
+        for (value, span) in values {
+            let mut numbers = Vec::new();
+            flatten(value, &mut numbers);
+            elements.extend(numbers.into_iter().map(|a| (a, span)));
+        }
+        values = elements;
+    }
+    // and/or/xor/not are logical rather than bitwise when every argument is a boolean.
+    let logical = !values.is_empty() && values.iter().all(|(a, _)| matches!(a, Value::Boolean(_)));
+    if logical && ["and", "or", "xor", "not"].contains(&entry.name.as_str()) {
+        let bits: Vec<f64> = values.into_iter().map(|(a, _)| (a == Value::Boolean(true)) as u8 as f64).collect();
+        entry.check(called_as, &bits, span)?;
+        return match entry.name.as_str() {
+            "not" => Ok(Value::Boolean(bits[0] == 0.)),
+            _ => Ok(Value::Boolean(entry.call(bits, env.angle_mode()) != Value::Number(0.))),
+        };
+    }
+    // Complex arguments need a complex version of the function.
+    if values.iter().any(|(a, _)| matches!(a, Value::Complex(_))) && entry.complex.is_some() {
+        let mut args = Vec::new();
+        for (value, span) in values {
+            match value.complex() {
+                Some(a) => args.push(a),
+                None => return Err(EvalError::TypeMismatch { expected: "number", found: value.type_name(), span }),
             }
         }
+        entry.check_arity(called_as, args.len(), span)?;
+        return Ok(entry.call_complex(args, env.angle_mode()).expect("the entry has a complex version"));
     }
-    if validate(&paramaters, &function_call.operation){
-        match func{
-            Function::OneToOne(f) => Ok(f(paramaters[0])),
-            Function::MultiToOne(f) => Ok(f(paramaters)),
+    // Functions with an exact version keep fractions exact in rational mode.
+    let exact = values.iter().any(|(a, _)| matches!(a, Value::Rational(_)));
+    let fractions: Option<Vec<BigRational>> = values.iter().map(|(a, _)| a.rational()).collect();
+    let decimals: Option<(Vec<BigDecimal>, u64)> = decimal_mode(env).and_then(|precision| Some((values.iter().map(|(a, _)| a.decimal(precision)).collect::<Option<_>>()?, precision)));
+    let mut paramaters = Vec::new();
+    for (value, span) in values {
+        paramaters.push(value.number(span)?);
+    }
+    // Outside the real domain, as for ln(-1) or asin(2), a finite complex result stands in
+    // for the domain error.
+    if let Err(error) = entry.check(called_as, &paramaters, span) {
+        let complex = match error {
+            EvalError::DomainError { .. } if paramaters.iter().all(|a| a.is_finite()) => entry.call_complex(paramaters.into_iter().map(Complex64::from).collect(), env.angle_mode()),
+            _ => None,
+        };
+        return match complex {
+            Some(result) if result.complex().is_some_and(|a| a.is_finite()) => Ok(result),
+            _ => Err(error),
+        };
+    }
+    if let (true, Some(rational), Some(fractions)) = (exact, entry.rational, fractions) {
+        return Ok(Value::Rational(rational(&fractions)));
+    }
+    if let Some(result) = decimals.and_then(|(decimals, precision)| entry.call_decimal(decimals, env.angle_mode(), precision)) {
+        return Ok(result);
+    }
+    let result = entry.call(paramaters.clone(), env.angle_mode());
+    // NaN from finite arguments may be a complex result, as for sqrt(-1).
+    if matches!(result, Value::Number(a) if a.is_nan()) && paramaters.iter().all(|a| a.is_finite()) {
+        if let Some(complex) = entry.call_complex(paramaters.into_iter().map(Complex64::from).collect(), env.angle_mode()) {
+            return Ok(complex);
         }
+    }
+    Ok(result)
+}
+
+/// Calls `function` once for each element of the list arguments, which must all be the same
+/// length, passing the other arguments unchanged to every call. Matrices go row by row, and
+/// give a matrix back.
+fn broadcast(values: Vec<(Value, Span)>, function: impl Fn(Vec<(Value, Span)>) -> Result<Value, EvalError>) -> Result<Value, EvalError> {
+    let values: Vec<(Value, Span)> = values.into_iter().map(|(value, span)| match value {
+        Value::Matrix(rows) => (Value::List(rows.into_iter().map(Value::List).collect()), span),
+        value => (value, span),
+    }).collect();
+    let mut length = None;
+    for (value, span) in &values {
+        match (value, length) {
+            (Value::List(a), Some(length)) if a.len() != length => return Err(EvalError::LengthMismatch { left: length, right: a.len(), span: *span }),
+            (Value::List(a), _) => length = Some(a.len()),
+            _ => {}
+        }
+    }
+    let element = |index: usize| values.iter().map(|(value, span)| match value {
+        Value::List(a) => (a[index].clone(), *span),
+        a => (a.clone(), *span),
+    }).collect();
+    (0..length.unwrap_or(0)).map(|index| function(element(index))).collect::<Result<_, _>>().map(Value::from_elements)
+}
+
+/// `if(condition, then, else)` only evaluates the branch it picks, so recursive user
+/// functions can stop.
+fn evaluate_if(function_call: FunctionCall, env: &Environment) -> Result<Value, EvalError> {
+    let mut inputs = function_call.inputs;
+    if inputs.len() != 3 {
+        return Err(EvalError::ArityMismatch { name: function_call.operation, min: 3, max: Some(3), found: inputs.len(), span: function_call.span });
+    }
+    let condition = inputs.pop_front().unwrap();
+    let span = condition.span();
+    let branch = if evaluate_ast(condition, env)?.boolean(span)? { inputs.pop_front() } else { inputs.pop_back() };
+    evaluate_ast(branch.unwrap(), env)
+}
+
+/// `map(f, v)` calls the function named `f`, built-in or user defined, on each element of
+/// the list `v`.
+fn evaluate_map(function_call: FunctionCall, env: &Environment) -> Result<Value, EvalError> {
+    let (called_as, span) = (function_call.operation, function_call.span);
+    let mut inputs = function_call.inputs;
+    if inputs.len() != 2 {
+        return Err(EvalError::ArityMismatch { name: called_as, min: 2, max: Some(2), found: inputs.len(), span });
+    }
+    let (name, name_span) = match inputs.pop_front().unwrap() {
+        ASTNode::Variable(name, span) => (name, span),
+        node => return Err(EvalError::TypeMismatch { expected: "function name", found: "expression", span: node.span() }),
+    };
+    let list_node = inputs.pop_front().unwrap();
+    let list_span = list_node.span();
+    let elements = list(evaluate_ast(list_node, env)?, list_span)?;
+    let entry = env.registry().get(&name);
+    match (entry, env.function(&name)) {
+        (Some(entry), _) if matches!(entry.implementation, Implementation::Conditional | Implementation::Map) => {
+            return Err(EvalError::DomainError { name: called_as, span: name_span });
+        }
+        (None, Some(function)) if function.parameters.len() != 1 => {
+            let expected = function.parameters.len();
+            return Err(EvalError::ArityMismatch { name, min: expected, max: Some(expected), found: 1, span: name_span });
+        }
+        (None, None) => return Err(EvalError::UnknownFunction { name, span: name_span }),
+        _ => {}
+    }
+    let mut results = Vec::new();
+    for element in elements {
+        results.push(match (entry, env.function(&name)) {
+            (Some(entry), _) => call_entry(entry, &name, vec![(element, list_span)], span, env)?,
+            (None, Some(function)) => apply_user_function(name.clone(), function, vec![element], span, env)?,
+            (None, None) => unreachable!("checked above"),
+        });
+    }
+    Ok(Value::List(results))
+}
+
+/// Evaluates the arguments in the caller's scope, then the body with `apply_user_function`.
+fn call_user_function(function_call: FunctionCall, function: &UserFunction, env: &Environment) -> Result<Value, EvalError> {
+    let (name, span) = (function_call.operation, function_call.span);
+    let expected = function.parameters.len();
+    if function_call.inputs.len() != expected {
+        return Err(EvalError::ArityMismatch { name, min: expected, max: Some(expected), found: function_call.inputs.len(), span });
+    }
+    let mut values = Vec::new();
+    for input in function_call.inputs {
+        values.push(evaluate_ast(input, env)?);
+    }
+    apply_user_function(name, function, values, span, env)
+}
+
+/// Evaluates the body in a new scope with the parameters bound to `values`. The body's spans
+/// refer to the text it was defined in, so errors from it are reported at the call's `span`.
+fn apply_user_function(name: String, function: &UserFunction, values: Vec<Value>, span: Span, env: &Environment) -> Result<Value, EvalError> {
+    if env.depth() >= MAX_CALL_DEPTH {
+        return Err(EvalError::RecursionLimit { name, limit: MAX_CALL_DEPTH, span });
+    }
+    let nesting = function.body.depth();
+    if env.nesting() + nesting > MAX_CALL_NESTING {
+        return Err(EvalError::NestingLimit { name, limit: MAX_CALL_NESTING, span });
+    }
+    let mut scope = env.call_scope(nesting);
+    for (parameter, value) in function.parameters.iter().zip(values) {
+        scope.set(parameter, value);
+    }
+    evaluate_ast(function.body.clone(), &scope).map_err(|e| match e {
+        EvalError::InFunction { name, source, .. } => EvalError::InFunction { name, source, span },
+        EvalError::RecursionLimit { name, limit, .. } => EvalError::RecursionLimit { name, limit, span },
+        EvalError::NestingLimit { name, limit, .. } => EvalError::NestingLimit { name, limit, span },
+        e => EvalError::InFunction { name, source: Box::new(e), span },
+    })
+}
+
+/// An element or slice of a list, see `IndexNode`. Slice bounds past either end are clamped
+/// as in Python, while an element index past the end is an error.
+fn evaluate_index(node: IndexNode, env: &Environment) -> Result<Value, EvalError> {
+    let target_span = node.target.span();
+    let elements = list(evaluate_ast(*node.target, env)?, target_span)?;
+    let length = elements.len() as i64;
+    match node.index {
+        Index::Element(index) => {
+            let span = index.span();
+            let index = list_index(*index, env)?;
+            let position = if index < 0 { index + length } else { index };
+            match (0..length).contains(&position) {
+                true => Ok(elements[position as usize].clone()),
+                false => Err(EvalError::IndexOutOfRange { index, length: elements.len(), span }),
+            }
+        }
+        Index::Slice(start, end) => {
+            let bound = |bound: Option<Box<ASTNode>>, default: i64| match bound {
+                Some(node) => list_index(*node, env).map(|a| if a < 0 { a + length } else { a }.clamp(0, length)),
+                None => Ok(default),
+            };
+            let (start, end) = (bound(start, 0)? as usize, bound(end, length)? as usize);
+            Ok(Value::from_elements(elements[start..end.max(start)].to_vec()))
+        }
+    }
+}
+
+/// A whole number list index, counting back from the end when negative.
+fn list_index(node: ASTNode, env: &Environment) -> Result<i64, EvalError> {
+    let span = node.span();
+    Ok(whole_number(evaluate_ast(node, env)?.number(span)?, span)? as i64)
+}
+
+fn whole_number(value: f64, span: Span) -> Result<f64, EvalError> {
+    if is_integer(value) {
+        Ok(value)
     } else {
-        Err(format!("Invalid input for {}", function_call.operation))
+        Err(EvalError::NotAnInteger { span })
     }
 }
 
+/// `evaluate_ast`, along with the innermost sub-expression that produced NaN from inputs
+/// that were not NaN when the result is NaN, so it can be traced back to the text that
+/// caused it.
+pub fn evaluate_tracing_nan(ast: ASTNode, env: &mut Environment) -> Result<(Value, Option<Span>), EvalError> {
+    env.take_nan();
+    let value = evaluate_ast(ast, env)?;
+    let nan = env.take_nan().filter(|_| matches!(value, Value::Number(a) if a.is_nan()));
+    Ok((value, nan))
+}
+
 #[cfg(test)]
-mod tests {
-    use crate::{ast::build_ast, eval::evaluate_ast, tokens::tokenize};
+pub(crate) mod tests {
+    use crate::{ast::{build_ast, build_statement, defined_function, Statement}, environment::{Environment, UserFunction, EVALUATION_STACK_SIZE, MAX_CALL_DEPTH, MAX_CALL_NESTING}, error::EvalError, eval::{evaluate_ast, evaluate_tracing_nan}};
+    use crate::{math::{factorial, AngleMode}, registry::{FunctionEntry, Registry}, tokens::{Span, tokenize, tokenize_with_functions}, value::{ComplexForm, NumberMode, Value}};
+    use num_complex::Complex64;
+
+    /// Evaluates `input` in `env`, with `env`'s registered and user-defined functions in scope.
+    pub(crate) fn evaluate(input: &str, env: &Environment) -> Result<Value, EvalError> {
+        let tokens = tokenize_with_functions(input.to_string(), env.registry(), &env.user_function_names()).unwrap();
+        evaluate_ast(build_ast(tokens).unwrap(), env)
+    }
+
+    /// `evaluate`, with the result as it's displayed.
+    pub(crate) fn show(input: &str, env: &Environment) -> Result<String, EvalError> {
+        evaluate(input, env).map(|a| a.to_string())
+    }
+
+    /// Defines the user function in `definition`, such as `f(x) = x^2`, in `env`.
+    pub(crate) fn define(env: &mut Environment, definition: &str) {
+        let mut functions = env.user_function_names();
+        functions.extend(defined_function(&tokenize(definition.to_string()).unwrap()));
+        let tokens = tokenize_with_functions(definition.to_string(), env.registry(), &functions).unwrap();
+        let Statement::FunctionDefinition { name, parameters, body, .. } = build_statement(tokens).unwrap() else { panic!() };
+        env.define(&name, UserFunction { parameters, body, text: definition.to_string() });
+    }
 
     #[test]
     fn factorial_parse(){
         let tokens = tokenize("factorial(8)".to_string()).unwrap();
         let ast = build_ast(tokens).unwrap();
-        let result = evaluate_ast(ast).unwrap();
-        assert_eq!(result, 40320.);
-    }   
-}
\ No newline at end of file
+        let result = evaluate_ast(ast, &Environment::new()).unwrap();
+        assert_eq!(result, Value::Number(40320.));
+    }
+
+    #[test]
+    fn errors_carry_spans(){
+        let env = Environment::new();
+        assert_eq!(evaluate("1 + sin(1, 2)", &env), Err(EvalError::ArityMismatch { name: "sin".to_string(), min: 1, max: Some(1), found: 2, span: Span::new(4, 13) }));
+        assert_eq!(evaluate("2 * factorial(-1)", &env), Err(EvalError::DomainError { name: "factorial".to_string(), span: Span::new(4, 17) }));
+        assert_eq!(evaluate("3 + x", &env), Err(EvalError::UndefinedVariable { name: "x".to_string(), span: Span::new(4, 5) }));
+        assert_eq!(evaluate("max()", &env), Err(EvalError::ArityMismatch { name: "max".to_string(), min: 1, max: None, found: 0, span: Span::new(0, 5) }));
+    }
+
+    #[test]
+    fn builtins_check_arity_and_domain(){
+        let env = Environment::new();
+        assert_eq!(evaluate("ln(1, 2)", &env), Err(EvalError::ArityMismatch { name: "ln".to_string(), min: 1, max: Some(1), found: 2, span: Span::new(0, 8) }));
+        assert_eq!(evaluate("average()", &env), Err(EvalError::ArityMismatch { name: "average".to_string(), min: 1, max: None, found: 0, span: Span::new(0, 9) }));
+        assert_eq!(evaluate("ln(0)", &env), Err(EvalError::DomainError { name: "ln".to_string(), span: Span::new(0, 5) }));
+        assert_eq!(evaluate("log2(0)", &env), Err(EvalError::DomainError { name: "log2".to_string(), span: Span::new(0, 7) }));
+        assert_eq!(evaluate("factorial(0) + 0!", &env), Ok(Value::Number(2.)));
+        assert_eq!(evaluate("asin(1)", &env), Ok(Value::Number(std::f64::consts::FRAC_PI_2)));
+        assert_eq!(evaluate("factorial(1000000)", &env), Ok(Value::Number(f64::INFINITY)));
+    }
+
+    #[test]
+    fn nan_is_traced_to_its_source(){
+        let mut env = Environment::new();
+        define(&mut env, "f(x) = x * 0/0");
+        env.set("x", Value::Number(1.));
+        let mut trace = |input: &str| {
+            let tokens = tokenize_with_functions(input.to_string(), env.registry(), &env.user_function_names()).unwrap();
+            evaluate_tracing_nan(build_ast(tokens).unwrap(), &mut env).map(|a| a.1)
+        };
+        assert_eq!(trace("1 + 2 * (0/0) - 4"), Ok(Some(Span::new(9, 12))));
+        assert_eq!(trace("3 + sin(inf)"), Ok(Some(Span::new(4, 12))));
+        assert_eq!(trace("x + f(2)"), Ok(Some(Span::new(4, 8))));
+        assert_eq!(trace("3 + 4"), Ok(None));
+        assert_eq!(trace("[0/0, 1][1]"), Ok(None));
+    }
+
+    #[test]
+    fn bitwise_in_float_mode(){
+        let env = Environment::new();
+        assert_eq!(evaluate("0xF0 | 0b1010 & 0o17", &env), Ok(Value::Number(250.)));
+        assert_eq!(evaluate("xor(5, 3) + shl(1, 4) + not(0)", &env), Ok(Value::Number(21.)));
+        assert_eq!(evaluate("1.5 & 1", &env), Err(EvalError::NotAnInteger { span: Span::new(0, 3) }));
+    }
+
+    #[test]
+    fn elementary_functions(){
+        let env = Environment::new();
+        assert_eq!(evaluate("sqrt(16) + exp(0) + log10(1000) + log2(8)", &env), Ok(Value::Number(11.)));
+        assert_eq!(evaluate("log(100) + log(27, 3)", &env), Ok(Value::Number(5.)));
+        assert_eq!(evaluate("asinh(sinh(1)) + acosh(cosh(2)) + atanh(tanh(0.5))", &env), Ok(Value::Number(3.5)));
+        assert_eq!(evaluate("floor(-2.5) + ceil(2.1) + sign(-3) + sign(0)", &env), Ok(Value::Number(-1.)));
+        assert_eq!(evaluate("round(2.5) + round(3.14159, 2) + round(1234, -2)", &env), Ok(Value::Number(1206.14)));
+        assert_eq!(evaluate("hypot(3, 4) + atan2(1, 0)", &env), Ok(Value::Number(5. + std::f64::consts::FRAC_PI_2)));
+        assert_eq!(evaluate("sqrt(-1)", &env), Ok(Value::Complex(Complex64::i())));
+        assert_eq!(evaluate("log(8, 1)", &env), Err(EvalError::DomainError { name: "log".to_string(), span: Span::new(0, 9) }));
+        assert_eq!(evaluate("atanh(1)", &env), Err(EvalError::DomainError { name: "atanh".to_string(), span: Span::new(0, 8) }));
+        assert_eq!(evaluate("round(1, 0.5)", &env), Err(EvalError::NotAnInteger { span: Span::new(0, 13) }));
+        assert_eq!(evaluate("atan2(1)", &env), Err(EvalError::ArityMismatch { name: "atan2".to_string(), min: 2, max: Some(2), found: 1, span: Span::new(0, 8) }));
+    }
+
+    #[test]
+    fn constants(){
+        let mut env = Environment::new();
+        assert_eq!(evaluate("2pi", &env), Ok(Value::Number(std::f64::consts::TAU)));
+        assert_eq!(evaluate("tau - 2π", &env), Ok(Value::Number(0.)));
+        assert_eq!(evaluate("ln(e) + 2e3", &env), Ok(Value::Number(2001.)));
+        assert_eq!(evaluate("phi^2 - phi - 1 < 1e-12 and -inf < 0", &env), Ok(Value::Boolean(true)));
+        assert_eq!(evaluate("phys.h phys.c / phys.k_B", &env), Ok(Value::Number(6.62607015e-34 * 299792458. / 1.380649e-23)));
+        assert_eq!(evaluate("2h", &env), Err(EvalError::UndefinedVariable { name: "h".to_string(), span: Span::new(1, 2) }));
+        env.set("e", Value::Number(3.));
+        assert_eq!(evaluate("2e", &env), Ok(Value::Number(6.)));
+    }
+
+    #[test]
+    fn unicode_math(){
+        let env = Environment::new();
+        assert_eq!(evaluate("6 × 2 ÷ 3 − 1", &env), Ok(Value::Number(3.)));
+        assert_eq!(evaluate("√16 + 2³", &env), Ok(Value::Number(12.)));
+        assert_eq!(evaluate("√3²", &env), Ok(Value::Number(3.)));
+        assert_eq!(evaluate("−2²", &env), Ok(Value::Number(-4.)));
+        assert_eq!(evaluate("10⁻¹", &env), Ok(Value::Number(0.1)));
+        assert_eq!(evaluate("2π", &env), Ok(Value::Number(std::f64::consts::TAU)));
+        assert_eq!(evaluate("2√9", &env), Ok(Value::Number(6.)));
+        assert_eq!(evaluate("1 + 1 ≤ 2", &env), Ok(Value::Boolean(true)));
+        assert_eq!(evaluate("1 ≥ 2", &env), Ok(Value::Boolean(false)));
+    }
+
+    #[test]
+    fn comparisons_and_booleans(){
+        let env = Environment::new();
+        assert_eq!(evaluate("1 < 2", &env), Ok(Value::Boolean(true)));
+        assert_eq!(evaluate("2 + 2 == 4 and 3 != 3", &env), Ok(Value::Boolean(false)));
+        assert_eq!(evaluate("1 > 2 or 2 >= 2", &env), Ok(Value::Boolean(true)));
+        assert_eq!(evaluate("not 1 > 2", &env), Ok(Value::Boolean(true)));
+        assert_eq!(evaluate("(1 < 2) == (3 < 4)", &env), Ok(Value::Boolean(true)));
+        assert_eq!(evaluate("xor(1 < 2, 3 < 4) or not(1 > 2)", &env), Ok(Value::Boolean(true)));
+        assert_eq!(evaluate("1 > 2 and x", &env), Ok(Value::Boolean(false)));
+        assert_eq!(evaluate("1 < 2 or x", &env), Ok(Value::Boolean(true)));
+        assert_eq!(evaluate("1 + (2 < 3)", &env), Err(EvalError::TypeMismatch { expected: "number", found: "boolean", span: Span::new(4, 11) }));
+        assert_eq!(evaluate("1 and 2 < 3", &env), Err(EvalError::TypeMismatch { expected: "boolean", found: "number", span: Span::new(0, 1) }));
+        assert_eq!(evaluate("1 == (1 < 2)", &env), Err(EvalError::TypeMismatch { expected: "number", found: "boolean", span: Span::new(5, 12) }));
+        assert_eq!(evaluate("1 < 2 < 3", &env), Err(EvalError::TypeMismatch { expected: "number", found: "boolean", span: Span::new(0, 5) }));
+    }
+
+    #[test]
+    fn angle_modes(){
+        let mut env = Environment::new();
+        assert_eq!(evaluate("sin(30deg) + cos(90deg) + tan(50grad)", &env), Ok(Value::Number(1.5)));
+        assert_eq!(evaluate("sin(π/2)", &env), Ok(Value::Number(1.)));
+        env.set_angle_mode(AngleMode::Degrees);
+        assert_eq!(evaluate("sin(30) + sec(60) + cot(45)", &env), Ok(Value::Number(3.5)));
+        assert_eq!(evaluate("asin(0.5) + acos(0) + atan2(1, 1)", &env), Ok(Value::Number(165.)));
+        assert_eq!(evaluate("π rad + 100grad", &env), Ok(Value::Number(270.)));
+        assert_eq!(evaluate("2 * 3deg^2", &env), Ok(Value::Number(18.)));
+        env.set_angle_mode(AngleMode::Gradians);
+        assert_eq!(evaluate("acos(-1)", &env), Ok(Value::Number(200.)));
+        assert_eq!(evaluate("deg", &env), Err(EvalError::UndefinedVariable { name: "deg".to_string(), span: Span::new(0, 3) }));
+    }
+
+    #[test]
+    fn postfix_operators(){
+        let env = Environment::new();
+        assert_eq!(evaluate("5!", &env), Ok(Value::Number(120.)));
+        assert_eq!(evaluate("7!!", &env), Ok(Value::Number(105.)));
+        assert_eq!(evaluate("8!! + 0!!", &env), Ok(Value::Number(385.)));
+        assert_eq!(evaluate("2^3!", &env), Ok(Value::Number(64.)));
+        assert_eq!(evaluate("3!^2", &env), Ok(Value::Number(36.)));
+        assert_eq!(evaluate("-3!", &env), Ok(Value::Number(-6.)));
+        assert_eq!(evaluate("(1 + 2)!", &env), Ok(Value::Number(6.)));
+        assert_eq!(evaluate("200 * 15%", &env), Ok(Value::Number(30.)));
+        assert_eq!(evaluate("50% - 1", &env), Ok(Value::Number(-0.5)));
+        assert_eq!(evaluate("7 % 3", &env), Ok(Value::Number(1.)));
+        assert_eq!(evaluate("7 % (2 + 2)", &env), Ok(Value::Number(3.)));
+        assert_eq!(evaluate("7 % -3 + 8 % +5", &env), Ok(Value::Number(4.)));
+        assert_eq!(evaluate("50% -1", &env), Ok(Value::Number(0.)));
+        assert_eq!(evaluate("50%- 1", &env), Ok(Value::Number(-0.5)));
+        assert_eq!(evaluate("(-2)!", &env), Err(EvalError::DomainError { name: "factorial".to_string(), span: Span::new(0, 5) }));
+        assert_eq!(evaluate("1 + (-1)!!", &env), Err(EvalError::DomainError { name: "double factorial".to_string(), span: Span::new(4, 10) }));
+    }
+
+    #[test]
+    fn exact_integers_and_gamma(){
+        let env = Environment::new();
+        let integer = |digits: &str| Ok(Value::Integer(digits.parse().unwrap()));
+        assert_eq!(evaluate("25!", &env), integer("15511210043330985984000000"));
+        assert_eq!(evaluate("factorial(25) + 1", &env), integer("15511210043330985984000001"));
+        assert_eq!(evaluate("200!/198!", &env), integer("39800"));
+        assert_eq!(evaluate("-(30!) / 7", &env), integer("-37893265687455865519472640000000"));
+        assert_eq!(evaluate("nCr(100, 50)", &env), integer("100891344545564193334812497256"));
+        assert_eq!(evaluate("choose(5, 7) + nPr(10, 3)", &env), integer("720"));
+        assert_eq!(evaluate("5! == 120 and 3! < 7", &env), Ok(Value::Boolean(true)));
+        assert_eq!(evaluate("1000!", &env).map(|a| a.to_string().len()), Ok(2568));
+        assert_eq!(evaluate("nCr(-1, 2)", &env), Err(EvalError::DomainError { name: "nCr".to_string(), span: Span::new(0, 10) }));
+        assert_eq!(evaluate("gamma(0)", &env), Err(EvalError::DomainError { name: "gamma".to_string(), span: Span::new(0, 8) }));
+        let close = |input: &str, expected: f64| {
+            let result = evaluate(input, &env).unwrap().number(Span::new(0, 0)).unwrap();
+            assert!((result - expected).abs() < 1e-12 * expected.abs(), "{} = {}, expected {}", input, result, expected);
+        };
+        close("gamma(0.5)^2", std::f64::consts::PI);
+        close("0.5!", std::f64::consts::PI.sqrt() / 2.);
+        close("gamma(-1.5)", 4. * std::f64::consts::PI.sqrt() / 3.);
+        close("gamma(6)", 120.);
+        close("lgamma(200)", 857.9336698258574);
+        close("nCr(4.5, 2)", 7.875);
+    }
+
+    #[test]
+    fn rational_mode(){
+        let mut env = Environment::new();
+        assert_eq!(show("0.1 + 0.2", &env), Ok("0.30000000000000004".to_string()));
+        env.set_number_mode(NumberMode::Rational);
+        assert_eq!(show("1/3 + 1/6", &env), Ok("1/2".to_string()));
+        assert_eq!(show("0.1 + 0.2", &env), Ok("3/10".to_string()));
+        assert_eq!(show("0.1 + 0.2 == 0.3", &env), Ok("true".to_string()));
+        assert_eq!(show("(2/3)^-2 - 7 % (3/2)", &env), Ok("5/4".to_string()));
+        assert_eq!(show("-(1/3)^3 * 50%", &env), Ok("-1/54".to_string()));
+        assert_eq!(show("sum(1/2, 1/3) + max(1/7, 1/5) + round(2/3, 2)", &env), Ok("511/300".to_string()));
+        assert_eq!(show("20!/3 + 0xFF", &env), Ok("810967336058880255".to_string()));
+        assert_eq!(show("1/3 < 0.3334 and 2^0.5 > 1.4", &env), Ok("true".to_string()));
+        assert_eq!(show("sin(0) + 1/4", &env), Ok("1/4".to_string()));
+        assert_eq!(show("ln(2)", &env), Ok("0.6931471805599453".to_string()));
+        assert_eq!(show("1/0", &env), Err(EvalError::DivisionByZero { span: Span::new(2, 3) }));
+        assert_eq!(show("(1/2) % (1 - 1)", &env), Err(EvalError::DivisionByZero { span: Span::new(8, 15) }));
+        assert_eq!(show("0^-1", &env), Err(EvalError::DivisionByZero { span: Span::new(2, 4) }));
+        assert_eq!(show("0^0 + 0/5", &env), Ok("1".to_string()));
+        assert_eq!(show("9007199254740993 + 0", &env), Ok("9007199254740993".to_string()));
+        assert_eq!(show("1.00000000000000000001 - 1", &env), Ok("1/100000000000000000000".to_string()));
+        assert_eq!(show("round(1/3, 1e12)", &env), Err(EvalError::DomainError { name: "round".to_string(), span: Span::new(0, 16) }));
+        assert!(evaluate("(1/3)^(10^12)", &env).unwrap().is_approximate());
+        assert!(evaluate("[ln(2), 1/3]", &env).unwrap().is_approximate());
+        assert!(!evaluate("(1/3)^2 + 2^100", &env).unwrap().is_approximate());
+        env.set_number_mode(NumberMode::Float);
+        assert_eq!(show("1/0", &env), Ok("inf".to_string()));
+    }
+
+    #[test]
+    fn complex_numbers(){
+        let mut env = Environment::new();
+        assert_eq!(show("(3 + 4i)(1 - 2i)", &env), Ok("11 - 2i".to_string()));
+        assert_eq!(show("i * i + sqrt(-4) + √-9", &env), Ok("-1 + 5i".to_string()));
+        assert_eq!(show("(-8)^(1/3)", &env), Ok("1 + 1.732050807568877i".to_string()));
+        assert_eq!(show("exp(i π) + (1 + i)^2 / i", &env), Ok("1".to_string()));
+        assert_eq!(show("abs(3 + 4i) + re(2 - i) + im(2 - i) + conj(i) - arg(-1)", &env), Ok("2.858407346410207 - i".to_string()));
+        assert_eq!(show("ln(-1)", &env), Ok("3.141592653589793i".to_string()));
+        assert_eq!(show("log10(-100) + log2(-8) + acosh(0.5)", &env), Ok("5 + 6.943934046865634i".to_string()));
+        assert_eq!(show("asin(2) + acos(-1.5)", &env), Ok("4.71238898038469 - 2.2793815470440233i".to_string()));
+        assert_eq!(show("ln(i) + sin(2i) / i", &env), Ok("3.626860407847019 + 1.5707963267948966i".to_string()));
+        assert_eq!(show("i < 1", &env), Err(EvalError::TypeMismatch { expected: "real number", found: "complex number", span: Span::new(0, 1) }));
+        env.set_angle_mode(AngleMode::Degrees);
+        assert_eq!(show("10∠90 + 2∠0 + arg(i)", &env), Ok("92 + 10i".to_string()));
+        assert_eq!(show("cos(i rad)", &env), Ok("1.5430806348152437".to_string()));
+        let value = evaluate("5∠-30 * 2∠120", &env).unwrap();
+        assert_eq!(value.format(ComplexForm::Polar, AngleMode::Degrees), "10∠90deg");
+        env.set_number_mode(NumberMode::Rational);
+        assert_eq!(show("(1/2 + i)^2", &env), Ok("-0.75 + i".to_string()));
+    }
+
+    #[test]
+    fn registered_functions() {
+        let mut env = Environment::new();
+        env.registry_mut().register(FunctionEntry::one_to_one("cube", "Custom", "cube(x) x cubed", |x| x * x * x));
+        assert_eq!(evaluate("cube(3) + sin(0)", &env), Ok(Value::Number(27.)));
+        assert_eq!(evaluate("cube(1, 2)", &env), Err(EvalError::ArityMismatch { name: "cube".to_string(), min: 1, max: Some(1), found: 2, span: Span::new(0, 10) }));
+        assert!(!Registry::builtin().contains("cube"));
+        assert!(evaluate("cube(3)", &Environment::new()).is_err());
+    }
+
+    #[test]
+    fn user_functions(){
+        let mut env = Environment::new();
+        define(&mut env, "f(x) = x^2 + 1");
+        define(&mut env, "hyp(a, b) = √(a² + b²)");
+        define(&mut env, "fact(n) = if(n <= 1, 1, n fact(n - 1))");
+        define(&mut env, "spin(n) = spin(n + 1)");
+        define(&mut env, &format!("nest(n) = if(n <= 0, 0, {}nest(n - 1){})", "(".repeat(20), ")".repeat(20)));
+        define(&mut env, "g(x) = x + y");
+        env.set("x", Value::Number(100.));
+        assert_eq!(evaluate("f(3)", &env), Ok(Value::Number(10.)));
+        assert_eq!(evaluate("2f(f(1)) + x", &env), Ok(Value::Number(110.)));
+        assert_eq!(evaluate("hyp(3, 4)", &env), Ok(Value::Number(5.)));
+        assert_eq!(evaluate("fact(10)", &env), Ok(Value::Number(3628800.)));
+        assert_eq!(evaluate("f(1, 2)", &env), Err(EvalError::ArityMismatch { name: "f".to_string(), min: 1, max: Some(1), found: 2, span: Span::new(0, 7) }));
+        assert_eq!(evaluate("g(1)", &env), Err(EvalError::InFunction { name: "g".to_string(),
+            source: Box::new(EvalError::UndefinedVariable { name: "y".to_string(), span: Span::new(11, 12) }), span: Span::new(0, 4) }));
+        assert_eq!(evaluate("if(1, 2, 3)", &env), Err(EvalError::TypeMismatch { expected: "boolean", found: "number", span: Span::new(3, 4) }));
+        let deep = std::thread::scope(|scope| {
+            let thread = std::thread::Builder::new().stack_size(EVALUATION_STACK_SIZE);
+            thread.spawn_scoped(scope, || (evaluate("fact(170)", &env), evaluate("1 + spin(0)", &env), evaluate("nest(100) + nest(300)", &env))).unwrap().join().unwrap()
+        });
+        assert_eq!(deep.0, Ok(Value::Number(factorial(170.))));
+        assert_eq!(deep.1, Err(EvalError::RecursionLimit { name: "spin".to_string(), limit: MAX_CALL_DEPTH, span: Span::new(4, 11) }));
+        assert_eq!(deep.2, Err(EvalError::NestingLimit { name: "nest".to_string(), limit: MAX_CALL_NESTING, span: Span::new(12, 21) }));
+    }
+}
@@ -1,8 +1,11 @@
 use std::collections::VecDeque;
 use std::fmt::{Display, Formatter};
-use crate::ast::{ASTNode, BinaryNode, BinaryOperation, build_ast, FunctionCall, UnaryNode, UnaryOperation};
-use crate::eval::{evaluate_ast, evaluate_function};
-use crate::tokens::Token;
+use crate::ast::{ASTNode, BinaryNode, build_ast, FunctionCall, Index, IndexNode, UnaryNode};
+use crate::environment::Environment;
+use crate::error::{Error, EvalError, ParseError};
+use crate::eval::evaluate_ast;
+use crate::tokens::{Literal, SpannedToken, Token};
+use crate::value::Value;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Point {
@@ -10,17 +13,27 @@ pub struct Point {
     y: f64,
 }
 
+/// A point with no real `y`, such as one outside the domain of the function graphed, has a
+/// `null` y so the plot leaves a gap there.
 impl Display for Point {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        return f.write_str(&format!("{{\"x\":{},\"y\":{}}}", self.x, self.y));
+        match self.y.is_finite() {
+            true => f.write_str(&format!("{{\"x\":{},\"y\":{}}}", self.x, self.y)),
+            false => f.write_str(&format!("{{\"x\":{},\"y\":null}}", self.x)),
+        }
     }
 }
 
-pub fn graph(mut tokens: VecDeque<Token>) -> Result<Vec<Point>, String> {
-    if *tokens.front().unwrap() == Token::Graph {
+/// Plots `y = expression` over `x`, with any other variables taken from `env`.
+pub fn graph(mut tokens: VecDeque<SpannedToken>, env: &Environment) -> Result<Vec<Point>, Error> {
+    let span = match (tokens.front(), tokens.back()) {
+        (Some(first), Some(last)) => first.span.to(last.span),
+        _ => return Err(ParseError::EmptyInput.into()),
+    };
+    if tokens[0] == Token::Graph {
         tokens.pop_front();
     }
-    if *tokens.back().unwrap() == Token::RightParen {
+    if tokens.back().is_some_and(|a| *a == Token::RightParen) {
         tokens.pop_back();
     }
     let mut left = VecDeque::new();
@@ -35,214 +48,38 @@ pub fn graph(mut tokens: VecDeque<Token>) -> Result<Vec<Point>, String> {
             left.push_back(token);
         }
     }
-    let left = build_ast(left);
-    let right = build_ast(right);
-    match (left, right) {
-        (Ok(left), Ok(right)) => {
-            let mut points = vec![];
-            let right_variables = get_variables(&right);
-            let left_variables = get_variables(&left);
-            if right_variables.contains(&"y".to_string()){
-                for i in -50..50 {
-                    let subbed_left = replace_variables(&left, &"x".to_string(), i as f64/5.0);
-                    match evaluate_ast(subbed_left){
-                        Ok(y) =>  points.push(Point { x: i as f64/5., y }),
-                        Err(e) => return Err(format!("Syntax Error: {}", e)),
-                    }
-                };
-            } else if left_variables.contains(&"y".to_string()){
-                for i in -50..50 {
-                    let subbed_right = replace_variables(&right, &"x".to_string(), i as f64/5.0);
-                    match evaluate_ast(subbed_right){
-                        Ok(y) => {
-                            points.push(Point { x: i as f64/5., y });
-                        },
-                        Err(e) => return Err(format!("Syntax Error: {}", e)),
-                    }
-                };
-            } else {
-                return Err("Syntax Error".to_string())
-            }
-            return Ok(points)
-        }
-        (_,_) => {
-            return Err("Syntax Error".to_owned())
-        }
+    if left.is_empty() || right.is_empty() {
+        return Err(ParseError::InvalidGraph { span }.into())
     }
-}
-
-fn solve_for_variable(mut left: ASTNode, mut right: ASTNode) -> f64 {
-    if get_variables(&left).len() > 0 && get_variables(&right).len() == 0 {
-        let mut right = evaluate_ast(right).unwrap();
-        loop {
-            match left.clone() {
-                ASTNode::BinaryNode(a) => {
-                    let left_node = reduce_ast(&a.left).unwrap();
-                    let right_node = reduce_ast(&a.right).unwrap();
-                    match (a.operation, left_node, right_node) {
-                        (BinaryOperation::Plus, ASTNode::NumberNode(a), node) => {
-                            left = node;
-                            right -= a;
-                        }
-                        (BinaryOperation::Plus, node, ASTNode::NumberNode(a)) => {
-                            left = node;
-                            right -= a;
-                        }
-                        (BinaryOperation::Minus, ASTNode::NumberNode(a), node) => {
-                            left = node;
-                            right = a - right;
-                        }
-                        (BinaryOperation::Minus, node, ASTNode::NumberNode(a)) => {
-                            left = node;
-                            right = right + a;
-                        }
-                        (BinaryOperation::Times, ASTNode::NumberNode(a), node) => {
-                            left = node;
-                            right = right/a;
-                        }
-                        (BinaryOperation::Times, node, ASTNode::NumberNode(a)) => {
-                            left = node;
-                            right = right/a;
-                        }
-                        (BinaryOperation::Divide, ASTNode::NumberNode(a), node) => {
-                            left = node;
-                            right = a/right;
-                        }
-                        (BinaryOperation::Divide, node, ASTNode::NumberNode(a)) => {
-                            left = node;
-                            right = a*right;
-                        }
-                        (BinaryOperation::Exponent, ASTNode::NumberNode(a), node) => {
-                            left = node;
-                            right = right.ln()/a.ln();
-                        }
-                        (BinaryOperation::Exponent, node, ASTNode::NumberNode(a)) => {
-                            left = node;
-                            right = right.powf(1.0/a);
-                        }
-                        _ => {
-                            panic!();
-                        }
-                    }
-                }
-                ASTNode::UnaryNode(a) => {
-                    match a.operation {
-                        UnaryOperation::Negate => {
-                            right = -1.0 * right;
-                            left = *a.child;
-                        }
-                        UnaryOperation::Parens => {
-                            left = *a.child;
-                        }
-                    }
-                }
-                ASTNode::NumberNode(_) => {
-                    panic!("am tired");
-                }
-                ASTNode::UnfinishedNode(_) => {
-                    panic!("this shouldn't happen");
-                }
-                ASTNode::FunctionCall(_) => {
-                    panic!("No functions allowed in graphing");
-                }
-                ASTNode::Comma => {
-                    panic!("bad");
-                }
-                ASTNode::Variable(_) => {
-                    return right;
-                }
-            }
-        }
+    let left = build_ast(left)?;
+    let right = build_ast(right)?;
+    let mut points = vec![];
+    let expression = if get_variables(&right).contains(&"y".to_string()) {
+        left
+    } else if get_variables(&left).contains(&"y".to_string()) {
+        right
     } else {
-        let mut left = evaluate_ast(left).unwrap();
-        loop {
-            match right.clone() {
-                ASTNode::BinaryNode(a) => {
-                    let left_node = reduce_ast(&a.left).unwrap();
-                    let right_node = reduce_ast(&a.right).unwrap();
-                    match (a.operation, left_node, right_node) {
-                        (BinaryOperation::Plus, ASTNode::NumberNode(a), node) => {
-                            right = node;
-                            left -= a;
-                        }
-                        (BinaryOperation::Plus, node, ASTNode::NumberNode(a)) => {
-                            right = node;
-                            left -= a;
-                        }
-                        (BinaryOperation::Minus, ASTNode::NumberNode(a), node) => {
-                            right = node;
-                            left = a - left;
-                        }
-                        (BinaryOperation::Minus, node, ASTNode::NumberNode(a)) => {
-                            right = node;
-                            left = left + a;
-                        }
-                        (BinaryOperation::Times, ASTNode::NumberNode(a), node) => {
-                            right = node;
-                            left = left/a;
-                        }
-                        (BinaryOperation::Times, node, ASTNode::NumberNode(a)) => {
-                            right = node;
-                            left = left/a;
-                        }
-                        (BinaryOperation::Divide, ASTNode::NumberNode(a), node) => {
-                            right = node;
-                            left = a/left;
-                        }
-                        (BinaryOperation::Divide, node, ASTNode::NumberNode(a)) => {
-                            right = node;
-                            left = a*left;
-                        }
-                        (BinaryOperation::Exponent, ASTNode::NumberNode(a), node) => {
-                            right = node;
-                            left = left.ln()/a.ln();
-                        }
-                        (BinaryOperation::Exponent, node, ASTNode::NumberNode(a)) => {
-                            right = node;
-                            left = left.powf(1.0/a);
-                        }
-                        _ => {
-                            panic!();
-                        }
-                    }
-                }
-                ASTNode::UnaryNode(a) => {
-                    match a.operation {
-                        UnaryOperation::Negate => {
-                            left = -1.0 * left;
-                            right = *a.child;
-                        }
-                        UnaryOperation::Parens => {
-                            right = *a.child;
-                        }
-                    }
-                }
-                ASTNode::NumberNode(_) => {
-                    panic!("am tired");
-                }
-                ASTNode::UnfinishedNode(_) => {
-                    panic!("this shouldn't happen");
-                }
-                ASTNode::FunctionCall(_) => {
-                    panic!("No functions allowed in graphing");
-                }
-                ASTNode::Comma => {
-                    panic!("bad");
-                }
-                ASTNode::Variable(_) => {
-                    return left;
-                }
-            }
-        }
+        return Err(ParseError::InvalidGraph { span }.into())
+    };
+    let expression_span = expression.span();
+    for i in -50..50 {
+        let subbed = replace_variables(&expression, "x", i as f64/5.0);
+        let y = match evaluate_ast(subbed, env) {
+            Ok(Value::Complex(_)) => f64::NAN,
+            Err(e) if matches!(e.cause(), EvalError::DomainError { .. }) => f64::NAN,
+            value => value?.number(expression_span)?,
+        };
+        points.push(Point { x: i as f64/5., y });
     }
+    Ok(points)
 }
 
 fn get_variables(node: &ASTNode) -> Vec<String> {
     let mut variables = vec![];
     match node {
         ASTNode::BinaryNode(a) => {
-            let left_vars = get_variables(&*a.left);
-            let right_vars = get_variables(&*a.right);
+            let left_vars = get_variables(&a.left);
+            let right_vars = get_variables(&a.right);
             for var in left_vars {
                 variables.push(var);
             }
@@ -251,172 +88,94 @@ fn get_variables(node: &ASTNode) -> Vec<String> {
             }
         }
         ASTNode::UnaryNode(a) => {
-            for var in get_variables(&*a.child) {
+            for var in get_variables(&a.child) {
                 variables.push(var);
             }
         }
-        ASTNode::NumberNode(_) => {}
-        ASTNode::UnfinishedNode(_) => {}
+        ASTNode::NumberNode(..) | ASTNode::IntegerNode(..) => {}
         ASTNode::FunctionCall(_) => {}
-        ASTNode::Comma => {}
-        ASTNode::Variable(a) => {
-            variables.push(a.clone());
-        }
-    }
-    return variables;
-}
-
-fn reduce_ast(node: &ASTNode) -> Result<ASTNode, String> {
-    match node {
-        ASTNode::BinaryNode(a) => {
-            let left = reduce_ast(&*a.left).unwrap();
-            let right = reduce_ast(&*a.right).unwrap();
-            match (left, right, &a.operation) {
-                (ASTNode::NumberNode(left), ASTNode::NumberNode(right), BinaryOperation::Plus) => {
-                    return Ok(ASTNode::NumberNode(left + right));
-                }
-                (ASTNode::NumberNode(left), ASTNode::NumberNode(right), BinaryOperation::Times) => {
-                    return Ok(ASTNode::NumberNode(left * right));
-                }
-                (ASTNode::NumberNode(left), ASTNode::NumberNode(right), BinaryOperation::Minus) => {
-                    return Ok(ASTNode::NumberNode(left - right));
-                }
-                (ASTNode::NumberNode(left), ASTNode::NumberNode(right), BinaryOperation::Divide) => {
-                    return Ok(ASTNode::NumberNode(left / right));
-                }
-                (ASTNode::NumberNode(left), ASTNode::NumberNode(right), BinaryOperation::Exponent) => {
-                    return Ok(ASTNode::NumberNode(left.powf(right)));
-                }
-                (ASTNode::NumberNode(left), ASTNode::NumberNode(right), BinaryOperation::Modulus) => {
-                    return Ok(ASTNode::NumberNode(left % right));
-                }
-                (left, right, b) => {
-                    return Ok(ASTNode::BinaryNode(BinaryNode {
-                        priority: a.priority,
-                        left: Box::new(left),
-                        right: Box::new(right),
-                        operation: b.clone(),
-                    }));
-                }
-            }
-        }
-        ASTNode::UnaryNode(a) => {
-            let child = reduce_ast(&*a.child).unwrap();
-            match (child, &a.operation) {
-                (ASTNode::NumberNode(a),UnaryOperation::Parens) => {
-                    return Ok(ASTNode::NumberNode(a));
-                }
-                (ASTNode::NumberNode(a),UnaryOperation::Negate) => {
-                    return Ok(ASTNode::NumberNode(-a));
-                }
-                (b, c) => {
-                    return Ok(ASTNode::UnaryNode(UnaryNode {
-                        priority: a.priority,
-                        child: Box::new(b),
-                        operation: c.clone(),
-                    }));
-                }
-            }
-        }
-        ASTNode::NumberNode(_) => {
-            return Ok(node.clone());
-        }
-        ASTNode::UnfinishedNode(_) | ASTNode::Comma => {
-            panic!();
-        }
-        ASTNode::FunctionCall(a) => {
-            let mut inputs = VecDeque::new();
-            let mut non_reducable = false;
-            for input in a.inputs.clone() {
-                let input = reduce_ast(&input).unwrap();
-                match input {
-                    ASTNode::NumberNode(_) => {}
-                    _ => {
-                        non_reducable = true;
-                    }
-                }
-                inputs.push_back(input);
-            }
-            if non_reducable {
-                return Ok(ASTNode::FunctionCall(FunctionCall {
-                    inputs,
-                    operation: a.operation.clone(),
-                }));
-            } else {
-                match evaluate_function(FunctionCall {
-                    inputs,
-                    operation: a.operation.clone(),
-                }) {
-                    Ok(a) => {
-                        return Ok(ASTNode::NumberNode(a));
-                    }
-                    Err(e) => {
-                        return Err(e);
-                    }
-                }
+        ASTNode::List(..) | ASTNode::Index(_) => {
+            for child in node.children() {
+                variables.extend(get_variables(child));
             }
         }
-        ASTNode::Variable(_) => {
-            return Ok(node.clone());
+        ASTNode::Variable(a, _) => {
+            variables.push(a.clone());
         }
     }
+    variables
 }
 
-fn replace_variables(node: &ASTNode, variable: &String, value: f64) -> ASTNode {
+fn replace_variables(node: &ASTNode, variable: &str, value: f64) -> ASTNode {
     match node {
         ASTNode::BinaryNode(a) => {
-            return ASTNode::BinaryNode(BinaryNode {
+            ASTNode::BinaryNode(BinaryNode {
                 priority: a.priority,
-                left: Box::new(replace_variables(&*a.left, variable, value)),
-                right: Box::new(replace_variables(&*a.right, variable, value)),
+                left: Box::new(replace_variables(&a.left, variable, value)),
+                right: Box::new(replace_variables(&a.right, variable, value)),
                 operation: a.operation.clone(),
-            });
+                span: a.span,
+            })
         }
         ASTNode::UnaryNode(a) => {
-            return ASTNode::UnaryNode(UnaryNode {
+            ASTNode::UnaryNode(UnaryNode {
                 priority: a.priority,
-                child: Box::new(replace_variables(&*a.child, variable, value)),
+                child: Box::new(replace_variables(&a.child, variable, value)),
                 operation: a.operation.clone(),
-            });
-        }
-        ASTNode::NumberNode(_a) => {
-            return node.clone();
+                span: a.span,
+            })
         }
-        ASTNode::UnfinishedNode(_a) => {
-            panic!();
+        ASTNode::NumberNode(..) | ASTNode::IntegerNode(..) => {
+            node.clone()
         }
         ASTNode::FunctionCall(a) => {
             let mut inputs = VecDeque::new();
             for input in a.inputs.clone() {
-                inputs.push_back(replace_variables(&input, &variable, value));
+                inputs.push_back(replace_variables(&input, variable, value));
             }
-            return ASTNode::FunctionCall(FunctionCall {
+            ASTNode::FunctionCall(FunctionCall {
                 inputs,
                 operation: a.operation.clone(),
+                span: a.span,
             })
         }
-        ASTNode::Comma => {
-            return node.clone();
-        }
-        ASTNode::Variable(a) => {
+        ASTNode::Variable(a, span) => {
             if *a == *variable {
-                return ASTNode::NumberNode(value);
+                ASTNode::NumberNode(Literal::from_f64(value).unwrap(), *span)
             } else {
-                return node.clone();
+                node.clone()
             }
         }
+        ASTNode::List(a, span) => {
+            ASTNode::List(a.iter().map(|a| replace_variables(a, variable, value)).collect(), *span)
+        }
+        ASTNode::Index(a) => {
+            let replace = |a: &ASTNode| Box::new(replace_variables(a, variable, value));
+            ASTNode::Index(IndexNode {
+                target: replace(&a.target),
+                index: match &a.index {
+                    Index::Element(index) => Index::Element(replace(index)),
+                    Index::Slice(start, end) => Index::Slice(start.as_deref().map(replace), end.as_deref().map(replace)),
+                },
+                span: a.span,
+            })
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::environment::Environment;
+    use crate::error::{Error, ParseError};
+    use crate::eval::tests::define;
     use crate::graph::{graph, Point};
-    use crate::tokens::tokenize;
+    use crate::tokens::{Span, tokenize, tokenize_with_functions};
+    use crate::value::Value;
 
     #[test]
     fn graph_of_linear_equation_works() {
         let input = tokenize("graph(y=5x)".to_string()).unwrap();
-        let output = graph(input);
+        let output = graph(input, &Environment::new());
         let mut expected_results = vec![];
         for i in -50..50 {
             expected_results.push(Point {
@@ -430,12 +189,12 @@ mod tests {
     #[test]
     fn graph_of_sin_works() {
         let input = tokenize("graph(y=sin(x))".to_string()).unwrap();
-        let output = graph(input);
+        let output = graph(input, &Environment::new());
         let mut expected_results = vec![];
         for i in -50..50 {
             expected_results.push(Point {
-                x: (i as f64).sin(),
-                y: i as f64,
+                x: i as f64 / 5.0,
+                y: (i as f64 / 5.0).sin(),
             });
         }
         assert_eq!(output, Ok(expected_results));
@@ -444,7 +203,7 @@ mod tests {
     #[test]
     fn graph_of_y_equals_4() {
         let input = tokenize("graph(y=4)".to_string()).unwrap();
-        let output = graph(input);
+        let output = graph(input, &Environment::new());
         let mut expected_results = vec![];
         for i in -50..50 {
             expected_results.push(Point {
@@ -454,4 +213,52 @@ mod tests {
         }
         assert_eq!(output, Ok(expected_results));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn graph_without_y_is_an_error() {
+        let input = tokenize("graph(x=5)".to_string()).unwrap();
+        assert_eq!(graph(input, &Environment::new()), Err(Error::Parse(ParseError::InvalidGraph { span: Span::new(0, 10) })));
+    }
+
+    #[test]
+    fn graph_uses_session_variables() {
+        let mut env = Environment::new();
+        env.set("a", Value::Number(2.));
+        env.set("x", Value::Number(100.));
+        let input = tokenize("graph(y=a x)".to_string()).unwrap();
+        let output = graph(input, &env).unwrap();
+        assert_eq!(output[0], Point { x: -10., y: -20. });
+    }
+
+    #[test]
+    fn graph_leaves_gaps_where_values_are_complex() {
+        let input = tokenize("graph(y=sqrt(x))".to_string()).unwrap();
+        let output = graph(input, &Environment::new()).unwrap();
+        assert!(output[..50].iter().all(|a| a.y.is_nan()));
+        assert_eq!(output[70], Point { x: 4., y: 2. });
+        assert_eq!(output[0].to_string(), "{\"x\":-10,\"y\":null}");
+    }
+
+    #[test]
+    fn graph_leaves_gaps_outside_the_domain() {
+        let plot = |input: &str| graph(tokenize(input.to_string()).unwrap(), &Environment::new()).unwrap();
+        for (function, at_two) in [("ln", 2f64.ln()), ("log10", 2f64.log10()), ("log2", 1.)] {
+            let output = plot(&format!("graph(y={}(x))", function));
+            assert!(output[..51].iter().all(|a| a.y.is_nan()), "{} of x <= 0", function);
+            assert_eq!(output[60], Point { x: 2., y: at_two });
+        }
+        let output = plot("graph(y=asin(x))");
+        assert!(output[..45].iter().chain(&output[56..]).all(|a| a.y.is_nan()));
+        assert_eq!(output[55], Point { x: 1., y: std::f64::consts::FRAC_PI_2 });
+    }
+
+    #[test]
+    fn graph_leaves_gaps_outside_the_domain_of_user_functions() {
+        let mut env = Environment::new();
+        define(&mut env, "f(x) = ln(x)");
+        let input = tokenize_with_functions("graph(y=f(x))".to_string(), env.registry(), &env.user_function_names()).unwrap();
+        let output = graph(input, &env).unwrap();
+        assert!(output[..51].iter().all(|a| a.y.is_nan()));
+        assert_eq!(output[60], Point { x: 2., y: 2f64.ln() });
+    }
+}
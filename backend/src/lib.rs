@@ -0,0 +1,15 @@
+pub mod ast;
+pub mod constants;
+pub mod decimal;
+pub mod environment;
+pub mod error;
+pub mod eval;
+pub mod graph;
+pub mod list;
+pub mod math;
+pub mod matrix;
+pub mod programmer;
+pub mod registry;
+pub mod statistics;
+pub mod tokens;
+pub mod value;
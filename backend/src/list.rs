@@ -0,0 +1,162 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use crate::error::EvalError;
+use crate::math::decimal_rational;
+use crate::tokens::Span;
+use crate::value::{NumberMode, Value};
+
+/// Most elements `range` builds.
+pub const MAX_LENGTH: usize = 1_000_000;
+
+/// `len(v)` the number of elements in `v`.
+pub fn len(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let elements = list(args.into_iter().next().unwrap(), span)?;
+    Ok(Value::from_rational(BigRational::from_integer(BigInt::from(elements.len())), mode))
+}
+
+/// `sort(v)` the elements of `v` from smallest to largest, compared exactly when they all
+/// are exact.
+pub fn sort(args: Vec<Value>, _: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let elements = list(args.into_iter().next().unwrap(), span)?;
+    let mut numbers = Vec::new();
+    for element in &elements {
+        numbers.push(element.clone().number(span)?);
+    }
+    let mut order: Vec<usize> = (0..elements.len()).collect();
+    match elements.iter().map(Value::rational).collect::<Option<Vec<BigRational>>>() {
+        Some(exact) => order.sort_by(|a, b| exact[*a].cmp(&exact[*b])),
+        None => order.sort_by(|a, b| numbers[*a].total_cmp(&numbers[*b])),
+    }
+    Ok(Value::List(order.into_iter().map(|a| elements[a].clone()).collect()))
+}
+
+/// `range(stop)`, `range(start, stop)` or `range(start, stop, step)`: start, start + step, ...
+/// up to but not including stop, as in Python. The steps are exact, so `range(0, 1, 0.1)`
+/// ends at 0.9 rather than 0.9000000000000001.
+pub fn range(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let mut bounds = Vec::new();
+    for arg in args {
+        let number = arg.clone().number(span)?;
+        let exact = match arg {
+            Value::Number(_) => decimal_rational(number),
+            arg => arg.rational(),
+        };
+        bounds.push(exact.ok_or_else(|| EvalError::DomainError { name: "range".to_string(), span })?);
+    }
+    let one = BigRational::from_integer(BigInt::from(1));
+    let (start, stop, step) = match bounds.len() {
+        1 => (BigRational::zero(), bounds.remove(0), one),
+        2 => (bounds.remove(0), bounds.remove(0), one),
+        _ => (bounds.remove(0), bounds.remove(0), bounds.remove(0)),
+    };
+    if step.is_zero() {
+        return Err(EvalError::DomainError { name: "range".to_string(), span });
+    }
+    let count = ((&stop - &start) / &step).ceil().to_integer();
+    let count = if count.is_negative() { 0 } else { count.to_usize().unwrap_or(usize::MAX) };
+    if count > MAX_LENGTH {
+        return Err(EvalError::DomainError { name: "range".to_string(), span });
+    }
+    Ok(Value::List((0..count).map(|k| Value::from_rational(&start + &step * BigInt::from(k), mode)).collect()))
+}
+
+/// Appends the numbers in `value` to `into`, taking them out of lists, lists of lists and
+/// matrices.
+pub fn flatten(value: Value, into: &mut Vec<Value>) {
+    match value {
+        Value::List(elements) => elements.into_iter().for_each(|a| flatten(a, into)),
+        Value::Matrix(rows) => into.extend(rows.into_iter().flatten()),
+        value => into.push(value),
+    }
+}
+
+/// Unwraps a list, or a matrix as the list of its rows, reporting a type error at `span` for
+/// anything else.
+pub fn list(value: Value, span: Span) -> Result<Vec<Value>, EvalError> {
+    match value {
+        Value::List(elements) => Ok(elements),
+        Value::Matrix(rows) => Ok(rows.into_iter().map(Value::List).collect()),
+        value => Err(EvalError::TypeMismatch { expected: "list", found: value.type_name(), span }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::environment::Environment;
+    use crate::error::EvalError;
+    use crate::eval::tests::{define, show};
+    use crate::list::{range, sort};
+    use crate::tokens::Span;
+    use crate::value::{NumberMode, Value};
+
+    #[test]
+    fn ranges_step_exactly() {
+        let span = Span::new(0, 1);
+        let numbers = |a: &[f64]| Value::List(a.iter().map(|a| Value::Number(*a)).collect());
+        assert_eq!(range(vec![Value::Number(4.)], NumberMode::Float, span), Ok(numbers(&[0., 1., 2., 3.])));
+        assert_eq!(range(vec![Value::Number(0.), Value::Number(0.5), Value::Number(0.1)], NumberMode::Float, span), Ok(numbers(&[0., 0.1, 0.2, 0.3, 0.4])));
+        assert_eq!(range(vec![Value::Number(5.), Value::Number(0.), Value::Number(-2.)], NumberMode::Float, span), Ok(numbers(&[5., 3., 1.])));
+        assert_eq!(range(vec![Value::Number(3.), Value::Number(1.)], NumberMode::Float, span), Ok(numbers(&[])));
+        assert_eq!(range(vec![Value::Number(0.), Value::Number(1.), Value::Number(0.)], NumberMode::Float, span), Err(EvalError::DomainError { name: "range".to_string(), span }));
+        assert_eq!(range(vec![Value::Number(1e7)], NumberMode::Float, span), Err(EvalError::DomainError { name: "range".to_string(), span }));
+    }
+
+    #[test]
+    fn sorting_needs_real_numbers() {
+        let span = Span::new(0, 1);
+        let list = Value::List(vec![Value::Number(3.), Value::Number(f64::NAN), Value::Number(-1.)]);
+        assert_eq!(sort(vec![list], NumberMode::Float, span).unwrap().to_string(), "[-1, 3, NaN]");
+        let list = Value::List(vec![Value::Number(1.), Value::Boolean(true)]);
+        assert_eq!(sort(vec![list], NumberMode::Float, span), Err(EvalError::TypeMismatch { expected: "number", found: "boolean", span }));
+    }
+
+    #[test]
+    fn lists_broadcast_arithmetic_and_functions() {
+        let mut env = Environment::new();
+        env.set("v", Value::List(vec![Value::Number(3.), Value::Number(1.), Value::Number(2.)]));
+        assert_eq!(show("v * 2 + [10, 20, 30]", &env), Ok("[16, 22, 34]".to_string()));
+        assert_eq!(show("1 / [1, 2, 4] - v^2", &env), Ok("[-8, -0.5, -3.75]".to_string()));
+        assert_eq!(show("-v + sqrt([4, 9, 16]) + [1, 2, 3]!", &env), Ok("[0, 4, 8]".to_string()));
+        assert_eq!(show("v > 1", &env), Ok("[true, false, true]".to_string()));
+        assert_eq!(show("v == [3, 1, 2]", &env), Ok("true".to_string()));
+        assert_eq!(show("sum(v) + avg(v, 8) + std([1, 3]) + median(v) + max(v, [7]) + min(v)", &env), Ok("20.5".to_string()));
+        assert_eq!(show("round([1.234, 5.678], 1) + abs([-1, 1])", &env), Ok("[2.2, 6.7]".to_string()));
+        assert_eq!(show("map(sqrt, [4, 9]) + map(floor, [1.5, 2.5])", &env), Ok("[3, 5]".to_string()));
+        define(&mut env, "f(x) = x^2 + 1");
+        assert_eq!(show("map(f, range(4))", &env), Ok("[1, 2, 5, 10]".to_string()));
+        assert_eq!(show("f([1, 2]) - 1", &env), Ok("[1, 4]".to_string()));
+    }
+
+    #[test]
+    fn lists_index_and_slice() {
+        let mut env = Environment::new();
+        env.set("v", Value::List(vec![Value::Number(3.), Value::Number(1.), Value::Number(2.)]));
+        assert_eq!(show("v[0] + v[-1] + [[1, 2], [3, 4]][1][0]", &env), Ok("8".to_string()));
+        assert_eq!(show("v[1:] + v[:-1]", &env), Ok("[4, 3]".to_string()));
+        assert_eq!(show("v[5:] + v[2:1] == []", &env), Ok("true".to_string()));
+        assert_eq!(show("len(v) + len([])", &env), Ok("3".to_string()));
+        assert_eq!(show("sort(v)", &env), Ok("[1, 2, 3]".to_string()));
+        assert_eq!(show("range(1, 2, 0.25)", &env), Ok("[1, 1.25, 1.5, 1.75]".to_string()));
+    }
+
+    #[test]
+    fn list_errors() {
+        let mut env = Environment::new();
+        env.set("v", Value::List(vec![Value::Number(3.), Value::Number(1.), Value::Number(2.)]));
+        assert_eq!(show("v[3]", &env), Err(EvalError::IndexOutOfRange { index: 3, length: 3, span: Span::new(2, 3) }));
+        assert_eq!(show("v[0.5]", &env), Err(EvalError::NotAnInteger { span: Span::new(2, 5) }));
+        assert_eq!(show("v + [1, 2]", &env), Err(EvalError::LengthMismatch { left: 3, right: 2, span: Span::new(4, 10) }));
+        assert_eq!(show("len(5)", &env), Err(EvalError::TypeMismatch { expected: "list", found: "number", span: Span::new(0, 6) }));
+        assert_eq!(show("5[0]", &env), Err(EvalError::TypeMismatch { expected: "list", found: "number", span: Span::new(0, 1) }));
+        assert_eq!(show("map(g, v)", &env), Err(EvalError::UnknownFunction { name: "g".to_string(), span: Span::new(4, 5) }));
+    }
+
+    #[test]
+    fn lists_stay_exact_in_rational_mode() {
+        let mut env = Environment::new();
+        env.set_number_mode(NumberMode::Rational);
+        assert_eq!(show("[1, 2] / 3 + range(0, 1, 1/3)[1]", &env), Ok("[2/3, 1]".to_string()));
+        assert_eq!(show("sum([1/2, 1/3])", &env), Ok("5/6".to_string()));
+    }
+}
@@ -1,32 +1,145 @@
-mod tokens;
-mod ast;
-mod eval;
-mod math;
-mod graph;
-
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 use actix_cors::Cors;
 use actix_web::{web, App, HttpResponse, HttpServer, Responder, Error};
 use actix_web::web::Json;
+use num_bigint::BigInt;
 use serde::{Deserialize, Serialize};
-use crate::ast::build_ast;
-use crate::eval::evaluate_ast;
-use crate::graph::graph;
-use crate::tokens::{Token, tokenize};
+use backend::ast::{build_statement, defined_function, Statement};
+use backend::constants::{self, constant, IMAGINARY_UNIT};
+use backend::decimal::{DEFAULT_PRECISION, MAX_PRECISION};
+use backend::environment::{Environment, UserFunction, EVALUATION_STACK_SIZE};
+use backend::error;
+use backend::error::{point_at, EvalError, ParseError};
+use backend::eval::evaluate_tracing_nan;
+use backend::graph::graph;
+use backend::math::AngleMode;
+use backend::programmer::{evaluate_integer, format_integer, IntWidth};
+use backend::tokens::{Token, tokenize_with_functions};
+use backend::value::{ComplexForm, NumberMode, Value};
+
+/// Syntax help. The functions are listed after it from the session's registry.
+const HELP: &str = "Help\nMath Input: 3+4(2 + 9)*(-1)^3\nUnicode: 6 × 2 ÷ 3 − 1 2π √x x² x⁻¹ a ≤ b a ≥ b\nLogic: a < b a > b a <= b a >= b a == b a != b\nnot a a and b a or b\nPostfix: n! n!! 200 * 15%\nAngles: deg, rad or grad sets the mode for trig, 30deg 1rad 100grad convert\nComplex: 3 + 4i √-4 10∠30deg, polar or rect sets how results are shown\nLists: v = [3, 1, 2] then v * 2, v[0], v[-1], v[1:], sum(v), map(sqrt, v)\nMatrices: A = [[1, 2], [3, 4]] then A * A, A^-1, A * [1, 1], det(A), inv(A), solve(A, [5, 11])\nDecompositions: eig(A), svd(A), qr(A), lu(A) and cholesky(A) give lists such as [values, vectors], take parts with eig(A)[0]\nBitwise: 0xFF 0b1010 0o17 a & b a | b ~a a << n a >> n\nGraph must have y on left and x on right\nVariables: a = 5 then 3a + 1, vars lists them, clear removes them\nFunctions: f(x) = x^2 + 1 then f(3) or graph(y=f(x))\nfact(n) = if(n <= 1, 1, n fact(n - 1))";
 
 #[derive(Serialize, Debug)]
 struct ResponseData {
     message: String,
+    /// The numeric mode that produced `message`: `float`, `exact`, `decimal` or `programmer`.
+    mode: &'static str,
+    /// Significant digits in decimal mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    precision: Option<u64>,
+    /// A list or matrix result as JSON arrays, so clients can take apart results such as
+    /// `eig(A)` without parsing `message`. See `structured`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<serde_json::Value>,
+    /// In exact and decimal mode, set when part of the result is floating point, see
+    /// `Value::is_approximate`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    approximate: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ErrorData>,
+}
+
+/// The reply to one line: `message` to show, the structured `result`, if any, and whether an
+/// exact mode result is `approximate`.
+struct Reply {
+    message: String,
+    result: Option<serde_json::Value>,
+    approximate: bool,
+}
+
+impl From<String> for Reply {
+    fn from(message: String) -> Reply {
+        Reply { message, result: None, approximate: false }
+    }
+}
+
+/// Machine readable error details so the frontend can highlight `text[start..end]`.
+#[derive(Serialize, Debug)]
+struct ErrorData {
+    kind: &'static str,
+    start: usize,
+    end: usize,
+    message: String,
 }
 
 #[derive(Deserialize)]
 struct Request {
     text: String,
+    /// `"programmer"` switches to exact integer evaluation, `"exact"` to exact fractions,
+    /// `"decimal"` to arbitrary precision decimals, anything else is the default floating
+    /// point mode.
+    #[serde(default)]
+    mode: Option<String>,
+    /// Decimal mode significant digits, 1 to 1000, defaults to 34.
+    #[serde(default)]
+    precision: Option<u64>,
+    /// Programmer mode integer width such as `u8` or `i32`, defaults to `i64`.
+    #[serde(default)]
+    width: Option<String>,
+    /// Programmer mode output base, 2 to 36.
+    #[serde(default)]
+    base: Option<u32>,
+    /// Client chosen id whose variables persist between requests. Without one,
+    /// assignments only last for the request.
+    #[serde(default)]
+    session: Option<String>,
+    /// `rad`, `deg` or `grad` for this request only, otherwise the session's angle mode.
+    #[serde(default)]
+    angle: Option<String>,
+    /// `rect` or `polar` for this request only, otherwise the session's complex form.
+    #[serde(default)]
+    form: Option<String>,
+}
+
+/// How long a session's variables are kept after its last request.
+const SESSION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Most sessions kept at once. Past this the least recently used one is dropped.
+const MAX_SESSIONS: usize = 10_000;
+
+/// One session's variables, locked on their own so requests in different sessions evaluate
+/// at the same time.
+struct Session {
+    env: Arc<Mutex<Environment<'static>>>,
+    used: Instant,
+}
+
+/// Variables for each session id, see `Request::session`.
+#[derive(Default)]
+struct Sessions(Mutex<HashMap<String, Session>>);
+
+impl Sessions {
+    /// The variables for `id`, new if it has none. Only finding the session holds the lock
+    /// over every session. Starting one drops those idle longer than `SESSION_TTL` and, if
+    /// there are still `MAX_SESSIONS`, the least recently used.
+    fn get(&self, id: &str) -> Arc<Mutex<Environment<'static>>> {
+        let mut sessions = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = Instant::now();
+        if let Some(session) = sessions.get_mut(id) {
+            session.used = now;
+            return session.env.clone();
+        }
+        sessions.retain(|_, a| now.duration_since(a.used) < SESSION_TTL);
+        if sessions.len() >= MAX_SESSIONS {
+            if let Some(oldest) = sessions.iter().min_by_key(|(_, a)| a.used).map(|(id, _)| id.clone()) {
+                sessions.remove(&oldest);
+            }
+        }
+        let env = Arc::new(Mutex::new(Environment::new()));
+        sessions.insert(id.to_string(), Session { env: env.clone(), used: now });
+        env
+    }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    HttpServer::new(|| {
+    let sessions = web::Data::new(Sessions::default());
+    HttpServer::new(move || {
         App::new()
+            .app_data(sessions.clone())
             .wrap(
                 Cors::default()
                     .allow_any_origin()
@@ -40,7 +153,7 @@ async fn main() -> std::io::Result<()> {
             )
             .route(
                 "/calculate",
-                web::post().to(|payload: Json<Request>| async move { receive_string(payload) }),
+                web::post().to(receive_string),
             )
     })
     .bind("127.0.0.1:8080")?
@@ -48,57 +161,222 @@ async fn main() -> std::io::Result<()> {
     .await
 }
 
-fn receive_string(payload: Json<Request>) -> Result<impl Responder, Error> {
-    println!("Received string: {}", payload.0.text);
-    let tokens = tokenize(payload.0.text.clone());
-    match tokens{
-        Ok(mut tokens) => {
-            if tokens.len() == 1 && tokens[0] == Token::Help {
-                println!("Help\nSupports Math Input: 3+4(2 + 9)*(-1)^3\nTrigonometry: sin(t) cos(t) tan(t) asin(t) acos(t) atan(t) sec(t) csc(t) cot(n)\nStatistics: sum(n1, n2) avg(n1, n2) std(n1, n2)\nGraphing: graph(y=x^3)\nGraph must have y on left and x on right Other: ln(t) factorial(n)");
-                return Ok(web::Json(ResponseData{message: "Help\nMath Input: 3+4(2 + 9)*(-1)^3\nTrigonometry: sin(t) cos(t) tan(t) asin(t) acos(t) atan(t) sec(t)\ncsc(t) cot(n)\nStatistics: median(n1, n2) sum(n1, n2) avg(n1, n2)\nstd(n1, n2) max(n1, n2) min(n1, n2)\nGraph must have y on left and x on right\nOther: ln(t) factorial(n)".to_string()}));
-            } else if tokens.len() > 0 && tokens[0] == Token::Graph {
-                tokens.pop_front();
-                tokens.pop_back();
-                let points = graph(tokens);
-                match points{
-                    Ok(points) => {
-                        let data_points = points.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(",");
-                        return Ok(web::Json(ResponseData {
-                            message: data_points,
-                        }));
-                    },
-                    Err(e) => {
-                        return Ok(web::Json(ResponseData{message: payload.0.text + ": " + &e}));
-                    },
-                }
-            }
-            let ast = build_ast(tokens);
-            match ast {
-                Ok(ast) => {
-                    let val = evaluate_ast(ast);
-                    match val {
-                        Ok(val) => {
-                            println!("Responding with: {}", val);
-                            Ok(web::Json(ResponseData{message: (payload.0.text +  " = " + val.to_string().as_str()).to_string()}))
-                        },
-                        Err(e) => {
-                            println!("Failed Eval: {}", e);
-                            println!("Responding with: {}", e);
-                            Ok(web::Json(ResponseData{message: payload.0.text + ": " + &e}))
-                        },
-                    }
-                },
-                Err(e) => {
-                    println!("Failed Build AST: {}", e);
-                    println!("Responding with: {}", e);
-                Ok(web::Json(ResponseData{message: e}))
-                }, 
-            }
-        },
+/// Evaluates on the blocking thread pool so a slow calculation holds up neither the server's
+/// workers nor other sessions.
+async fn receive_string(payload: Json<Request>, sessions: web::Data<Sessions>) -> Result<impl Responder, Error> {
+    let request = payload.into_inner();
+    let text = request.text.clone();
+    println!("Received string: {}", text);
+    let session = request.session.as_deref().map(|id| sessions.get(id));
+    let (request, result) = web::block(move || {
+        let mut scratch = Environment::new();
+        let mut locked = session.as_ref().map(|a| a.lock().unwrap_or_else(PoisonError::into_inner));
+        let env = match &mut locked {
+            Some(env) => &mut **env,
+            None => &mut scratch,
+        };
+        let result = std::thread::scope(|scope| {
+            let thread = std::thread::Builder::new().stack_size(EVALUATION_STACK_SIZE);
+            thread.spawn_scoped(scope, || calculate(&request, env))?.join().map_err(|_| std::io::Error::other("evaluation panicked"))
+        })?;
+        Ok::<_, std::io::Error>((request, result))
+    }).await??;
+    let (message, result, approximate, error) = match result {
+        Ok(Reply { message, result, approximate }) => {
+            println!("Responding with: {}", message);
+            (message, result, approximate, None)
+        }
         Err(e) => {
-            println!("Failed Tokenize: {}", e);
-            println!("Responding with: {}", e);
-            Ok(web::Json(ResponseData{message: e}))
+            println!("Failed: {}", e);
+            let (message, error) = error_response(&text, e);
+            (message, None, false, error)
+        }
+    };
+    let (mode, precision) = match number_mode(&request) {
+        _ if request.mode.as_deref() == Some("programmer") => ("programmer", None),
+        NumberMode::Float => ("float", None),
+        NumberMode::Rational => ("exact", None),
+        NumberMode::Decimal { precision } => ("decimal", Some(precision)),
+    };
+    Ok(web::Json(ResponseData { message, mode, precision, result, approximate, error }))
+}
+
+fn number_mode(request: &Request) -> NumberMode {
+    match request.mode.as_deref() {
+        Some("exact") => NumberMode::Rational,
+        Some("decimal") => NumberMode::Decimal { precision: request.precision.unwrap_or(DEFAULT_PRECISION) },
+        _ => NumberMode::Float,
+    }
+}
+
+/// Handles one line of input in the request's number mode, and its angle mode and complex
+/// form if it has them.
+fn calculate(request: &Request, env: &mut Environment) -> Result<Reply, error::Error> {
+    let mode = number_mode(request);
+    if let NumberMode::Decimal { precision } = mode {
+        if !(1..=MAX_PRECISION).contains(&precision) {
+            return Ok(format!("Precision must be between 1 and {} digits, got {}", MAX_PRECISION, precision).into());
+        }
+    }
+    env.set_number_mode(mode);
+    let angle = match request.angle.as_deref() {
+        Some(name) => match AngleMode::parse(name) {
+            Some(angle) => angle,
+            None => return Ok(format!("Unknown angle mode {}, use rad, deg or grad", name).into()),
         },
+        None => env.angle_mode(),
+    };
+    let form = match request.form.as_deref() {
+        Some(name) => match ComplexForm::parse(name) {
+            Some(form) => form,
+            None => return Ok(format!("Unknown complex form {}, use rect or polar", name).into()),
+        },
+        None => env.complex_form(),
+    };
+    let (session_angle, session_form) = (env.angle_mode(), env.complex_form());
+    env.set_angle_mode(angle);
+    env.set_complex_form(form);
+    let result = calculate_line(request, env);
+    if request.angle.is_some() {
+        env.set_angle_mode(session_angle);
+    }
+    if request.form.is_some() {
+        env.set_complex_form(session_form);
+    }
+    result
+}
+
+/// A lone `vars` lists the session's variables and functions, a lone `clear` removes them,
+/// a lone `deg`, `rad` or `grad` sets the session's angle mode and a lone `rect` or `polar`
+/// sets how it shows complex numbers.
+fn calculate_line(request: &Request, env: &mut Environment) -> Result<Reply, error::Error> {
+    let text = request.text.as_str();
+    let mut functions = env.user_function_names();
+    let mut tokens = tokenize_with_functions(text.to_string(), env.registry(), &functions)?;
+    if let Some(name) = defined_function(&tokens).filter(|a| !functions.contains(a) && !env.registry().contains(a)) {
+        functions.insert(name);
+        tokens = tokenize_with_functions(text.to_string(), env.registry(), &functions)?;
+    }
+    if tokens.len() == 1 {
+        match &tokens[0].token {
+            Token::Help => return Ok(format!("{}\n{}\n{}", HELP, env.registry().help(), constants::help()).into()),
+            Token::Variable(a) if a == "vars" => return Ok(list_variables(env).into()),
+            Token::Variable(a) if a == "clear" => return Ok(format!("Cleared {} definition(s)", env.clear()).into()),
+            Token::Variable(a) if AngleMode::parse(a).is_some() => {
+                env.set_angle_mode(AngleMode::parse(a).unwrap());
+                return Ok(format!("Angle mode: {}", env.angle_mode()).into());
+            }
+            Token::Variable(a) if ComplexForm::parse(a).is_some() => {
+                env.set_complex_form(ComplexForm::parse(a).unwrap());
+                return Ok(format!("Complex form: {}", env.complex_form()).into());
+            }
+            _ => {}
+        }
+    }
+    if tokens[0] == Token::Graph {
+        // Points are plotted as f64, so there is nothing for exact or decimal mode to keep.
+        env.set_number_mode(NumberMode::Float);
+        let points = graph(tokens, env)?;
+        return Ok(points.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(",").into());
+    }
+    let (name, ast) = match build_statement(tokens)? {
+        Statement::Expression(ast) => (None, ast),
+        Statement::Assignment { name, value, .. } => (Some(name), value),
+        Statement::FunctionDefinition { name, span, .. } if env.registry().contains(&name) => {
+            return Err(EvalError::ReservedName { name, span }.into());
+        }
+        Statement::FunctionDefinition { name, parameters, body, .. } => {
+            let span = body.span();
+            let function = UserFunction { parameters, body, text: text[span.start..span.end].to_string() };
+            let message = format!("{}({}) = {}", name, function.parameters.join(", "), function.text);
+            env.define(&name, function);
+            return Ok(message.into());
+        }
+    };
+    let warning = name.as_deref().map(|a| shadow_warning(a, env)).unwrap_or_default();
+    if request.mode.as_deref() == Some("programmer") {
+        let width = match &request.width {
+            Some(name) => match IntWidth::parse(name) {
+                Some(width) => width,
+                None => return Ok(format!("Unknown integer width {}, use i8 to i64 or u8 to u64", name).into()),
+            },
+            None => IntWidth::default(),
+        };
+        let base = request.base.unwrap_or(10);
+        if !(2..=36).contains(&base) {
+            return Ok(format!("Base must be between 2 and 36, got {}", base).into());
+        }
+        let val = evaluate_integer(ast, width, env)?;
+        let formatted = format!("{} ({})", format_integer(val, width, base), width);
+        return Ok(match name {
+            Some(name) => {
+                env.set(&name, Value::Integer(BigInt::from(val)));
+                format!("{} = {}{}", name, formatted, warning)
+            }
+            None => format!("{} = {}", text, formatted),
+        }.into());
+    }
+    let (val, nan) = evaluate_tracing_nan(ast, env)?;
+    if let Some(name) = &name {
+        env.set(name, val.clone());
     }
-}
\ No newline at end of file
+    let shown = val.format(env.complex_form(), env.angle_mode());
+    if let Some(span) = nan {
+        return Ok(point_at(&format!("{} = {}", text, shown), span).into());
+    }
+    let result = matches!(val, Value::List(_) | Value::Matrix(_)).then(|| structured(&val, env));
+    let approximate = env.number_mode() != NumberMode::Float && val.is_approximate();
+    let note = if approximate { "\nNote: not exact, part of this result is floating point" } else { "" };
+    let message = match name {
+        Some(name) => format!("{} = {}{}{}", name, shown, note, warning),
+        None => format!("{} = {}{}", text, shown, note),
+    };
+    Ok(Reply { message, result, approximate })
+}
+
+/// `value` as JSON: lists and matrices as arrays, finite real numbers as numbers and
+/// booleans as booleans. Anything else is a string as shown in the message, so exact
+/// fractions stay exact.
+fn structured(value: &Value, env: &Environment) -> serde_json::Value {
+    match value {
+        Value::List(elements) => elements.iter().map(|a| structured(a, env)).collect(),
+        Value::Matrix(rows) => rows.iter().map(|row| row.iter().map(|a| structured(a, env)).collect::<serde_json::Value>()).collect(),
+        Value::Number(a) if a.is_finite() => serde_json::Value::from(*a),
+        Value::Boolean(a) => serde_json::Value::from(*a),
+        a => serde_json::Value::from(a.format(env.complex_form(), env.angle_mode())),
+    }
+}
+
+/// A note for the first assignment that hides a built-in constant or `i`, which the
+/// variable replaces until `clear`.
+fn shadow_warning(name: &str, env: &Environment) -> String {
+    let description = match constant(name) {
+        Some(constant) => constant.description,
+        None if name == IMAGINARY_UNIT => "the imaginary unit",
+        None => return String::new(),
+    };
+    match env.get(name) {
+        None => format!("\nWarning: {} now hides the built-in constant ({}) until clear", name, description),
+        Some(_) => String::new(),
+    }
+}
+
+fn list_variables(env: &Environment) -> String {
+    let functions = env.functions().into_iter().map(|(name, function)| format!("{}({}) = {}", name, function.parameters.join(", "), function.text));
+    let variables = env.variables().into_iter().map(|(name, value)| format!("{} = {}", name, value.format(env.complex_form(), env.angle_mode())));
+    let lines: Vec<String> = functions.chain(variables).collect();
+    if lines.is_empty() {
+        return "No variables or functions defined".to_string();
+    }
+    lines.join("\n")
+}
+
+fn error_response(text: &str, e: error::Error) -> (String, Option<ErrorData>) {
+    let span = e.span();
+    let message = match e {
+        error::Error::Parse(ParseError::EmptyInput) => e.to_string(),
+        _ => format!("{}\n{}", point_at(text, span), e),
+    };
+    let error = ErrorData { kind: e.kind(), start: span.start, end: span.end, message: e.to_string() };
+    (message, Some(error))
+}
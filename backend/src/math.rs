@@ -1,26 +1,403 @@
-pub fn validate(n: &Vec<f64>, func: &String) -> bool{
-    if ["sin".to_string(), "cos".to_string()].contains(func){
-        if n.len() > 1{
-            return false
+use std::f64::consts::PI;
+use std::fmt::{Display, Formatter};
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use crate::decimal::{MAX_EXPONENT, MAX_PRECISION};
+use crate::error::EvalError;
+use crate::tokens::Span;
+
+/// The unit trig functions take and inverse trig functions return, set per session with
+/// `deg`, `rad` or `grad`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AngleMode {
+    #[default]
+    Radians,
+    Degrees,
+    Gradians,
+}
+
+impl AngleMode {
+    /// Parses `rad`, `deg` or `grad`, the same names used as suffixes like `30deg`.
+    pub fn parse(name: &str) -> Option<AngleMode> {
+        match name {
+            "rad" => Some(AngleMode::Radians),
+            "deg" => Some(AngleMode::Degrees),
+            "grad" => Some(AngleMode::Gradians),
+            _ => None,
+        }
+    }
+
+    pub fn suffix(self) -> &'static str {
+        match self {
+            AngleMode::Radians => "rad",
+            AngleMode::Degrees => "deg",
+            AngleMode::Gradians => "grad",
         }
     }
-    if func == "factorial"{
-        if  !is_positive_integer(n[0]){
-            return false
+
+    pub fn to_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle.to_radians(),
+            AngleMode::Gradians => angle * PI / 200.,
+        }
+    }
+
+    pub fn from_radians(self, radians: f64) -> f64 {
+        match self {
+            AngleMode::Radians => radians,
+            AngleMode::Degrees => radians.to_degrees(),
+            AngleMode::Gradians => radians * 200. / PI,
         }
     }
-    true
+
+    /// `angle` in these units converted to `to` units.
+    pub fn convert(self, angle: f64, to: AngleMode) -> f64 {
+        if self == to {
+            return angle
+        }
+        to.from_radians(self.to_radians(angle))
+    }
+}
+
+impl Display for AngleMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AngleMode::Radians => f.write_str("radians"),
+            AngleMode::Degrees => f.write_str("degrees"),
+            AngleMode::Gradians => f.write_str("gradians"),
+        }
+    }
+}
+
+/// Moves `x` onto the nearest multiple of 1/2 when it is within rounding error of it,
+/// where `scale` is the size of the values the error came from. Angles like 30° are
+/// inexact in radians, so without this `sin(30deg)` is 0.49999999999999994 and `cos(90deg)`
+/// is 6.1e-17.
+pub fn snap(x: f64, scale: f64) -> f64 {
+    let nearest = (2. * x).round() / 2.;
+    if (x - nearest).abs() <= 4. * f64::EPSILON * scale {
+        return nearest
+    }
+    x
+}
+
+/// `snap` on both parts of a complex number, so `exp(iπ)` is exactly -1.
+pub fn snap_complex(z: Complex64, scale: f64) -> Complex64 {
+    Complex64::new(snap(z.re, scale), snap(z.im, scale))
+}
+
+/// Domain check for functions defined for every real input.
+pub fn any_real(_: &[f64], _: &str, _: Span) -> Result<(), EvalError> {
+    Ok(())
+}
+
+/// Domain check for `factorial`, defined for 0, 1, 2, ...
+pub fn non_negative_integer(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    if !is_non_negative_integer(n[0]) {
+        return Err(EvalError::DomainError { name: func.to_string(), span })
+    }
+    Ok(())
+}
+
+/// Domain check for `factorial` and the Gamma functions, which have poles at the
+/// integers `offset`, `offset - 1`, `offset - 2`, ... so factorial passes -1 and gamma 0.
+fn not_a_pole(x: f64, offset: f64, func: &str, span: Span) -> Result<(), EvalError> {
+    if x <= offset && x.fract() == 0. {
+        return Err(EvalError::DomainError { name: func.to_string(), span })
+    }
+    Ok(())
+}
+
+/// Domain check for `factorial`, defined for everything but the negative integers.
+pub fn factorial_domain(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    not_a_pole(n[0], -1., func, span)
+}
+
+/// Domain check for `gamma` and `lgamma`, defined for everything but 0, -1, -2, ...
+pub fn gamma_domain(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    not_a_pole(n[0], 0., func, span)
+}
+
+/// Domain check for `nCr` and `nPr`: whole numbers must not be negative, other values go
+/// through the Gamma function.
+pub fn combinations_domain(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    if n.iter().all(|a| a.fract() == 0.) && n.iter().any(|a| *a < 0.) {
+        return Err(EvalError::DomainError { name: func.to_string(), span })
+    }
+    Ok(())
+}
+
+/// Domain check for logarithms, defined for x > 0.
+pub fn positive(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    if n[0] <= 0. {
+        return Err(EvalError::DomainError { name: func.to_string(), span })
+    }
+    Ok(())
+}
+
+/// Domain check for `acosh`, defined for x >= 1.
+pub fn at_least_one(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    if n[0] < 1. {
+        return Err(EvalError::DomainError { name: func.to_string(), span })
+    }
+    Ok(())
+}
+
+/// Domain check for `atanh`, defined for -1 < x < 1.
+pub fn open_unit_interval(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    if n[0] <= -1. || n[0] >= 1. {
+        return Err(EvalError::DomainError { name: func.to_string(), span })
+    }
+    Ok(())
+}
+
+/// Domain check for `log(x, base)`, which needs x > 0 and a positive base other than 1.
+pub fn logarithm(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    positive(n, func, span)?;
+    if n.get(1).is_some_and(|base| *base <= 0. || *base == 1.) {
+        return Err(EvalError::DomainError { name: func.to_string(), span })
+    }
+    Ok(())
+}
+
+/// Domain check for `round(x, digits)`, which needs a whole number of digits, no more than
+/// the most any decimal can have.
+pub fn round_digits(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    if n.get(1).is_some_and(|digits| !is_integer(*digits)) {
+        return Err(EvalError::NotAnInteger { span })
+    }
+    if n.get(1).is_some_and(|digits| digits.abs() > (MAX_PRECISION as i64 + MAX_EXPONENT) as f64) {
+        return Err(EvalError::DomainError { name: func.to_string(), span })
+    }
+    Ok(())
+}
+
+/// Domain check for `asin` and `acos`, defined for -1 <= x <= 1.
+pub fn unit_interval(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    if !(-1. ..=1.).contains(&n[0]) {
+        return Err(EvalError::DomainError { name: func.to_string(), span })
+    }
+    Ok(())
+}
+
+/// Domain check for bitwise functions, which work on whole numbers.
+pub fn integers(n: &[f64], _: &str, span: Span) -> Result<(), EvalError> {
+    if !n.iter().all(|x| is_integer(*x)) {
+        return Err(EvalError::NotAnInteger { span })
+    }
+    Ok(())
+}
+
+/// Domain check for `shl`/`shr`, which also need a non-negative shift.
+pub fn integer_and_shift(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    integers(n, func, span)?;
+    if n[1] < 0. {
+        return Err(EvalError::DomainError { name: func.to_string(), span })
+    }
+    Ok(())
+}
+
+/// Domain check for sample statistics, which need at least two values.
+pub fn at_least_two(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    if n.len() < 2 {
+        return Err(EvalError::DomainError { name: func.to_string(), span })
+    }
+    Ok(())
+}
+
+/// Domain check for `skew` and `kurtosis`, which divide by the spread of the values.
+pub fn not_all_equal(n: &[f64], func: &str, span: Span) -> Result<(), EvalError> {
+    if n.iter().all(|a| *a == n[0]) {
+        return Err(EvalError::DomainError { name: func.to_string(), span })
+    }
+    Ok(())
 }
 
+/// `1 * 2 * ... * n` with `0! = 1`, stopping once the result overflows to infinity.
 pub fn factorial(n: f64) -> f64 {
-    if n == 0.{
-        return 1.
+    if n.fract() != 0. {
+        return gamma(n + 1.)
+    }
+    let mut result: f64 = 1.;
+    let mut k = 2.;
+    while k <= n && result.is_finite() {
+        result *= k;
+        k += 1.;
+    }
+    result
+}
+
+/// Largest `n` whose factorial, and the most factors `nCr` and `nPr` multiply, are computed
+/// exactly. 10000! already has 35660 digits; past this results are floating point.
+pub const MAX_EXACT_FACTORS: f64 = 10000.;
+
+/// Product of the `count` integers counting down from `top`.
+fn falling_product(top: &BigInt, count: u64) -> BigInt {
+    (0..count).fold(BigInt::from(1), |acc, k| acc * (top - k))
+}
+
+/// `n!` exactly, for whole `0 <= n <= MAX_EXACT_FACTORS`.
+pub fn exact_factorial(n: &[f64]) -> Option<BigInt> {
+    if !is_non_negative_integer(n[0]) || n[0] > MAX_EXACT_FACTORS {
+        return None
+    }
+    Some(falling_product(&BigInt::from(n[0] as u64), n[0] as u64))
+}
+
+/// Ways to choose `k` of `n` items in order, `n! / (n - k)!`, exactly for whole arguments.
+pub fn exact_permutations(n: &[f64]) -> Option<BigInt> {
+    let (top, count) = (n[0], n[1]);
+    if !is_non_negative_integer(top) || !is_non_negative_integer(count) || !is_integer(top) {
+        return None
+    }
+    if count > top {
+        return Some(BigInt::from(0))
+    }
+    if count > MAX_EXACT_FACTORS {
+        return None
+    }
+    Some(falling_product(&BigInt::from(top as i64), count as u64))
+}
+
+/// Ways to choose `k` of `n` items in any order, `n! / (k! (n - k)!)`, exactly for whole
+/// arguments.
+pub fn exact_combinations(n: &[f64]) -> Option<BigInt> {
+    let (top, count) = (n[0], n[1]);
+    if !is_non_negative_integer(top) || !is_non_negative_integer(count) || !is_integer(top) {
+        return None
+    }
+    if count > top {
+        return Some(BigInt::from(0))
+    }
+    let count = count.min(top - count);
+    if count > MAX_EXACT_FACTORS {
+        return None
+    }
+    Some(falling_product(&BigInt::from(top as i64), count as u64) / falling_product(&BigInt::from(count as u64), count as u64))
+}
+
+/// `nPr` for arguments `exact_permutations` doesn't handle, through the Gamma function.
+pub fn permutations(n: Vec<f64>) -> f64 {
+    (ln_gamma(n[0] + 1.) - ln_gamma(n[0] - n[1] + 1.)).exp() * gamma_sign(n[0] + 1.) * gamma_sign(n[0] - n[1] + 1.)
+}
+
+/// `nCr` for arguments `exact_combinations` doesn't handle, through the Gamma function.
+pub fn combinations(n: Vec<f64>) -> f64 {
+    permutations(n.clone()) / gamma(n[1] + 1.)
+}
+
+/// Lanczos approximation coefficients for g = 7.
+const LANCZOS: [f64; 9] = [0.999_999_999_999_809_9, 676.520_368_121_885_1, -1_259.139_216_722_402_8, 771.323_428_777_653_1,
+    -176.615_029_162_140_6, 12.507_343_278_686_905, -0.138_571_095_265_720_12, 9.984_369_578_019_572e-6, 1.505_632_735_149_311_6e-7];
+
+/// `ln |Γ(x)|` from the Lanczos approximation, reflected for `x < 0.5`.
+pub fn ln_gamma(x: f64) -> f64 {
+    if x < 0.5 {
+        return (PI / (PI * x).sin().abs()).ln() - ln_gamma(1. - x)
+    }
+    let x = x - 1.;
+    let t = x + 7.5;
+    let series = LANCZOS.iter().enumerate().skip(1).fold(LANCZOS[0], |acc, (i, c)| acc + c / (x + i as f64));
+    0.5 * (2. * PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
+
+/// Sign of `Γ(x)`, which alternates between the poles at 0, -1, -2, ...
+fn gamma_sign(x: f64) -> f64 {
+    if x > 0. || (x.floor() as i64) % 2 == 0 { 1. } else { -1. }
+}
+
+/// The Gamma function, `Γ(n) = (n - 1)!` for whole `n`.
+pub fn gamma(x: f64) -> f64 {
+    if x.fract() == 0. && x > 0. {
+        return factorial(x - 1.)
+    }
+    gamma_sign(x) * ln_gamma(x).exp()
+}
+
+/// `n * (n - 2) * (n - 4) * ...` down to 1 or 2, with `0!! = 1`.
+pub fn double_factorial(n: f64) -> f64 {
+    let mut result: f64 = 1.;
+    let mut k = n;
+    while k > 1. && result.is_finite() {
+        result *= k;
+        k -= 2.;
     }
-    n * factorial(n - 1.)
+    result
 }
 
-pub fn is_positive_integer(num: f64) -> bool {
-    num > 0.0 && num.fract() == 0.0
+/// Logarithm of `nums[0]` to base `nums[1]`, or base 10 when no base is given.
+pub fn log(nums: Vec<f64>) -> f64 {
+    match nums.get(1) {
+        Some(base) => nums[0].ln() / base.ln(),
+        None => nums[0].log10(),
+    }
+}
+
+/// `nums[0]` rounded half away from zero to `nums[1]` decimal places, which may be negative
+/// to round to tens, hundreds and so on.
+pub fn round(nums: Vec<f64>) -> f64 {
+    let digits = nums.get(1).copied().unwrap_or(0.);
+    let scale = 10f64.powf(digits);
+    if scale == 0. {
+        return 0.
+    }
+    match (nums[0] * scale).round() / scale {
+        // Past f64 precision there is nothing left to round.
+        a if a.is_finite() => a,
+        _ => nums[0],
+    }
+}
+
+/// -1, 0 or 1 by the sign of `n`, unlike `f64::signum` which gives 1 for 0.
+pub fn sign(n: f64) -> f64 {
+    if n == 0. { 0. } else { n.signum() }
+}
+
+/// Length of the vector with components `nums`.
+pub fn hypot(nums: Vec<f64>) -> f64 {
+    nums.into_iter().fold(0., f64::hypot)
+}
+
+/// Angle of the point `(nums[1], nums[0])` in radians, from -pi to pi.
+pub fn atan2(nums: Vec<f64>) -> f64 {
+    nums[0].atan2(nums[1])
+}
+
+pub fn is_non_negative_integer(num: f64) -> bool {
+    num >= 0.0 && num.fract() == 0.0
+}
+
+/// True for whole numbers that fit in an i64, the range bitwise operations work on.
+pub fn is_integer(num: f64) -> bool {
+    num.fract() == 0.0 && num.abs() < 9.223372036854776e18
+}
+
+pub fn bit_and(nums: Vec<f64>) -> f64 {
+    nums.iter().fold(-1, |acc, num| acc & *num as i64) as f64
+}
+
+pub fn bit_or(nums: Vec<f64>) -> f64 {
+    nums.iter().fold(0, |acc, num| acc | *num as i64) as f64
+}
+
+pub fn bit_xor(nums: Vec<f64>) -> f64 {
+    nums.iter().fold(0, |acc, num| acc ^ *num as i64) as f64
+}
+
+pub fn bit_not(n: f64) -> f64 {
+    !(n as i64) as f64
+}
+
+pub fn shift_left(nums: Vec<f64>) -> f64 {
+    (nums[0] as i64).checked_shl(nums[1] as u32).unwrap_or(0) as f64
+}
+
+pub fn shift_right(nums: Vec<f64>) -> f64 {
+    let n = nums[0] as i64;
+    n.checked_shr(nums[1] as u32).unwrap_or(if n < 0 { -1 } else { 0 }) as f64
 }
 
 pub fn average(nums: Vec<f64>) -> f64{
@@ -68,8 +445,149 @@ pub fn min(nums: Vec<f64>) -> f64 {
     min
 }
 
+/// The middle value, or the average of the two middle values of an even count.
 pub fn median(nums: Vec<f64>) -> f64 {
     let mut v = nums.clone();
-    v.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    v[v.len()/2]
-}
\ No newline at end of file
+    v.sort_by(f64::total_cmp);
+    let middle = v.len()/2;
+    if v.len().is_multiple_of(2) {
+        return v[middle - 1] + (v[middle] - v[middle - 1]) / 2.
+    }
+    v[middle]
+}
+
+/// The most common value, the smallest of them when several are equally common.
+pub fn mode(nums: Vec<f64>) -> f64 {
+    let mut v = nums;
+    v.sort_by(f64::total_cmp);
+    most_common(&v)
+}
+
+/// The value with the longest run in `sorted`, the first of them on a tie.
+pub fn most_common<T: PartialEq + Clone>(sorted: &[T]) -> T {
+    let (mut best, mut best_count, mut start) = (0, 0, 0);
+    for index in 1..=sorted.len() {
+        if index == sorted.len() || sorted[index] != sorted[start] {
+            if index - start > best_count {
+                (best, best_count) = (start, index - start);
+            }
+            start = index;
+        }
+    }
+    sorted[best].clone()
+}
+
+/// Population variance, the mean squared distance from the mean.
+pub fn variance(nums: Vec<f64>) -> f64 {
+    central_moment(&nums, 2)
+}
+
+/// Sample variance, which divides by one less than the count to estimate the variance of
+/// the population the values were drawn from.
+pub fn sample_variance(nums: Vec<f64>) -> f64 {
+    let count = nums.len() as f64;
+    central_moment(&nums, 2) * count / (count - 1.)
+}
+
+pub fn sample_standard_deviation(nums: Vec<f64>) -> f64 {
+    sample_variance(nums).sqrt()
+}
+
+/// Population skewness, the third central moment over the standard deviation cubed.
+pub fn skewness(nums: Vec<f64>) -> f64 {
+    central_moment(&nums, 3) / central_moment(&nums, 2).powf(1.5)
+}
+
+/// Excess kurtosis, the fourth central moment over the variance squared less 3, so it is 0
+/// for a normal distribution.
+pub fn kurtosis(nums: Vec<f64>) -> f64 {
+    central_moment(&nums, 4) / central_moment(&nums, 2).powi(2) - 3.
+}
+
+/// The mean of the `k`th powers of the distances from the mean.
+fn central_moment(nums: &[f64], k: i32) -> f64 {
+    let average = average(nums.to_vec());
+    nums.iter().map(|a| (a - average).powi(k)).sum::<f64>() / nums.len() as f64
+}
+
+/// The fraction `x` was most likely written as, from its shortest decimal form, so `0.1`
+/// is 1/10 rather than the binary fraction nearest it.
+pub fn decimal_rational(x: f64) -> Option<BigRational> {
+    if !x.is_finite() {
+        return None
+    }
+    let text = x.to_string();
+    let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+    let numerator: BigInt = format!("{}{}", whole, fraction).parse().ok()?;
+    Some(BigRational::new(numerator, BigInt::from(10).pow(fraction.len() as u32)))
+}
+
+pub fn rational_sum(nums: &[BigRational]) -> BigRational {
+    nums.iter().fold(BigRational::zero(), |acc, num| acc + num)
+}
+
+pub fn rational_average(nums: &[BigRational]) -> BigRational {
+    rational_sum(nums) / BigInt::from(nums.len())
+}
+
+pub fn rational_median(nums: &[BigRational]) -> BigRational {
+    let mut v = nums.to_vec();
+    v.sort();
+    let middle = v.len()/2;
+    if v.len().is_multiple_of(2) {
+        return (&v[middle - 1] + &v[middle]) / BigInt::from(2)
+    }
+    v[middle].clone()
+}
+
+pub fn rational_mode(nums: &[BigRational]) -> BigRational {
+    let mut v = nums.to_vec();
+    v.sort();
+    most_common(&v)
+}
+
+pub fn rational_variance(nums: &[BigRational]) -> BigRational {
+    let average = rational_average(nums);
+    nums.iter().map(|a| (a - &average) * (a - &average)).fold(BigRational::zero(), |acc, a| acc + a) / BigInt::from(nums.len())
+}
+
+pub fn rational_sample_variance(nums: &[BigRational]) -> BigRational {
+    rational_variance(nums) * BigInt::from(nums.len()) / BigInt::from(nums.len() - 1)
+}
+
+pub fn rational_max(nums: &[BigRational]) -> BigRational {
+    nums.iter().max().unwrap().clone()
+}
+
+pub fn rational_min(nums: &[BigRational]) -> BigRational {
+    nums.iter().min().unwrap().clone()
+}
+
+pub fn rational_abs(n: &[BigRational]) -> BigRational {
+    n[0].abs()
+}
+
+pub fn rational_floor(n: &[BigRational]) -> BigRational {
+    n[0].floor()
+}
+
+pub fn rational_ceil(n: &[BigRational]) -> BigRational {
+    n[0].ceil()
+}
+
+pub fn rational_sign(n: &[BigRational]) -> BigRational {
+    n[0].signum()
+}
+
+/// `round` on an exact fraction, with the same limits on `digits` as on an `f64`.
+pub fn rational_round(nums: &[BigRational]) -> BigRational {
+    let digits = nums.get(1).map_or(0, |a| a.to_integer().to_i32().unwrap_or(if a.is_negative() { i32::MIN } else { i32::MAX }));
+    if digits > 400 {
+        return nums[0].clone()
+    }
+    if digits < -400 {
+        return BigRational::zero()
+    }
+    let scale = BigRational::from_integer(BigInt::from(10)).pow(digits);
+    (&nums[0] * &scale).round() / scale
+}
@@ -0,0 +1,856 @@
+use std::cmp::Ordering;
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, Signed, ToPrimitive, Zero};
+use crate::error::EvalError;
+use crate::math::{is_integer, snap};
+use crate::tokens::Span;
+use crate::value::{NumberMode, Value};
+
+/// Relative size below which floating point elimination takes an element to be zero.
+const TOLERANCE: f64 = 1e-12;
+/// Largest power of an exact matrix kept exact, past it the entries grow too long.
+const MAX_EXACT_POWER: u64 = 1 << 12;
+/// Sweeps of rotations, or QR steps for each eigenvalue, before an iterative decomposition
+/// stops.
+const MAX_ITERATIONS: usize = 100;
+
+/// The numbers linear algebra works on: exact fractions when every element of a matrix is
+/// exact, or a whole `Number`, and floating point otherwise.
+trait Scalar: Clone + Signed + PartialOrd + FromPrimitive {
+    /// Whether `self` counts as zero next to elements as large as `scale`.
+    fn negligible(&self, scale: &Self) -> bool;
+
+    fn into_value(self, mode: NumberMode) -> Value;
+}
+
+impl Scalar for BigRational {
+    fn negligible(&self, _: &BigRational) -> bool {
+        self.is_zero()
+    }
+
+    fn into_value(self, mode: NumberMode) -> Value {
+        Value::from_rational(self, mode)
+    }
+}
+
+impl Scalar for f64 {
+    fn negligible(&self, scale: &f64) -> bool {
+        self.abs() <= scale * TOLERANCE
+    }
+
+    fn into_value(self, _: NumberMode) -> Value {
+        Value::Number(self)
+    }
+}
+
+/// The elements of a matrix as one kind of `Scalar`.
+enum Numbers {
+    Exact(Vec<Vec<BigRational>>),
+    Float(Vec<Vec<f64>>),
+}
+
+impl Numbers {
+    /// Reads the elements of `rows`, reporting a type error at `span` for any that isn't a
+    /// real number.
+    fn new(rows: &[Vec<Value>], span: Span) -> Result<Numbers, EvalError> {
+        let mut float = Vec::new();
+        for row in rows {
+            let mut numbers = Vec::new();
+            for element in row {
+                numbers.push(element.clone().number(span)?);
+            }
+            float.push(numbers);
+        }
+        let exact: Option<Vec<Vec<BigRational>>> = rows.iter().map(|row| row.iter().map(Value::rational).collect()).collect();
+        Ok(exact.map_or(Numbers::Float(float), Numbers::Exact))
+    }
+
+    fn float(self) -> Vec<Vec<f64>> {
+        match self {
+            Numbers::Exact(rows) => rows.iter().map(|row| row.iter().map(|a| a.to_f64().unwrap_or(f64::NAN)).collect()).collect(),
+            Numbers::Float(rows) => rows,
+        }
+    }
+}
+
+/// Rows by columns.
+pub fn shape(rows: &[Vec<Value>]) -> (usize, usize) {
+    (rows.len(), rows.first().map_or(0, Vec::len))
+}
+
+/// `transpose(A)` the rows of `A` as columns. A list is taken as a row, so it becomes a
+/// column.
+pub fn transpose(args: Vec<Value>, _: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let rows = match args.into_iter().next().unwrap() {
+        Value::List(row) => vec![row],
+        value => matrix(value, span)?,
+    };
+    let columns = (0..shape(&rows).1).map(|column| Value::List(rows.iter().map(|row| row[column].clone()).collect()));
+    Ok(Value::from_elements(columns.collect()))
+}
+
+/// `det(A)` the determinant of the square matrix `A`.
+pub fn det(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let rows = square(args.into_iter().next().unwrap(), span)?;
+    let size = rows.len();
+    Ok(match Numbers::new(&rows, span)? {
+        Numbers::Exact(mut a) => eliminate(&mut a, size).1.into_value(mode),
+        Numbers::Float(mut a) => eliminate(&mut a, size).1.into_value(mode),
+    })
+}
+
+/// `inv(A)` the inverse of the square matrix `A`, an error when it is singular.
+pub fn inv(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let rows = square(args.into_iter().next().unwrap(), span)?;
+    let inverse = match Numbers::new(&rows, span)? {
+        Numbers::Exact(a) => inverse(a).map(|a| values(a, mode)),
+        Numbers::Float(a) => inverse(a).map(|a| values(a, mode)),
+    };
+    inverse.map(Value::Matrix).ok_or(EvalError::DomainError { name: "inv".to_string(), span })
+}
+
+/// `rank(A)` the number of linearly independent rows of `A`.
+pub fn rank(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let rows = matrix(args.into_iter().next().unwrap(), span)?;
+    let columns = shape(&rows).1;
+    let rank = match Numbers::new(&rows, span)? {
+        Numbers::Exact(mut a) => eliminate(&mut a, columns).0.len(),
+        Numbers::Float(mut a) => eliminate(&mut a, columns).0.len(),
+    };
+    Ok(Value::from_rational(BigRational::from_integer(BigInt::from(rank)), mode))
+}
+
+/// `rref(A)` the reduced row echelon form of `A`.
+pub fn rref(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let rows = matrix(args.into_iter().next().unwrap(), span)?;
+    let columns = shape(&rows).1;
+    Ok(Value::Matrix(match Numbers::new(&rows, span)? {
+        Numbers::Exact(mut a) => {
+            eliminate(&mut a, columns);
+            values(a, mode)
+        }
+        Numbers::Float(mut a) => {
+            eliminate(&mut a, columns);
+            values(a, mode)
+        }
+    }))
+}
+
+/// `trace(A)` the sum of the diagonal of the square matrix `A`.
+pub fn trace(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let rows = square(args.into_iter().next().unwrap(), span)?;
+    Ok(match Numbers::new(&rows, span)? {
+        Numbers::Exact(a) => diagonal_sum(a).into_value(mode),
+        Numbers::Float(a) => diagonal_sum(a).into_value(mode),
+    })
+}
+
+/// `solve(A, b)` the `x` with `A x = b` for a square, non-singular `A`. `b` is a list, giving
+/// a list, or a matrix with a column for each system to solve.
+pub fn solve(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let mut args = args.into_iter();
+    let a = square(args.next().unwrap(), span)?;
+    let (b, vector) = match args.next().unwrap() {
+        Value::List(b) => (b.into_iter().map(|a| vec![a]).collect(), true),
+        b => (matrix(b, span)?, false),
+    };
+    if b.len() != a.len() {
+        return Err(EvalError::ShapeMismatch { left: shape(&a), right: shape(&b), span });
+    }
+    let solution = match (Numbers::new(&a, span)?, Numbers::new(&b, span)?) {
+        (Numbers::Exact(a), Numbers::Exact(b)) => solve_numbers(a, b).map(|a| values(a, mode)),
+        (a, b) => solve_numbers(a.float(), b.float()).map(|a| values(a, mode)),
+    };
+    let solution = solution.ok_or(EvalError::DomainError { name: "solve".to_string(), span })?;
+    Ok(if vector { Value::List(solution.into_iter().flatten().collect()) } else { Value::Matrix(solution) })
+}
+
+/// `left * right` where one side is a matrix and the other a matrix or a list. A list on the
+/// left is a row vector and on the right a column vector, and either way the result is a
+/// list.
+pub fn multiply(left: Value, right: Value, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let (a, b, vector) = match (left, right) {
+        (Value::List(a), b) => (vec![a], matrix(b, span)?, true),
+        (a, Value::List(b)) => (matrix(a, span)?, b.into_iter().map(|a| vec![a]).collect(), true),
+        (a, b) => (matrix(a, span)?, matrix(b, span)?, false),
+    };
+    if shape(&a).1 != b.len() || b.is_empty() {
+        return Err(EvalError::ShapeMismatch { left: shape(&a), right: shape(&b), span });
+    }
+    let product = match (Numbers::new(&a, span)?, Numbers::new(&b, span)?) {
+        (Numbers::Exact(a), Numbers::Exact(b)) => values(product(&a, &b), mode),
+        (a, b) => values(product(&a.float(), &b.float()), mode),
+    };
+    Ok(if vector { Value::List(product.into_iter().flatten().collect()) } else { Value::Matrix(product) })
+}
+
+/// `A^n` for a square matrix and a whole `n`, through the inverse when `n` is negative.
+pub fn power(rows: Vec<Vec<Value>>, exponent: Value, mode: NumberMode, span: Span, exponent_span: Span) -> Result<Value, EvalError> {
+    let rows = square(Value::Matrix(rows), span)?;
+    let exponent = exponent.number(exponent_span)?;
+    if !is_integer(exponent) {
+        return Err(EvalError::NotAnInteger { span: exponent_span });
+    }
+    let singular = || EvalError::DomainError { name: "^".to_string(), span };
+    let count = exponent.abs() as u64;
+    let result = match Numbers::new(&rows, span)? {
+        Numbers::Exact(a) if count <= MAX_EXACT_POWER => {
+            let base = if exponent < 0. { inverse(a).ok_or_else(singular)? } else { a };
+            values(matrix_power(base, count), mode)
+        }
+        numbers => {
+            let a = numbers.float();
+            let base = if exponent < 0. { inverse(a).ok_or_else(singular)? } else { a };
+            values(matrix_power(base, count), mode)
+        }
+    };
+    Ok(Value::Matrix(result))
+}
+
+/// `eig(A)` the eigenvalues of the square matrix `A` and a matrix with a unit eigenvector for
+/// each as its columns, `[values, vectors]`. Values come largest real part first and are
+/// complex where `A` rotates, so a state matrix is stable when every real part is negative.
+pub fn eig(args: Vec<Value>, _: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let a = floats(square(args.into_iter().next().unwrap(), span)?, "eig", span)?;
+    // Eigenvalues can overflow where the elements don't, so the work is done on `a` over a
+    // power of two near its largest element, which divides without rounding.
+    let unit = match 2f64.powi(largest(&a).log2().floor() as i32) {
+        unit if unit.is_normal() => unit,
+        _ => 1.,
+    };
+    let a: Vec<Vec<f64>> = a.into_iter().map(|row| row.into_iter().map(|a| a / unit).collect()).collect();
+    let scale = largest(&a);
+    let mut pairs: Vec<(Complex64, Vec<Complex64>)> = match symmetric(&a, scale) {
+        true => {
+            let (values, vectors) = symmetric_eigen(a);
+            let columns = transpose_floats(&vectors);
+            values.into_iter().zip(columns).map(|(value, vector)| (Complex64::from(value), vector.into_iter().map(Complex64::from).collect())).collect()
+        }
+        false => {
+            let values = eigenvalues(&a).ok_or(EvalError::DomainError { name: "eig".to_string(), span })?;
+            values.into_iter().map(|value| (value, eigenvector(&a, value, scale))).collect()
+        }
+    };
+    pairs.sort_by(|(a, _), (b, _)| b.re.total_cmp(&a.re).then(b.im.total_cmp(&a.im)));
+    let values = pairs.iter().map(|(value, _)| Value::from_complex(Complex64::new(clean(value.re, scale), clean(value.im, scale)) * unit)).collect();
+    let vectors = (0..pairs.len()).map(|row| pairs.iter().map(|(_, vector)| {
+        Value::from_complex(Complex64::new(clean(vector[row].re, 1.), clean(vector[row].im, 1.)))
+    }).collect()).collect();
+    Ok(Value::List(vec![Value::List(values), Value::Matrix(vectors)]))
+}
+
+/// `svd(A)` the singular value decomposition `[U, s, V]` with `A = U diag(s) transpose(V)`,
+/// singular values `s` largest first and orthonormal columns in `U` and `V`, as many as the
+/// shorter side of `A`.
+pub fn svd(args: Vec<Value>, _: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let a = floats(matrix(args.into_iter().next().unwrap(), span)?, "svd", span)?;
+    let scale = largest(&a);
+    let wide = a.len() < a[0].len();
+    let (u, values, v) = singular_values(if wide { transpose_floats(&a) } else { a });
+    let (u, v) = if wide { (v, u) } else { (u, v) };
+    let values = values.into_iter().map(|a| Value::Number(clean(a, scale))).collect();
+    Ok(Value::List(vec![float_matrix(u, 1.), Value::List(values), float_matrix(v, 1.)]))
+}
+
+/// `qr(A)` orthonormal columns `Q` and upper triangular `R` with `A = Q R`, `[Q, R]`, by
+/// Householder reflections. `Q` has as many columns as the shorter side of `A`, and the
+/// diagonal of `R` is not negative.
+pub fn qr(args: Vec<Value>, _: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let mut r = floats(matrix(args.into_iter().next().unwrap(), span)?, "qr", span)?;
+    let scale = largest(&r);
+    let (rows, columns) = (r.len(), r[0].len());
+    let size = rows.min(columns);
+    let mut q: Vec<Vec<f64>> = identity(rows);
+    for column in 0..size.min(rows - 1) {
+        let norm = r[column..].iter().map(|row| row[column] * row[column]).sum::<f64>().sqrt();
+        let alpha = if r[column][column] > 0. { -norm } else { norm };
+        let mut v: Vec<f64> = r[column..].iter().map(|row| row[column]).collect();
+        v[0] -= alpha;
+        let length = v.iter().map(|a| a * a).sum::<f64>();
+        if length == 0. {
+            continue;
+        }
+        for j in 0..columns {
+            let factor = 2. * v.iter().zip(&r[column..]).map(|(v, row)| v * row[j]).sum::<f64>() / length;
+            r[column..].iter_mut().zip(&v).for_each(|(row, v)| row[j] -= factor * v);
+        }
+        for row in q.iter_mut() {
+            let factor = 2. * v.iter().zip(&row[column..]).map(|(v, a)| v * a).sum::<f64>() / length;
+            row[column..].iter_mut().zip(&v).for_each(|(a, v)| *a -= factor * v);
+        }
+    }
+    for k in 0..size {
+        if r[k][k] < 0. {
+            r[k].iter_mut().for_each(|a| *a = -*a);
+            q.iter_mut().for_each(|row| row[k] = -row[k]);
+        }
+    }
+    let q = q.into_iter().map(|row| row[..size].to_vec()).collect();
+    let r = r.into_iter().take(size).enumerate().map(|(k, row)| row.into_iter().enumerate().map(|(j, a)| if j < k { 0. } else { a }).collect()).collect();
+    Ok(Value::List(vec![float_matrix(q, 1.), float_matrix(r, scale)]))
+}
+
+/// `lu(A)` unit lower triangular `L`, upper triangular `U` and permutation `P` with
+/// `P A = L U`, `[L, U, P]`, taking the largest pivot in each column. Exact for exact
+/// matrices.
+pub fn lu(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let rows = matrix(args.into_iter().next().unwrap(), span)?;
+    let (l, u, p) = match Numbers::new(&rows, span)? {
+        Numbers::Exact(a) => {
+            let [l, u, p] = lower_upper(a);
+            (values(l, mode), values(u, mode), values(p, mode))
+        }
+        Numbers::Float(a) => {
+            let [l, u, p] = lower_upper(a);
+            (values(l, mode), values(u, mode), values(p, mode))
+        }
+    };
+    Ok(Value::List(vec![Value::Matrix(l), Value::Matrix(u), Value::Matrix(p)]))
+}
+
+/// `cholesky(A)` the lower triangular `L` with `A = L transpose(L)`, for a symmetric positive
+/// definite `A` only.
+pub fn cholesky(args: Vec<Value>, _: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let a = floats(square(args.into_iter().next().unwrap(), span)?, "cholesky", span)?;
+    let scale = largest(&a);
+    let size = a.len();
+    let not_definite = || EvalError::DomainError { name: "cholesky".to_string(), span };
+    if !symmetric(&a, scale) {
+        return Err(not_definite());
+    }
+    let mut l = vec![vec![0.; size]; size];
+    for j in 0..size {
+        let diagonal = a[j][j] - l[j][..j].iter().map(|a| a * a).sum::<f64>();
+        if diagonal <= 0. || diagonal.is_nan() {
+            return Err(not_definite());
+        }
+        l[j][j] = diagonal.sqrt();
+        for i in j + 1..size {
+            l[i][j] = (a[i][j] - l[i][..j].iter().zip(&l[j][..j]).map(|(a, b)| a * b).sum::<f64>()) / l[j][j];
+        }
+    }
+    Ok(float_matrix(l, scale.sqrt()))
+}
+
+/// Unwraps a matrix, reporting a type error at `span` for anything else.
+fn matrix(value: Value, span: Span) -> Result<Vec<Vec<Value>>, EvalError> {
+    match value {
+        Value::Matrix(rows) => Ok(rows),
+        value => Err(EvalError::TypeMismatch { expected: "matrix", found: value.type_name(), span }),
+    }
+}
+
+/// `matrix` for square matrices only.
+fn square(value: Value, span: Span) -> Result<Vec<Vec<Value>>, EvalError> {
+    let rows = matrix(value, span)?;
+    match shape(&rows) {
+        (size, columns) if size != columns => Err(EvalError::NotSquare { rows: size, columns, span }),
+        _ => Ok(rows),
+    }
+}
+
+fn values<T: Scalar>(rows: Vec<Vec<T>>, mode: NumberMode) -> Vec<Vec<Value>> {
+    rows.into_iter().map(|row| row.into_iter().map(|a| a.into_value(mode)).collect()).collect()
+}
+
+/// Gauss-Jordan elimination with partial pivoting on the first `columns` columns, leaving
+/// `rows` in reduced row echelon form. Returns the pivot columns and the determinant of the
+/// leading square block, which is zero unless every column has a pivot.
+fn eliminate<T: Scalar>(rows: &mut [Vec<T>], columns: usize) -> (Vec<usize>, T) {
+    let largest = rows.iter().flat_map(|row| &row[..columns]).map(|a| a.abs()).fold(T::zero(), |a, b| if b > a { b } else { a });
+    let scale = largest * T::from_usize(rows.len().max(columns)).unwrap();
+    let mut determinant = T::one();
+    let mut pivots = Vec::new();
+    for column in 0..columns {
+        let row = pivots.len();
+        if row == rows.len() {
+            break;
+        }
+        let best = (row..rows.len()).max_by(|a, b| rows[*a][column].abs().partial_cmp(&rows[*b][column].abs()).unwrap_or(Ordering::Equal)).unwrap();
+        if rows[best][column].negligible(&scale) {
+            determinant = T::zero();
+            continue;
+        }
+        if best != row {
+            rows.swap(best, row);
+            determinant = -determinant;
+        }
+        let pivot = rows[row][column].clone();
+        determinant = determinant * pivot.clone();
+        rows[row].iter_mut().for_each(|a| *a = a.clone() / pivot.clone());
+        let pivot_row = rows[row].clone();
+        for (index, other) in rows.iter_mut().enumerate() {
+            let factor = other[column].clone();
+            if index != row && !factor.is_zero() {
+                other.iter_mut().zip(&pivot_row).for_each(|(a, b)| *a = a.clone() - factor.clone() * b.clone());
+            }
+        }
+        pivots.push(column);
+    }
+    for row in rows.iter_mut() {
+        row[..columns].iter_mut().filter(|a| a.negligible(&scale)).for_each(|a| *a = T::zero());
+    }
+    (pivots, determinant)
+}
+
+/// The `x` with `a x = b`, or `None` when `a` is singular.
+fn solve_numbers<T: Scalar>(a: Vec<Vec<T>>, b: Vec<Vec<T>>) -> Option<Vec<Vec<T>>> {
+    let size = a.len();
+    let mut rows: Vec<Vec<T>> = a.into_iter().zip(b).map(|(mut row, b)| {
+        row.extend(b);
+        row
+    }).collect();
+    let (pivots, _) = eliminate(&mut rows, size);
+    (pivots.len() == size).then(|| rows.into_iter().map(|row| row[size..].to_vec()).collect())
+}
+
+fn inverse<T: Scalar>(a: Vec<Vec<T>>) -> Option<Vec<Vec<T>>> {
+    let size = a.len();
+    solve_numbers(a, identity(size))
+}
+
+fn identity<T: Scalar>(size: usize) -> Vec<Vec<T>> {
+    (0..size).map(|row| (0..size).map(|column| if row == column { T::one() } else { T::zero() }).collect()).collect()
+}
+
+fn product<T: Scalar>(a: &[Vec<T>], b: &[Vec<T>]) -> Vec<Vec<T>> {
+    let columns = b.first().map_or(0, Vec::len);
+    a.iter().map(|row| (0..columns).map(|column| {
+        row.iter().zip(b).fold(T::zero(), |sum, (a, b)| sum + a.clone() * b[column].clone())
+    }).collect()).collect()
+}
+
+/// `a^exponent` by repeated squaring.
+fn matrix_power<T: Scalar>(mut a: Vec<Vec<T>>, mut exponent: u64) -> Vec<Vec<T>> {
+    let mut result = identity(a.len());
+    while exponent > 0 {
+        if exponent % 2 == 1 {
+            result = product(&result, &a);
+        }
+        exponent /= 2;
+        if exponent > 0 {
+            a = product(&a, &a);
+        }
+    }
+    result
+}
+
+fn diagonal_sum<T: Scalar>(a: Vec<Vec<T>>) -> T {
+    a.into_iter().enumerate().fold(T::zero(), |sum, (index, row)| sum + row[index].clone())
+}
+
+/// The elements of `rows` as floating point numbers, which must be finite for the iterative
+/// decomposition `name`.
+fn floats(rows: Vec<Vec<Value>>, name: &str, span: Span) -> Result<Vec<Vec<f64>>, EvalError> {
+    let a = Numbers::new(&rows, span)?.float();
+    match a.iter().flatten().all(|a| a.is_finite()) {
+        true => Ok(a),
+        false => Err(EvalError::DomainError { name: name.to_string(), span }),
+    }
+}
+
+/// `rows` as a matrix of numbers, cleaned up for rounding error next to `scale`.
+fn float_matrix(rows: Vec<Vec<f64>>, scale: f64) -> Value {
+    Value::Matrix(rows.into_iter().map(|row| row.into_iter().map(|a| Value::Number(clean(a, scale))).collect()).collect())
+}
+
+/// `a` as zero when it is negligible next to `scale`, otherwise `snap`ped, so the result of
+/// an iterative method reads as exactly as it can.
+fn clean(a: f64, scale: f64) -> f64 {
+    if a.abs() <= scale * TOLERANCE { 0. } else { snap(a, scale) }
+}
+
+fn largest(a: &[Vec<f64>]) -> f64 {
+    a.iter().flatten().fold(0., |largest, a| a.abs().max(largest))
+}
+
+fn symmetric(a: &[Vec<f64>], scale: f64) -> bool {
+    (0..a.len()).all(|i| (0..i).all(|j| (a[i][j] - a[j][i]).abs() <= scale * TOLERANCE))
+}
+
+fn transpose_floats(a: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    (0..a[0].len()).map(|column| a.iter().map(|row| row[column]).collect()).collect()
+}
+
+/// The eigenvalues of a symmetric matrix and its eigenvectors as columns, by cyclic Jacobi
+/// rotations, which keep the eigenvectors orthonormal even for repeated eigenvalues.
+fn symmetric_eigen(mut a: Vec<Vec<f64>>) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let size = a.len();
+    let mut vectors: Vec<Vec<f64>> = identity(size);
+    for _ in 0..MAX_ITERATIONS {
+        let total = a.iter().flatten().map(|a| a * a).sum::<f64>();
+        let off_diagonal = (0..size).flat_map(|i| (0..size).filter(move |j| *j != i).map(move |j| (i, j))).map(|(i, j)| a[i][j] * a[i][j]).sum::<f64>();
+        if off_diagonal <= f64::EPSILON * f64::EPSILON * total {
+            break;
+        }
+        for p in 0..size {
+            for q in p + 1..size {
+                if a[p][q] == 0. {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2. * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+                let c = 1. / (t * t + 1.).sqrt();
+                let s = t * c;
+                for row in a.iter_mut().chain(vectors.iter_mut()) {
+                    let (x, y) = (row[p], row[q]);
+                    row[p] = c * x - s * y;
+                    row[q] = s * x + c * y;
+                }
+                let (upper, lower) = a.split_at_mut(q);
+                for (x, y) in upper[p].iter_mut().zip(lower[0].iter_mut()) {
+                    (*x, *y) = (c * *x - s * *y, s * *x + c * *y);
+                }
+            }
+        }
+    }
+    for column in 0..size {
+        let sign = vectors.iter().map(|row| row[column]).fold(0., |best: f64, a| if a.abs() > best.abs() { a } else { best }).signum();
+        vectors.iter_mut().for_each(|row| row[column] *= sign);
+    }
+    ((0..size).map(|k| a[k][k]).collect(), vectors)
+}
+
+/// The eigenvalues of a square matrix by the shifted QR algorithm on its Hessenberg form.
+/// Working in complex numbers lets complex conjugate pairs come out like any others. `None`
+/// when it doesn't converge.
+fn eigenvalues(a: &[Vec<f64>]) -> Option<Vec<Complex64>> {
+    let size = a.len();
+    let scale = largest(a);
+    let mut h: Vec<Vec<Complex64>> = a.iter().map(|row| row.iter().map(|a| Complex64::from(*a)).collect()).collect();
+    for column in 0..size.saturating_sub(2) {
+        for row in (column + 2..size).rev() {
+            if let Some((c, s)) = givens(h[row - 1][column], h[row][column]) {
+                rotate_rows(&mut h, row - 1, c, s, 0..size);
+                rotate_columns(&mut h, row - 1, c, s, 0..size);
+            }
+        }
+    }
+    let mut high = size - 1;
+    let mut iterations = 0;
+    while high > 0 {
+        let mut low = high;
+        while low > 0 && h[low][low - 1].norm() > f64::EPSILON * (h[low - 1][low - 1].norm() + h[low][low].norm()).max(f64::EPSILON * scale) {
+            low -= 1;
+        }
+        if low > 0 {
+            h[low][low - 1] = Complex64::zero();
+        }
+        if low == high {
+            high -= 1;
+            iterations = 0;
+            continue;
+        }
+        iterations += 1;
+        if iterations > MAX_ITERATIONS {
+            return None;
+        }
+        // Every so often an unusual shift breaks cycles that the usual one can fall into.
+        let shift = match iterations % 11 {
+            0 => h[high][high] + 0.75 * h[high][high - 1].norm(),
+            _ => wilkinson_shift(h[high - 1][high - 1], h[high - 1][high], h[high][high - 1], h[high][high]),
+        };
+        (low..=high).for_each(|k| h[k][k] -= shift);
+        let mut rotations = Vec::new();
+        for k in low..high {
+            let rotation = givens(h[k][k], h[k + 1][k]);
+            if let Some((c, s)) = rotation {
+                rotate_rows(&mut h, k, c, s, k..high + 1);
+            }
+            rotations.push((k, rotation));
+        }
+        for (k, rotation) in rotations {
+            if let Some((c, s)) = rotation {
+                rotate_columns(&mut h, k, c, s, low..k + 2);
+            }
+        }
+        (low..=high).for_each(|k| h[k][k] += shift);
+    }
+    Some((0..size).map(|k| h[k][k]).collect())
+}
+
+/// The eigenvalue of `[[a, b], [c, d]]` closest to `d`.
+fn wilkinson_shift(a: Complex64, b: Complex64, c: Complex64, d: Complex64) -> Complex64 {
+    let middle = (a + d) / 2.;
+    let root = ((a - d) * (a - d) / 4. + b * c).sqrt();
+    if (middle + root - d).norm() < (middle - root - d).norm() { middle + root } else { middle - root }
+}
+
+/// The rotation `[[conj(c), conj(s)], [-s, c]]` taking `[a, b]` to `[r, 0]`, or `None` when
+/// both are zero.
+fn givens(a: Complex64, b: Complex64) -> Option<(Complex64, Complex64)> {
+    let r = a.norm().hypot(b.norm());
+    (r != 0.).then(|| (a / r, b / r))
+}
+
+/// Applies the `givens` rotation to rows `k` and `k + 1`, in `columns`.
+fn rotate_rows(h: &mut [Vec<Complex64>], k: usize, c: Complex64, s: Complex64, columns: std::ops::Range<usize>) {
+    for j in columns {
+        let (x, y) = (h[k][j], h[k + 1][j]);
+        h[k][j] = c.conj() * x + s.conj() * y;
+        h[k + 1][j] = -s * x + c * y;
+    }
+}
+
+/// Applies the inverse of the `givens` rotation to columns `k` and `k + 1`, in `rows`.
+fn rotate_columns(h: &mut [Vec<Complex64>], k: usize, c: Complex64, s: Complex64, rows: std::ops::Range<usize>) {
+    for row in &mut h[rows] {
+        let (x, y) = (row[k], row[k + 1]);
+        row[k] = x * c + y * s;
+        row[k + 1] = -x * s.conj() + y * c.conj();
+    }
+}
+
+/// A unit eigenvector of `a` for the eigenvalue `value` by inverse iteration, trying other
+/// starting vectors when the first is no good. Its largest element is real and positive.
+fn eigenvector(a: &[Vec<f64>], value: Complex64, scale: f64) -> Vec<Complex64> {
+    let size = a.len();
+    let shifted: Vec<Vec<Complex64>> = a.iter().enumerate().map(|(i, row)| row.iter().enumerate().map(|(j, a)| {
+        if i == j { a - value } else { Complex64::from(*a) }
+    }).collect()).collect();
+    let starts = (0..=size).map(|start| (0..size).map(|k| match start {
+        0 => Complex64::from(1. / (k + 1) as f64 + 0.5),
+        start => Complex64::from((k == start - 1) as u8 as f64),
+    }).collect::<Vec<_>>());
+    let mut best: Option<(f64, Vec<Complex64>)> = None;
+    for mut vector in starts {
+        for _ in 0..3 {
+            vector = normalize(solve_shifted(shifted.clone(), vector, scale));
+        }
+        let residual = shifted.iter().map(|row| row.iter().zip(&vector).map(|(a, b)| a * b).sum::<Complex64>().norm_sqr()).sum::<f64>().sqrt();
+        if best.as_ref().is_none_or(|(best, _)| residual < *best) {
+            best = Some((residual, vector));
+        }
+        if residual <= scale.max(f64::MIN_POSITIVE) * TOLERANCE {
+            break;
+        }
+    }
+    let vector = best.unwrap().1;
+    let largest = vector.iter().fold(Complex64::zero(), |best, a| if a.norm() > best.norm() { *a } else { best });
+    let phase = largest.conj() / largest.norm();
+    vector.into_iter().map(|a| a * phase).collect()
+}
+
+fn normalize(vector: Vec<Complex64>) -> Vec<Complex64> {
+    let norm = vector.iter().map(|a| a.norm_sqr()).sum::<f64>().sqrt();
+    vector.into_iter().map(|a| a / norm).collect()
+}
+
+/// The `x` with `a x = b` for `a` shifted by an eigenvalue, so nearly singular. Pivots too
+/// small to divide by are nudged away from zero, which is what makes inverse iteration grow
+/// the eigenvector.
+fn solve_shifted(mut a: Vec<Vec<Complex64>>, mut b: Vec<Complex64>, scale: f64) -> Vec<Complex64> {
+    let size = a.len();
+    let smallest = f64::EPSILON * scale.max(f64::MIN_POSITIVE);
+    for column in 0..size {
+        let best = (column..size).max_by(|x, y| a[*x][column].norm().total_cmp(&a[*y][column].norm())).unwrap();
+        a.swap(column, best);
+        b.swap(column, best);
+        if a[column][column].norm() < smallest {
+            a[column][column] = Complex64::from(smallest);
+        }
+        let pivot_row = a[column].clone();
+        for row in column + 1..size {
+            let factor = a[row][column] / pivot_row[column];
+            a[row].iter_mut().zip(&pivot_row).skip(column).for_each(|(a, b)| *a -= factor * b);
+            b[row] = b[row] - factor * b[column];
+        }
+    }
+    let mut x = vec![Complex64::zero(); size];
+    for row in (0..size).rev() {
+        let sum = (row + 1..size).map(|j| a[row][j] * x[j]).sum::<Complex64>();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    x
+}
+
+/// `[U, s, V]` for a matrix with at least as many rows as columns, by one-sided Jacobi
+/// rotations of its columns until they are orthogonal. The columns of `U` for zero singular
+/// values are filled in to keep them orthonormal.
+fn singular_values(mut u: Vec<Vec<f64>>) -> (Vec<Vec<f64>>, Vec<f64>, Vec<Vec<f64>>) {
+    let (rows, columns) = (u.len(), u[0].len());
+    let mut v: Vec<Vec<f64>> = identity(columns);
+    for _ in 0..MAX_ITERATIONS {
+        let mut rotated = false;
+        for p in 0..columns {
+            for q in p + 1..columns {
+                let (alpha, beta, gamma) = u.iter().fold((0., 0., 0.), |(alpha, beta, gamma), row| {
+                    (alpha + row[p] * row[p], beta + row[q] * row[q], gamma + row[p] * row[q])
+                });
+                if gamma == 0. || gamma.abs() <= f64::EPSILON * (alpha * beta).sqrt() {
+                    continue;
+                }
+                rotated = true;
+                let zeta = (beta - alpha) / (2. * gamma);
+                let t = zeta.signum() / (zeta.abs() + (zeta * zeta + 1.).sqrt());
+                let c = 1. / (t * t + 1.).sqrt();
+                let s = t * c;
+                for row in u.iter_mut().chain(v.iter_mut()) {
+                    let (x, y) = (row[p], row[q]);
+                    row[p] = c * x - s * y;
+                    row[q] = s * x + c * y;
+                }
+            }
+        }
+        if !rotated {
+            break;
+        }
+    }
+    let norms: Vec<f64> = (0..columns).map(|j| u.iter().map(|row| row[j] * row[j]).sum::<f64>().sqrt()).collect();
+    let mut order: Vec<usize> = (0..columns).collect();
+    order.sort_by(|a, b| norms[*b].total_cmp(&norms[*a]));
+    let values: Vec<f64> = order.iter().map(|j| norms[*j]).collect();
+    let mut left: Vec<Vec<f64>> = u.iter().map(|row| order.iter().map(|j| row[*j] / norms[*j]).collect()).collect();
+    let right = v.iter().map(|row| order.iter().map(|j| row[*j]).collect()).collect();
+    for j in 0..columns {
+        if values[j] > values[0] * TOLERANCE {
+            continue;
+        }
+        let candidates = (0..rows).map(|unit| {
+            let mut column: Vec<f64> = (0..rows).map(|k| (k == unit) as u8 as f64).collect();
+            for k in 0..j {
+                let dot = left.iter().zip(&column).map(|(row, a)| row[k] * a).sum::<f64>();
+                column.iter_mut().zip(&left).for_each(|(a, row)| *a -= dot * row[k]);
+            }
+            column
+        });
+        let column = candidates.max_by(|a, b| a.iter().map(|a| a * a).sum::<f64>().total_cmp(&b.iter().map(|a| a * a).sum::<f64>())).unwrap();
+        let norm = column.iter().map(|a| a * a).sum::<f64>().sqrt();
+        left.iter_mut().zip(column).for_each(|(row, a)| row[j] = a / norm);
+    }
+    (left, values, right)
+}
+
+/// `[L, U, P]` by elimination with partial pivoting, see `lu`.
+fn lower_upper<T: Scalar>(mut u: Vec<Vec<T>>) -> [Vec<Vec<T>>; 3] {
+    let (rows, columns) = (u.len(), u[0].len());
+    let size = rows.min(columns);
+    let mut l = vec![vec![T::zero(); size]; rows];
+    let mut order: Vec<usize> = (0..rows).collect();
+    for column in 0..size {
+        let best = (column..rows).max_by(|a, b| u[*a][column].abs().partial_cmp(&u[*b][column].abs()).unwrap_or(Ordering::Equal)).unwrap();
+        u.swap(column, best);
+        l.swap(column, best);
+        order.swap(column, best);
+        l[column][column] = T::one();
+        if u[column][column].is_zero() {
+            continue;
+        }
+        let pivot_row = u[column].clone();
+        for row in column + 1..rows {
+            let factor = u[row][column].clone() / pivot_row[column].clone();
+            u[row].iter_mut().zip(&pivot_row).skip(column).for_each(|(a, b)| *a = a.clone() - factor.clone() * b.clone());
+            u[row][column] = T::zero();
+            l[row][column] = factor;
+        }
+    }
+    u.truncate(size);
+    let p = order.iter().map(|k| (0..rows).map(|j| if j == *k { T::one() } else { T::zero() }).collect()).collect();
+    [l, u, p]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::environment::Environment;
+    use crate::error::EvalError;
+    use crate::eval::tests::{evaluate, show};
+    use crate::matrix::eliminate;
+    use crate::tokens::Span;
+    use crate::value::{NumberMode, Value};
+
+    #[test]
+    fn elimination_treats_rounding_error_as_zero() {
+        let mut rows = vec![vec![0.1, 0.2, 0.3], vec![0.4, 0.5, 0.6], vec![0.7, 0.8, 0.9]];
+        let (pivots, determinant) = eliminate(&mut rows, 3);
+        assert_eq!((pivots, determinant), (vec![0, 1], 0.));
+        assert_eq!(rows[2], vec![0., 0., 0.]);
+        let mut rows = vec![vec![0., 2.], vec![3., 4.]];
+        assert_eq!(eliminate(&mut rows, 2), (vec![0, 1], -6.));
+        assert_eq!(rows, vec![vec![1., 0.], vec![0., 1.]]);
+    }
+
+    #[test]
+    fn matrix_arithmetic() {
+        let mut env = Environment::new();
+        env.set("A", Value::Matrix(vec![vec![Value::Number(1.), Value::Number(2.)], vec![Value::Number(3.), Value::Number(4.)]]));
+        assert_eq!(show("A + [[1, 1], [1, 1]] * 2", &env), Ok("[[3, 4], [5, 6]]".to_string()));
+        assert_eq!(show("A * A - A / 2", &env), Ok("[[6.5, 9], [13.5, 20]]".to_string()));
+        assert_eq!(show("A * [1, 1] + [1, 1] * A", &env), Ok("[7, 13]".to_string()));
+        assert_eq!(show("A^3 == A * A * A", &env), Ok("true".to_string()));
+        assert_eq!(show("A^-1 * A", &env), Ok("[[1, 0], [0, 1]]".to_string()));
+        assert_eq!(show("-A[1] + sqrt([4, 9]) + A[1:][0]", &env), Ok("[2, 3]".to_string()));
+        assert_eq!(show("sqrt([[4, 9], [16, 25]]) - A", &env), Ok("[[1, 1], [1, 1]]".to_string()));
+        assert_eq!(show("sum(A) + max(A)", &env), Ok("14".to_string()));
+    }
+
+    #[test]
+    fn matrix_functions() {
+        let mut env = Environment::new();
+        env.set("A", Value::Matrix(vec![vec![Value::Number(1.), Value::Number(2.)], vec![Value::Number(3.), Value::Number(4.)]]));
+        assert_eq!(show("transpose(A) + transpose([1, 2]) * [[0, 0]]", &env), Ok("[[1, 3], [2, 4]]".to_string()));
+        assert_eq!(show("det(A) + trace(A) + rank(A) + rank([[1, 2], [2, 4]])", &env), Ok("6".to_string()));
+        assert_eq!(show("inv(A)", &env), Ok("[[-2, 1], [1.5, -0.5]]".to_string()));
+        assert_eq!(show("rref([[1, 2, 3], [4, 5, 6], [7, 8, 9]])", &env), Ok("[[1, 0, -1], [0, 1, 2], [0, 0, 0]]".to_string()));
+        assert_eq!(show("det([[0.1, 0.2, 0.3], [0.4, 0.5, 0.6], [0.7, 0.8, 0.9]])", &env), Ok("0".to_string()));
+        assert_eq!(show("solve(A, [5, 11]) + transpose(solve(A, [[5], [11]]))[0]", &env), Ok("[2, 4]".to_string()));
+    }
+
+    #[test]
+    fn matrix_errors() {
+        let mut env = Environment::new();
+        env.set("A", Value::Matrix(vec![vec![Value::Number(1.), Value::Number(2.)], vec![Value::Number(3.), Value::Number(4.)]]));
+        assert_eq!(show("A + [[1, 2]]", &env), Err(EvalError::ShapeMismatch { left: (2, 2), right: (1, 2), span: Span::new(0, 12) }));
+        assert_eq!(show("A * [1, 2, 3]", &env), Err(EvalError::ShapeMismatch { left: (2, 2), right: (3, 1), span: Span::new(0, 13) }));
+        assert_eq!(show("det([[1, 2]])", &env), Err(EvalError::NotSquare { rows: 1, columns: 2, span: Span::new(0, 13) }));
+        assert_eq!(show("inv([[1, 2], [2, 4]])", &env), Err(EvalError::DomainError { name: "inv".to_string(), span: Span::new(0, 21) }));
+        assert_eq!(show("A + [1, 2]", &env), Err(EvalError::TypeMismatch { expected: "matrix", found: "list", span: Span::new(4, 10) }));
+        assert_eq!(show("A^0.5", &env), Err(EvalError::NotAnInteger { span: Span::new(2, 5) }));
+        assert_eq!(show("det(5)", &env), Err(EvalError::TypeMismatch { expected: "matrix", found: "number", span: Span::new(0, 6) }));
+    }
+
+    #[test]
+    fn matrices_stay_exact_in_rational_mode() {
+        let mut env = Environment::new();
+        env.set_number_mode(NumberMode::Rational);
+        assert_eq!(show("inv([[1, 2], [3, 4]])", &env), Ok("[[-2, 1], [3/2, -1/2]]".to_string()));
+        assert_eq!(show("solve([[3, 1], [1, 2]], [1, 1])", &env), Ok("[1/5, 2/5]".to_string()));
+        assert_eq!(show("[[1, 1], [1, 0]]^100", &env), Ok("[[573147844013817084101, 354224848179261915075], [354224848179261915075, 218922995834555169026]]".to_string()));
+    }
+
+    #[test]
+    fn eigen_decomposition() {
+        let mut env = Environment::new();
+        env.set("A", Value::Matrix(vec![vec![Value::Number(4.), Value::Number(1.), Value::Number(2.)], vec![Value::Number(1.), Value::Number(-3.), Value::Number(0.)], vec![Value::Number(2.), Value::Number(5.), Value::Number(7.)]]));
+        assert_eq!(show("eig([[2, 1], [1, 2]])", &env), Ok("[[3, 1], [[0.7071067811865475, 0.7071067811865475], [0.7071067811865475, -0.7071067811865475]]]".to_string()));
+        assert_eq!(show("eig([[0, 1], [-2, -3]])[0] + eig([[0, -1], [1, 0]])[0]", &env), Ok("[-1 + i, -2 - i]".to_string()));
+        assert_eq!(show("round(A * transpose(eig(A)[1])[2] - eig(A)[0][2] * transpose(eig(A)[1])[2], 9) == [0, 0, 0]", &env), Ok("true".to_string()));
+        assert_eq!(show("eig([[1, 2]])", &env), Err(EvalError::NotSquare { rows: 1, columns: 2, span: Span::new(0, 13) }));
+        assert_eq!(show("eig([[1e308, 1e308], [1e308, 1e308]])[0]", &env), Ok("[inf, 0]".to_string()));
+        env.set_number_mode(NumberMode::Rational);
+        assert!(evaluate("eig([[2, 1], [1, 2]])", &env).unwrap().is_approximate());
+    }
+
+    #[test]
+    fn qr_and_singular_value_decompositions() {
+        let mut env = Environment::new();
+        env.set("A", Value::Matrix(vec![vec![Value::Number(4.), Value::Number(1.), Value::Number(2.)], vec![Value::Number(1.), Value::Number(-3.), Value::Number(0.)], vec![Value::Number(2.), Value::Number(5.), Value::Number(7.)]]));
+        assert_eq!(show("round(transpose(qr(A)[0]) * qr(A)[0], 12) == [[1, 0, 0], [0, 1, 0], [0, 0, 1]]", &env), Ok("true".to_string()));
+        assert_eq!(show("round(qr(A)[0] * qr(A)[1], 12) == A", &env), Ok("true".to_string()));
+        assert_eq!(show("qr([[0, 1], [2, 0]])", &env), Ok("[[[0, 1], [1, 0]], [[2, 0], [0, 1]]]".to_string()));
+        assert_eq!(show("svd([[3, 0], [0, -4]])", &env), Ok("[[[0, 1], [-1, 0]], [4, 3], [[0, 1], [1, 0]]]".to_string()));
+        assert_eq!(show("round(svd(A)[1]^2 - eig(transpose(A) * A)[0], 9) == [0, 0, 0]", &env), Ok("true".to_string()));
+        assert_eq!(show("svd([[1, 2], [2, 4]])[1][1] + len(svd([[1, 2, 3]])[2])", &env), Ok("3".to_string()));
+    }
+
+    #[test]
+    fn lu_and_cholesky_decompositions() {
+        let mut env = Environment::new();
+        assert_eq!(show("lu([[1, 2], [3, 4]])", &env), Ok("[[[1, 0], [0.3333333333333333, 1]], [[3, 4], [0, 0.6666666666666666]], [[0, 1], [1, 0]]]".to_string()));
+        assert_eq!(show("cholesky([[4, 2], [2, 3]])[1][0]", &env), Ok("1".to_string()));
+        assert_eq!(show("cholesky([[1, 2], [2, 1]])", &env), Err(EvalError::DomainError { name: "cholesky".to_string(), span: Span::new(0, 26) }));
+        env.set_number_mode(NumberMode::Rational);
+        assert_eq!(show("lu([[2, 1], [4, 1]])", &env), Ok("[[[1, 0], [1/2, 1]], [[4, 1], [0, 1/2]], [[0, 1], [1, 0]]]".to_string()));
+    }
+}
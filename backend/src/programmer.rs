@@ -0,0 +1,356 @@
+use std::fmt::{Display, Formatter};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use crate::ast::{ASTNode, BinaryOperation, FunctionCall, UnaryOperation};
+use crate::environment::Environment;
+use crate::error::EvalError;
+use crate::constants::constant;
+use crate::math::is_integer;
+use crate::tokens::Span;
+
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Integer width used by programmer mode, e.g. `u8` or `i64`. Values are kept in an i128
+/// that is always wrapped into the range of the width.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IntWidth {
+    pub bits: u32,
+    pub signed: bool,
+}
+
+impl Default for IntWidth {
+    fn default() -> IntWidth {
+        IntWidth { bits: 64, signed: true }
+    }
+}
+
+impl Display for IntWidth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", if self.signed { "i" } else { "u" }, self.bits)
+    }
+}
+
+impl IntWidth {
+    pub fn parse(name: &str) -> Option<IntWidth> {
+        let signed = match name.chars().next()? {
+            'i' | 'I' => true,
+            'u' | 'U' => false,
+            _ => return None,
+        };
+        match name[1..].parse::<u32>() {
+            Ok(bits) if [8, 16, 32, 64].contains(&bits) => Some(IntWidth { bits, signed }),
+            _ => None,
+        }
+    }
+
+    fn mask(&self) -> u128 {
+        (1u128 << self.bits) - 1
+    }
+
+    /// Wraps `value` into this width the way two's complement hardware would.
+    pub fn wrap(&self, value: i128) -> i128 {
+        let bits = (value as u128) & self.mask();
+        if self.signed && bits >> (self.bits - 1) == 1 {
+            bits as i128 - (1i128 << self.bits)
+        } else {
+            bits as i128
+        }
+    }
+}
+
+/// Evaluates `ast` with integer semantics. Comparisons and `and`/`or`/`not` give 1 or 0,
+/// as in C, so results can still be shown in any base.
+pub fn evaluate_integer(ast: ASTNode, width: IntWidth, env: &Environment) -> Result<i128, EvalError> {
+    match ast {
+        ASTNode::BinaryNode(a) => {
+            let right_span = a.right.span();
+            let left = evaluate_integer(*a.left, width, env)?;
+            let right = evaluate_integer(*a.right, width, env)?;
+            let result = match a.operation {
+                BinaryOperation::Plus => left + right,
+                BinaryOperation::Minus => left - right,
+                BinaryOperation::Times => left.wrapping_mul(right),
+                BinaryOperation::Divide | BinaryOperation::Modulus if right == 0 => {
+                    return Err(EvalError::DivisionByZero { span: right_span })
+                }
+                BinaryOperation::Divide => left / right,
+                BinaryOperation::Modulus => left % right,
+                BinaryOperation::Exponent => power(left, right, width, right_span)?,
+                BinaryOperation::Phasor => return Err(EvalError::Unsupported { name: "∠".to_string(), mode: "programmer".to_string(), span: a.span }),
+                BinaryOperation::BitAnd => left & right,
+                BinaryOperation::BitOr => left | right,
+                BinaryOperation::ShiftLeft | BinaryOperation::ShiftRight if right < 0 => {
+                    return Err(EvalError::DomainError { name: "shift".to_string(), span: right_span })
+                }
+                BinaryOperation::ShiftLeft => shift(left, right, true, width),
+                BinaryOperation::ShiftRight => shift(left, right, false, width),
+                BinaryOperation::Less => (left < right) as i128,
+                BinaryOperation::Greater => (left > right) as i128,
+                BinaryOperation::LessEqual => (left <= right) as i128,
+                BinaryOperation::GreaterEqual => (left >= right) as i128,
+                BinaryOperation::Equal => (left == right) as i128,
+                BinaryOperation::NotEqual => (left != right) as i128,
+                BinaryOperation::And => (left != 0 && right != 0) as i128,
+                BinaryOperation::Or => (left != 0 || right != 0) as i128,
+            };
+            Ok(width.wrap(result))
+        }
+        ASTNode::UnaryNode(a) => {
+            let child = evaluate_integer(*a.child, width, env)?;
+            match a.operation {
+                UnaryOperation::Negate => Ok(width.wrap(-child)),
+                UnaryOperation::Parens => Ok(child),
+                UnaryOperation::BitNot => Ok(width.wrap(!child)),
+                UnaryOperation::Not => Ok((child == 0) as i128),
+                UnaryOperation::Factorial | UnaryOperation::DoubleFactorial | UnaryOperation::Percent => {
+                    let name = match a.operation {
+                        UnaryOperation::Factorial => "!",
+                        UnaryOperation::DoubleFactorial => "!!",
+                        _ => "%",
+                    };
+                    Err(EvalError::Unsupported { name: name.to_string(), mode: "programmer".to_string(), span: a.span })
+                }
+                UnaryOperation::SquareRoot => Err(EvalError::Unsupported { name: "√".to_string(), mode: "programmer".to_string(), span: a.span }),
+                UnaryOperation::Angle(unit) => Err(EvalError::Unsupported { name: unit.suffix().to_string(), mode: "programmer".to_string(), span: a.span }),
+            }
+        }
+        ASTNode::NumberNode(a, span) => {
+            if is_integer(a.value) {
+                Ok(width.wrap(a.value as i128))
+            } else {
+                Err(EvalError::NotAnInteger { span })
+            }
+        }
+        // Only the low 128 bits can survive wrapping to any width.
+        ASTNode::IntegerNode(a, _) => Ok(width.wrap((a & BigInt::from(u128::MAX)).to_u128().unwrap() as i128)),
+        ASTNode::FunctionCall(a) => evaluate_integer_function(a, width, env),
+        ASTNode::List(_, span) => Err(EvalError::Unsupported { name: "[".to_string(), mode: "programmer".to_string(), span }),
+        ASTNode::Index(a) => Err(EvalError::Unsupported { name: "[".to_string(), mode: "programmer".to_string(), span: a.span }),
+        // Assignments in programmer mode store an `Integer`, which reads back exactly.
+        ASTNode::Variable(name, span) => match env.get(&name) {
+            Some(value) => match (value.integer().and_then(|a| a.to_i128()), value.clone().number(span)?) {
+                (Some(a), _) => Ok(width.wrap(a)),
+                (None, a) if is_integer(a) => Ok(width.wrap(a as i128)),
+                _ => Err(EvalError::NotAnInteger { span }),
+            },
+            None if constant(&name).is_some() => Err(EvalError::NotAnInteger { span }),
+            None => Err(EvalError::UndefinedVariable { name, span }),
+        },
+    }
+}
+
+/// Calls a function that has an integer version in the registry, checking its arity and
+/// domain as in the other modes.
+fn evaluate_integer_function(function_call: FunctionCall, width: IntWidth, env: &Environment) -> Result<i128, EvalError> {
+    let name = function_call.operation;
+    let span = function_call.span;
+    let Some((entry, integer)) = env.registry().get(&name).and_then(|a| Some((a, a.integer?))) else {
+        return Err(EvalError::Unsupported { name, mode: "programmer".to_string(), span });
+    };
+    let mut paramaters = Vec::new();
+    for child in function_call.inputs {
+        paramaters.push(evaluate_integer(child, width, env)?);
+    }
+    entry.check(&name, &paramaters.iter().map(|a| *a as f64).collect::<Vec<f64>>(), span)?;
+    Ok(width.wrap(integer(&paramaters, width)))
+}
+
+pub fn not(n: &[i128], _: IntWidth) -> i128 {
+    !n[0]
+}
+
+pub fn abs(n: &[i128], _: IntWidth) -> i128 {
+    n[0].abs()
+}
+
+pub fn shl(n: &[i128], width: IntWidth) -> i128 {
+    shift(n[0], n[1], true, width)
+}
+
+pub fn shr(n: &[i128], width: IntWidth) -> i128 {
+    shift(n[0], n[1], false, width)
+}
+
+pub fn and(n: &[i128], _: IntWidth) -> i128 {
+    n.iter().fold(-1, |acc, x| acc & x)
+}
+
+pub fn or(n: &[i128], _: IntWidth) -> i128 {
+    n.iter().fold(0, |acc, x| acc | x)
+}
+
+pub fn xor(n: &[i128], _: IntWidth) -> i128 {
+    n.iter().fold(0, |acc, x| acc ^ x)
+}
+
+pub fn sum(n: &[i128], width: IntWidth) -> i128 {
+    n.iter().fold(0, |acc, x| width.wrap(acc + x))
+}
+
+pub fn min(n: &[i128], _: IntWidth) -> i128 {
+    *n.iter().min().unwrap()
+}
+
+pub fn max(n: &[i128], _: IntWidth) -> i128 {
+    *n.iter().max().unwrap()
+}
+
+fn power(base: i128, exponent: i128, width: IntWidth, span: Span) -> Result<i128, EvalError> {
+    if exponent < 0 {
+        return Err(EvalError::DomainError { name: "^".to_string(), span });
+    }
+    let mut result: i128 = 1;
+    let mut base = width.wrap(base);
+    let mut exponent = exponent;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = width.wrap(result.wrapping_mul(base));
+        }
+        base = width.wrap(base.wrapping_mul(base));
+        exponent >>= 1;
+    }
+    Ok(result)
+}
+
+/// Shifts within the width. Right shifts are arithmetic for signed widths and logical for
+/// unsigned ones, since the value is already wrapped to the width's range. `amount` is not
+/// negative.
+fn shift(value: i128, amount: i128, left: bool, width: IntWidth) -> i128 {
+    if amount >= width.bits as i128 {
+        return if !left && value < 0 { -1 } else { 0 };
+    }
+    if left {
+        width.wrap(value << amount)
+    } else {
+        value >> amount
+    }
+}
+
+/// Formats a programmer mode result. Base 10 shows the signed value, other bases show the
+/// raw bit pattern of the width, so `-1` as `u8`/`i8` in base 16 is `0xFF`.
+pub fn format_integer(value: i128, width: IntWidth, base: u32) -> String {
+    if base == 10 {
+        return value.to_string();
+    }
+    let mut bits = (value as u128) & width.mask();
+    let mut digits = vec![];
+    loop {
+        digits.push(DIGITS[(bits % base as u128) as usize]);
+        bits /= base as u128;
+        if bits == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    let digits = String::from_utf8(digits).unwrap();
+    match base {
+        2 => format!("0b{}", digits),
+        8 => format!("0o{}", digits),
+        16 => format!("0x{}", digits),
+        _ => format!("{} (base {})", digits, base),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::build_ast;
+    use crate::environment::Environment;
+    use crate::error::EvalError;
+    use crate::programmer::{evaluate_integer, format_integer, IntWidth};
+    use crate::registry::FunctionEntry;
+    use crate::tokens::{Span, tokenize, tokenize_with_functions};
+    use crate::value::Value;
+    use num_bigint::BigInt;
+
+    fn evaluate(input: &str, width: &str) -> Result<i128, EvalError> {
+        let ast = build_ast(tokenize(input.to_string()).unwrap()).unwrap();
+        evaluate_integer(ast, IntWidth::parse(width).unwrap(), &Environment::new())
+    }
+
+    #[test]
+    fn widths_parse() {
+        assert_eq!(IntWidth::parse("u8"), Some(IntWidth { bits: 8, signed: false }));
+        assert_eq!(IntWidth::parse("i32"), Some(IntWidth { bits: 32, signed: true }));
+        assert_eq!(IntWidth::parse("u7"), None);
+        assert_eq!(IntWidth::parse("f32"), None);
+    }
+
+    #[test]
+    fn arithmetic_wraps_at_width() {
+        assert_eq!(evaluate("255 + 1", "u8"), Ok(0));
+        assert_eq!(evaluate("127 + 1", "i8"), Ok(-128));
+        assert_eq!(evaluate("0 - 1", "u16"), Ok(65535));
+        assert_eq!(evaluate("0xFF", "i8"), Ok(-1));
+        assert_eq!(evaluate("2^64", "u64"), Ok(0));
+        assert_eq!(evaluate("0xFFFFFFFFFFFFFFFF * 2", "u64"), Ok(u64::MAX as i128 - 1));
+        assert_eq!(evaluate("18446744073709551615", "u64"), Ok(u64::MAX as i128));
+        assert_eq!(evaluate("9007199254740993", "i64"), Ok(9007199254740993));
+        assert_eq!(evaluate("340282366920938463463374607431768211457", "u8"), Ok(1));
+        assert_eq!(evaluate("1.5", "u8"), Err(EvalError::NotAnInteger { span: Span::new(0, 3) }));
+        assert_eq!(evaluate("7 / 2", "i32"), Ok(3));
+        assert_eq!(evaluate("-7 % 3", "i32"), Ok(-1));
+    }
+
+    #[test]
+    fn bitwise_operations() {
+        assert_eq!(evaluate("0xF0 | 0x0F", "u8"), Ok(0xFF));
+        assert_eq!(evaluate("~0", "u8"), Ok(0xFF));
+        assert_eq!(evaluate("~0", "i8"), Ok(-1));
+        assert_eq!(evaluate("1 << 7", "u8"), Ok(128));
+        assert_eq!(evaluate("1 << 8", "u8"), Ok(0));
+        assert_eq!(evaluate("0x80 >> 7", "i8"), Ok(-1));
+        assert_eq!(evaluate("0x80 >> 7", "u8"), Ok(1));
+        assert_eq!(evaluate("xor(0b1100, 0b1010)", "u8"), Ok(0b0110));
+        assert_eq!(evaluate("and(0xFF, 0x0F, 0x3)", "u8"), Ok(3));
+        assert_eq!(evaluate("not(0x0F)", "u8"), Ok(0xF0));
+        assert_eq!(evaluate("shl(1, 4) + shr(0x100, 4)", "u16"), Ok(32));
+        assert_eq!(evaluate("0xFF == 255 and not 1 > 2", "u8"), Ok(1));
+        assert_eq!(evaluate("(1 < 2) + (3 != 3)", "u8"), Ok(1));
+    }
+
+    #[test]
+    fn programmer_mode_errors() {
+        assert_eq!(evaluate("1 / 0", "i32"), Err(EvalError::DivisionByZero { span: Span::new(4, 5) }));
+        assert_eq!(evaluate("1.5 + 1", "i32"), Err(EvalError::NotAnInteger { span: Span::new(0, 3) }));
+        assert_eq!(evaluate("sin(1)", "i32"), Err(EvalError::Unsupported { name: "sin".to_string(), mode: "programmer".to_string(), span: Span::new(0, 6) }));
+        assert_eq!(evaluate("√4", "i32"), Err(EvalError::Unsupported { name: "√".to_string(), mode: "programmer".to_string(), span: Span::new(0, 4) }));
+        assert_eq!(evaluate("not(1, 2)", "i32"), Err(EvalError::ArityMismatch { name: "not".to_string(), min: 1, max: Some(1), found: 2, span: Span::new(0, 9) }));
+        assert_eq!(evaluate("shl(1, -1)", "i32"), Err(EvalError::DomainError { name: "shl".to_string(), span: Span::new(0, 10) }));
+        assert_eq!(evaluate("1 << -1", "i32"), Err(EvalError::DomainError { name: "shift".to_string(), span: Span::new(5, 7) }));
+    }
+
+    #[test]
+    fn registered_functions_with_an_integer_version() {
+        let mut env = Environment::new();
+        env.registry_mut().register(FunctionEntry::one_to_one("popcount", "Bitwise", "popcount(a) number of set bits", |x| x).with_integer(|n, width| ((n[0] as u128) & ((1u128 << width.bits) - 1)).count_ones() as i128));
+        let evaluate = |input: &str, width: &str| evaluate_integer(build_ast(tokenize_with_functions(input.to_string(), env.registry(), &env.user_function_names()).unwrap()).unwrap(), IntWidth::parse(width).unwrap(), &env);
+        assert_eq!(evaluate("popcount(-1)", "i8"), Ok(8));
+        assert_eq!(evaluate("popcount(0xF0F0)", "u16"), Ok(8));
+        assert_eq!(evaluate("max(3, 9, 4) - min(3, 9, 4) + abs(-2)", "i8"), Ok(8));
+        assert_eq!(evaluate("sum(127, 1)", "i8"), Ok(-128));
+    }
+
+    #[test]
+    fn integer_variables_read_back_exactly() {
+        let mut env = Environment::new();
+        env.set("a", Value::Integer(BigInt::from(0x1234567890ABCDEFi128)));
+        env.set("b", Value::Integer(BigInt::from(u64::MAX)));
+        let evaluate = |input: &str, width: &str| evaluate_integer(build_ast(tokenize(input.to_string()).unwrap()).unwrap(), IntWidth::parse(width).unwrap(), &env);
+        assert_eq!(evaluate("a", "i64"), Ok(0x1234567890ABCDEF));
+        assert_eq!(evaluate("b", "u64"), Ok(u64::MAX as i128));
+        assert_eq!(evaluate("b", "i64"), Ok(-1));
+        assert_eq!(evaluate("a & 0xFF", "u8"), Ok(0xEF));
+    }
+
+    #[test]
+    fn results_format_in_any_base() {
+        let width = IntWidth::parse("u8").unwrap();
+        assert_eq!(format_integer(255, width, 16), "0xFF");
+        assert_eq!(format_integer(5, width, 2), "0b101");
+        assert_eq!(format_integer(8, width, 8), "0o10");
+        assert_eq!(format_integer(35, width, 36), "Z (base 36)");
+        let width = IntWidth::parse("i8").unwrap();
+        assert_eq!(format_integer(-1, width, 16), "0xFF");
+        assert_eq!(format_integer(-1, width, 10), "-1");
+    }
+}
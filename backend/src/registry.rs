@@ -0,0 +1,417 @@
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use crate::{decimal, list, matrix, statistics};
+use crate::error::EvalError;
+use crate::math::{AngleMode, any_real, at_least_one, at_least_two, atan2, average, bit_and, bit_not, bit_or, bit_xor, combinations, combinations_domain, exact_combinations, exact_factorial, exact_permutations, factorial, factorial_domain, gamma, gamma_domain, hypot, integer_and_shift, integers, kurtosis, ln_gamma, log, logarithm, max, median, min, mode, not_all_equal, open_unit_interval, permutations, positive, rational_abs, rational_average, rational_ceil, rational_floor, rational_max, rational_median, rational_min, rational_mode, rational_round, rational_sample_variance, rational_sign, rational_sum, rational_variance, round, round_digits, sample_standard_deviation, sample_variance, shift_left, shift_right, sign, skewness, snap, snap_complex, standard_deviation, sum, unit_interval, variance};
+use crate::programmer::{self, IntWidth};
+use crate::tokens::Span;
+use crate::value::{NumberMode, Value};
+
+/// How a function computes its result from its evaluated arguments.
+#[derive(Clone, Copy, Debug)]
+pub enum Implementation {
+    OneToOne(fn(f64) -> f64),
+    MultiToOne(fn(Vec<f64>) -> f64),
+    /// An exact integer result where `exact` gives one, otherwise `approximate`.
+    Exact { exact: fn(&[f64]) -> Option<BigInt>, approximate: fn(Vec<f64>) -> f64 },
+    /// Evaluated by the evaluator itself because only some arguments are evaluated, as in `if`.
+    Conditional,
+    /// Evaluated by the evaluator itself because the first argument names a function, as in
+    /// `map`.
+    Map,
+    /// Takes the arguments whole rather than as real numbers, for functions of lists. Results
+    /// are numbers in the given mode.
+    Values(fn(Vec<Value>, NumberMode, Span) -> Result<Value, EvalError>),
+}
+
+/// Whether a function's argument or result is an angle in the session's `AngleMode`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AngleUse {
+    None,
+    /// The first argument is an angle, as for `sin`.
+    Argument,
+    /// The result is an angle, as for `asin`.
+    Result,
+}
+
+/// Checks arguments that are already known to be the right count, reporting problems as an
+/// `EvalError` for the function `name` at `span`.
+pub type DomainCheck = fn(&[f64], &str, Span) -> Result<(), EvalError>;
+
+/// Decimal mode version of a function, given the significant digits wanted. Where it returns
+/// `None` floating point takes over.
+pub type DecimalFunction = fn(&[BigDecimal], u64) -> Option<BigDecimal>;
+
+/// Programmer mode version of a function, on arguments already wrapped to the width. The
+/// result is wrapped afterwards.
+pub type IntegerFunction = fn(&[i128], IntWidth) -> i128;
+
+/// Everything the calculator knows about one callable function.
+#[derive(Clone, Debug)]
+pub struct FunctionEntry {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub min_arity: usize,
+    pub max_arity: Option<usize>,
+    pub domain: DomainCheck,
+    pub implementation: Implementation,
+    pub angle: AngleUse,
+    /// Exact version used in rational mode when every argument is exact.
+    pub rational: Option<fn(&[BigRational]) -> BigRational>,
+    /// Arbitrary precision version used in decimal mode.
+    pub decimal: Option<DecimalFunction>,
+    /// Version for complex arguments, also used where the real version gives NaN for real
+    /// arguments, as `sqrt` does for negative ones.
+    pub complex: Option<fn(&[Complex64]) -> Complex64>,
+    /// Integer version, the only one programmer mode can call.
+    pub integer: Option<IntegerFunction>,
+    /// Heading the function is listed under in help.
+    pub category: String,
+    /// Usage and a short description, e.g. `sin(t) sine of t radians`.
+    pub doc: String,
+}
+
+impl FunctionEntry {
+    pub fn new(name: &str, category: &str, doc: &str, implementation: Implementation, min_arity: usize, max_arity: Option<usize>) -> FunctionEntry {
+        FunctionEntry {
+            name: name.to_string(),
+            aliases: vec![],
+            min_arity,
+            max_arity,
+            domain: any_real,
+            implementation,
+            angle: AngleUse::None,
+            rational: None,
+            decimal: None,
+            complex: None,
+            integer: None,
+            category: category.to_string(),
+            doc: doc.to_string(),
+        }
+    }
+
+    /// A function of exactly one real argument.
+    pub fn one_to_one(name: &str, category: &str, doc: &str, implementation: fn(f64) -> f64) -> FunctionEntry {
+        FunctionEntry::new(name, category, doc, Implementation::OneToOne(implementation), 1, Some(1))
+    }
+
+    /// A function of one or more real arguments with exact integer results where possible.
+    pub fn exact(name: &str, category: &str, doc: &str, exact: fn(&[f64]) -> Option<BigInt>, approximate: fn(Vec<f64>) -> f64) -> FunctionEntry {
+        FunctionEntry::new(name, category, doc, Implementation::Exact { exact, approximate }, 1, None)
+    }
+
+    /// A function of one or more real arguments.
+    pub fn multi_to_one(name: &str, category: &str, doc: &str, implementation: fn(Vec<f64>) -> f64) -> FunctionEntry {
+        FunctionEntry::new(name, category, doc, Implementation::MultiToOne(implementation), 1, None)
+    }
+
+    pub fn with_aliases(mut self, aliases: &[&str]) -> FunctionEntry {
+        self.aliases = aliases.iter().map(|a| a.to_string()).collect();
+        self
+    }
+
+    pub fn with_arity(mut self, min: usize, max: Option<usize>) -> FunctionEntry {
+        self.min_arity = min;
+        self.max_arity = max;
+        self
+    }
+
+    pub fn with_domain(mut self, domain: DomainCheck) -> FunctionEntry {
+        self.domain = domain;
+        self
+    }
+
+    pub fn takes_angle(mut self) -> FunctionEntry {
+        self.angle = AngleUse::Argument;
+        self
+    }
+
+    pub fn returns_angle(mut self) -> FunctionEntry {
+        self.angle = AngleUse::Result;
+        self
+    }
+
+    pub fn with_rational(mut self, rational: fn(&[BigRational]) -> BigRational) -> FunctionEntry {
+        self.rational = Some(rational);
+        self
+    }
+
+    pub fn with_decimal(mut self, decimal: DecimalFunction) -> FunctionEntry {
+        self.decimal = Some(decimal);
+        self
+    }
+
+    pub fn with_complex(mut self, complex: fn(&[Complex64]) -> Complex64) -> FunctionEntry {
+        self.complex = Some(complex);
+        self
+    }
+
+    pub fn with_integer(mut self, integer: IntegerFunction) -> FunctionEntry {
+        self.integer = Some(integer);
+        self
+    }
+
+    /// Checks the argument count and then the domain, naming the function as it was called.
+    pub fn check(&self, called_as: &str, args: &[f64], span: Span) -> Result<(), EvalError> {
+        self.check_arity(called_as, args.len(), span)?;
+        (self.domain)(args, called_as, span)
+    }
+
+    /// Checks the argument count alone, for complex arguments, which have no domain check.
+    pub fn check_arity(&self, called_as: &str, count: usize, span: Span) -> Result<(), EvalError> {
+        if count < self.min_arity || self.max_arity.is_some_and(|max| count > max) {
+            return Err(EvalError::ArityMismatch { name: called_as.to_string(), min: self.min_arity, max: self.max_arity, found: count, span });
+        }
+        Ok(())
+    }
+
+    /// Applies the implementation to arguments that passed `check`, with angles in `angles`
+    /// units.
+    pub fn call(&self, mut args: Vec<f64>, angles: AngleMode) -> Value {
+        if self.angle == AngleUse::Argument {
+            args[0] = angles.to_radians(args[0]);
+        }
+        let radians = args.first().map_or(0., |a| a.abs());
+        let result = match self.implementation {
+            Implementation::OneToOne(f) => f(args[0]),
+            Implementation::MultiToOne(f) => f(args),
+            Implementation::Exact { exact, approximate } => match exact(&args) {
+                Some(a) => return Value::Integer(a),
+                None => approximate(args),
+            },
+            Implementation::Conditional | Implementation::Map | Implementation::Values(_) => {
+                unreachable!("conditional, map and list functions are evaluated by the evaluator")
+            }
+        };
+        Value::Number(match self.angle {
+            AngleUse::None => result,
+            AngleUse::Argument => snap(result, radians * result.abs().max(1.)),
+            AngleUse::Result if angles == AngleMode::Radians => result,
+            AngleUse::Result => {
+                let angle = angles.from_radians(result);
+                snap(angle, angle.abs())
+            }
+        })
+    }
+
+    /// `call` on complex arguments, or `None` for a function with no complex version.
+    pub fn call_complex(&self, mut args: Vec<Complex64>, angles: AngleMode) -> Option<Value> {
+        let complex = self.complex?;
+        if self.angle == AngleUse::Argument {
+            args[0] = Complex64::new(angles.to_radians(args[0].re), angles.to_radians(args[0].im));
+        }
+        let size = args.first().map_or(0., |a| a.norm());
+        let mut result = complex(&args);
+        if self.angle == AngleUse::Result {
+            result = Complex64::new(angles.from_radians(result.re), angles.from_radians(result.im));
+        }
+        Some(Value::from_complex(snap_complex(result, size * result.norm().max(1.))))
+    }
+
+    /// `call` in decimal mode, rounded to `precision` significant digits, or `None` where the
+    /// function has no decimal version for these arguments.
+    pub fn call_decimal(&self, mut args: Vec<BigDecimal>, angles: AngleMode, precision: u64) -> Option<Value> {
+        let decimal = self.decimal?;
+        if self.angle == AngleUse::Argument {
+            args[0] = decimal::convert(&args[0], angles, AngleMode::Radians, precision);
+        }
+        let radians = args.first().map_or_else(BigDecimal::default, |a| a.abs());
+        let result = decimal(&args, precision)?;
+        let result = match self.angle {
+            AngleUse::None => result,
+            AngleUse::Argument => decimal::snap(&result, &(radians * result.abs().max(1.into())), precision),
+            AngleUse::Result if angles == AngleMode::Radians => result,
+            AngleUse::Result => {
+                let angle = decimal::convert(&result, AngleMode::Radians, angles, precision);
+                decimal::snap(&angle, &angle.abs(), precision)
+            }
+        };
+        Some(Value::Decimal(decimal::round(&result, precision)))
+    }
+}
+
+/// The functions that can be called by name. The tokenizer, evaluator and help all read
+/// from a registry, and embedding code can `register` more at runtime.
+#[derive(Clone, Debug, Default)]
+pub struct Registry {
+    entries: Vec<FunctionEntry>,
+    names: HashMap<String, usize>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// The built-in functions, shared by every environment that doesn't register its own.
+    pub fn builtin() -> Arc<Registry> {
+        static BUILTIN: LazyLock<Arc<Registry>> = LazyLock::new(|| Arc::new(builtin_functions()));
+        BUILTIN.clone()
+    }
+
+    /// Adds `entry`, replacing any function that shares its name or one of its aliases.
+    pub fn register(&mut self, entry: FunctionEntry) {
+        let names: Vec<&String> = std::iter::once(&entry.name).chain(&entry.aliases).collect();
+        self.entries.retain(|a| !std::iter::once(&a.name).chain(&a.aliases).any(|a| names.contains(&a)));
+        self.entries.push(entry);
+        self.names.clear();
+        for (index, entry) in self.entries.iter().enumerate() {
+            for name in std::iter::once(&entry.name).chain(&entry.aliases) {
+                self.names.insert(name.clone(), index);
+            }
+        }
+    }
+
+    /// Looks a function up by its name or an alias.
+    pub fn get(&self, name: &str) -> Option<&FunctionEntry> {
+        self.names.get(name).map(|a| &self.entries[*a])
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains_key(name)
+    }
+
+    /// Every name and alias that can be called.
+    pub fn names(&self) -> impl Iterator<Item = String> + '_ {
+        self.names.keys().cloned()
+    }
+
+    /// Every function's doc line, grouped by category in registration order.
+    pub fn help(&self) -> String {
+        let mut categories: Vec<(&str, Vec<&str>)> = vec![];
+        for entry in &self.entries {
+            match categories.iter_mut().find(|a| a.0 == entry.category) {
+                Some((_, docs)) => docs.push(&entry.doc),
+                None => categories.push((&entry.category, vec![&entry.doc])),
+            }
+        }
+        categories.iter().map(|(category, docs)| format!("{}:\n  {}", category, docs.join("\n  "))).collect::<Vec<String>>().join("\n")
+    }
+}
+
+fn builtin_functions() -> Registry {
+    let mut registry = Registry::new();
+    let entries = [
+        FunctionEntry::one_to_one("sin", "Trigonometry", "sin(t) sine of angle t", f64::sin).takes_angle().with_decimal(decimal::sin).with_complex(|z| z[0].sin()),
+        FunctionEntry::one_to_one("cos", "Trigonometry", "cos(t) cosine of angle t", f64::cos).takes_angle().with_decimal(decimal::cos).with_complex(|z| z[0].cos()),
+        FunctionEntry::one_to_one("tan", "Trigonometry", "tan(t) tangent of angle t", f64::tan).takes_angle().with_decimal(decimal::tan).with_complex(|z| z[0].tan()),
+        FunctionEntry::one_to_one("asin", "Trigonometry", "asin(x) inverse sine as an angle", f64::asin).returns_angle().with_domain(unit_interval).with_decimal(decimal::asin).with_complex(|z| z[0].asin()),
+        FunctionEntry::one_to_one("acos", "Trigonometry", "acos(x) inverse cosine as an angle", f64::acos).returns_angle().with_domain(unit_interval).with_decimal(decimal::acos).with_complex(|z| z[0].acos()),
+        FunctionEntry::one_to_one("atan", "Trigonometry", "atan(x) inverse tangent as an angle", f64::atan).returns_angle().with_decimal(decimal::atan).with_complex(|z| z[0].atan()),
+        FunctionEntry::one_to_one("sec", "Trigonometry", "sec(t) secant, 1/cos(t)", |x| 1. / f64::cos(x)).takes_angle().with_decimal(decimal::sec).with_complex(|z| z[0].cos().inv()),
+        FunctionEntry::one_to_one("csc", "Trigonometry", "csc(t) cosecant, 1/sin(t)", |x| 1. / f64::sin(x)).takes_angle().with_decimal(decimal::csc).with_complex(|z| z[0].sin().inv()),
+        FunctionEntry::one_to_one("cot", "Trigonometry", "cot(t) cotangent, 1/tan(t)", |x| 1. / f64::tan(x)).takes_angle().with_decimal(decimal::cot).with_complex(|z| z[0].tan().inv()),
+        FunctionEntry::multi_to_one("atan2", "Trigonometry", "atan2(y, x) angle of the point (x, y)", atan2).with_arity(2, Some(2)).returns_angle().with_decimal(decimal::atan2),
+        FunctionEntry::multi_to_one("hypot", "Trigonometry", "hypot(x, y, ...) length of the vector (x, y, ...)", hypot).with_decimal(decimal::hypot),
+        FunctionEntry::one_to_one("sinh", "Hyperbolic", "sinh(x) hyperbolic sine", f64::sinh).with_decimal(decimal::sinh).with_complex(|z| z[0].sinh()),
+        FunctionEntry::one_to_one("cosh", "Hyperbolic", "cosh(x) hyperbolic cosine", f64::cosh).with_decimal(decimal::cosh).with_complex(|z| z[0].cosh()),
+        FunctionEntry::one_to_one("tanh", "Hyperbolic", "tanh(x) hyperbolic tangent", f64::tanh).with_decimal(decimal::tanh).with_complex(|z| z[0].tanh()),
+        FunctionEntry::one_to_one("asinh", "Hyperbolic", "asinh(x) inverse hyperbolic sine", f64::asinh).with_decimal(decimal::asinh).with_complex(|z| z[0].asinh()),
+        FunctionEntry::one_to_one("acosh", "Hyperbolic", "acosh(x) inverse hyperbolic cosine, x >= 1", f64::acosh).with_domain(at_least_one).with_decimal(decimal::acosh).with_complex(|z| z[0].acosh()),
+        FunctionEntry::one_to_one("atanh", "Hyperbolic", "atanh(x) inverse hyperbolic tangent, -1 < x < 1", f64::atanh).with_domain(open_unit_interval).with_decimal(decimal::atanh).with_complex(|z| z[0].atanh()),
+        FunctionEntry::one_to_one("sqrt", "Powers and logarithms", "sqrt(x) square root, also √x", f64::sqrt).with_decimal(decimal::sqrt).with_complex(|z| z[0].sqrt()),
+        FunctionEntry::one_to_one("exp", "Powers and logarithms", "exp(x) e to the power x", f64::exp).with_decimal(decimal::exp).with_complex(|z| z[0].exp()),
+        FunctionEntry::one_to_one("ln", "Powers and logarithms", "ln(x) natural logarithm", f64::ln).with_domain(positive).with_decimal(decimal::ln).with_complex(|z| z[0].ln()),
+        FunctionEntry::one_to_one("log10", "Powers and logarithms", "log10(x) base 10 logarithm", f64::log10).with_domain(positive).with_decimal(decimal::log10).with_complex(|z| z[0].log10()),
+        FunctionEntry::one_to_one("log2", "Powers and logarithms", "log2(x) base 2 logarithm", f64::log2).with_domain(positive).with_decimal(decimal::log2).with_complex(|z| z[0].log2()),
+        FunctionEntry::multi_to_one("log", "Powers and logarithms", "log(x, base) logarithm to base, 10 when left out", log).with_arity(1, Some(2)).with_domain(logarithm).with_decimal(decimal::log).with_complex(|z| z[0].ln() / z.get(1).map_or(Complex64::from(std::f64::consts::LN_10), |a| a.ln())),
+        FunctionEntry::one_to_one("re", "Complex numbers", "re(z) real part", |x| x).with_complex(|z| Complex64::from(z[0].re)),
+        FunctionEntry::one_to_one("im", "Complex numbers", "im(z) imaginary part", |_| 0.).with_complex(|z| Complex64::from(z[0].im)),
+        FunctionEntry::one_to_one("arg", "Complex numbers", "arg(z) angle of z from the positive real axis", |x| 0f64.atan2(x)).returns_angle().with_complex(|z| Complex64::from(z[0].arg())),
+        FunctionEntry::one_to_one("conj", "Complex numbers", "conj(z) complex conjugate", |x| x).with_complex(|z| z[0].conj()),
+        FunctionEntry::one_to_one("floor", "Rounding", "floor(x) largest integer <= x", f64::floor).with_rational(rational_floor).with_decimal(decimal::floor),
+        FunctionEntry::one_to_one("ceil", "Rounding", "ceil(x) smallest integer >= x", f64::ceil).with_rational(rational_ceil).with_decimal(decimal::ceil),
+        FunctionEntry::multi_to_one("round", "Rounding", "round(x, digits) x rounded to digits decimal places, 0 when left out", round).with_arity(1, Some(2)).with_domain(round_digits).with_rational(rational_round).with_decimal(decimal::round_digits),
+        FunctionEntry::one_to_one("sign", "Rounding", "sign(x) -1, 0 or 1", sign).with_rational(rational_sign).with_decimal(decimal::sign),
+        FunctionEntry::one_to_one("abs", "Rounding", "abs(x) absolute value", f64::abs).with_rational(rational_abs).with_decimal(decimal::abs).with_complex(|z| Complex64::from(z[0].norm())).with_integer(programmer::abs),
+        FunctionEntry::new("len", "Lists", "len(v) number of elements in the list v", Implementation::Values(list::len), 1, Some(1)),
+        FunctionEntry::new("sort", "Lists", "sort(v) the list v from smallest to largest", Implementation::Values(list::sort), 1, Some(1)),
+        FunctionEntry::new("range", "Lists", "range(a, b, step) [a, a + step, ...] up to but not including b, range(b) starts at 0, range(v) of a list is spread(v)", Implementation::Values(statistics::range), 1, Some(3)),
+        FunctionEntry::new("map", "Lists", "map(f, v) the function f applied to each element of v", Implementation::Map, 2, Some(2)),
+        FunctionEntry::new("transpose", "Matrices", "transpose(A) rows of A as columns, a list becomes a column", Implementation::Values(matrix::transpose), 1, Some(1)),
+        FunctionEntry::new("det", "Matrices", "det(A) determinant of the square matrix A", Implementation::Values(matrix::det), 1, Some(1)),
+        FunctionEntry::new("inv", "Matrices", "inv(A) inverse of the square matrix A", Implementation::Values(matrix::inv), 1, Some(1)),
+        FunctionEntry::new("rank", "Matrices", "rank(A) number of linearly independent rows of A", Implementation::Values(matrix::rank), 1, Some(1)),
+        FunctionEntry::new("rref", "Matrices", "rref(A) reduced row echelon form of A", Implementation::Values(matrix::rref), 1, Some(1)),
+        FunctionEntry::new("trace", "Matrices", "trace(A) sum of the diagonal of the square matrix A", Implementation::Values(matrix::trace), 1, Some(1)),
+        FunctionEntry::new("solve", "Matrices", "solve(A, b) x with A x = b, b a list or a matrix of columns", Implementation::Values(matrix::solve), 2, Some(2)),
+        FunctionEntry::new("eig", "Matrices", "eig(A) [eigenvalues, eigenvectors as columns] of the square matrix A", Implementation::Values(matrix::eig), 1, Some(1)),
+        FunctionEntry::new("svd", "Matrices", "svd(A) [U, s, V] with A = U diag(s) transpose(V)", Implementation::Values(matrix::svd), 1, Some(1)),
+        FunctionEntry::new("qr", "Matrices", "qr(A) [Q, R] with A = Q R, Q orthonormal and R upper triangular", Implementation::Values(matrix::qr), 1, Some(1)),
+        FunctionEntry::new("lu", "Matrices", "lu(A) [L, U, P] with P A = L U", Implementation::Values(matrix::lu), 1, Some(1)),
+        FunctionEntry::new("cholesky", "Matrices", "cholesky(A) lower triangular L with A = L transpose(L), A symmetric positive definite", Implementation::Values(matrix::cholesky), 1, Some(1)),
+        FunctionEntry::multi_to_one("sum", "Statistics", "sum(a, b, ...) total", sum).with_rational(rational_sum).with_decimal(decimal::sum).with_complex(|z| z.iter().sum()).with_integer(programmer::sum),
+        FunctionEntry::multi_to_one("average", "Statistics", "average(a, b, ...) arithmetic mean, also avg and mean", average).with_aliases(&["avg", "mean"]).with_rational(rational_average).with_decimal(decimal::average).with_complex(|z| z.iter().sum::<Complex64>() / z.len() as f64),
+        FunctionEntry::multi_to_one("median", "Statistics", "median(a, b, ...) middle value, the average of the two middle values for an even count", median).with_rational(rational_median).with_decimal(decimal::median),
+        FunctionEntry::multi_to_one("mode", "Statistics", "mode(a, b, ...) most common value, the smallest on a tie", mode).with_rational(rational_mode).with_decimal(decimal::mode),
+        FunctionEntry::multi_to_one("std", "Statistics", "std(a, b, ...) population standard deviation, also stdevp", standard_deviation).with_aliases(&["stdevp"]).with_decimal(decimal::standard_deviation),
+        FunctionEntry::multi_to_one("stdev", "Statistics", "stdev(a, b, ...) sample standard deviation, at least two values", sample_standard_deviation).with_domain(at_least_two).with_decimal(decimal::sample_standard_deviation),
+        FunctionEntry::multi_to_one("varp", "Statistics", "varp(a, b, ...) population variance", variance).with_rational(rational_variance).with_decimal(decimal::variance),
+        FunctionEntry::multi_to_one("var", "Statistics", "var(a, b, ...) sample variance, at least two values", sample_variance).with_domain(at_least_two).with_rational(rational_sample_variance).with_decimal(decimal::sample_variance),
+        FunctionEntry::new("percentile", "Statistics", "percentile(v, p) value p percent of the way through the sorted list v, interpolated", Implementation::Values(statistics::percentile), 2, Some(2)),
+        FunctionEntry::new("quartiles", "Statistics", "quartiles(a, b, ...) [25th, 50th, 75th] percentiles", Implementation::Values(statistics::quartiles), 1, None),
+        FunctionEntry::new("iqr", "Statistics", "iqr(a, b, ...) interquartile range, 75th less 25th percentile", Implementation::Values(statistics::iqr), 1, None),
+        FunctionEntry::new("spread", "Statistics", "spread(a, b, ...) largest less smallest value, the statistical range, also range(v) for a list v", Implementation::Values(statistics::spread), 1, None),
+        FunctionEntry::multi_to_one("skew", "Statistics", "skew(a, b, ...) population skewness", skewness).with_domain(not_all_equal),
+        FunctionEntry::multi_to_one("kurtosis", "Statistics", "kurtosis(a, b, ...) population excess kurtosis, 0 for a normal distribution", kurtosis).with_domain(not_all_equal),
+        FunctionEntry::new("zscore", "Statistics", "zscore(a, b, ...) standard scores, (x - mean) / std for each value", Implementation::Values(statistics::zscore), 1, None),
+        FunctionEntry::multi_to_one("max", "Statistics", "max(a, b, ...) largest value", max).with_rational(rational_max).with_decimal(decimal::max).with_integer(programmer::max),
+        FunctionEntry::multi_to_one("min", "Statistics", "min(a, b, ...) smallest value", min).with_rational(rational_min).with_decimal(decimal::min).with_integer(programmer::min),
+        FunctionEntry::exact("factorial", "Combinatorics", "factorial(n) n!, exact for whole n, Γ(n + 1) otherwise", exact_factorial, |n| factorial(n[0])).with_arity(1, Some(1)).with_domain(factorial_domain),
+        FunctionEntry::exact("nCr", "Combinatorics", "nCr(n, k) ways to choose k of n items, also choose", exact_combinations, combinations).with_aliases(&["choose"]).with_arity(2, Some(2)).with_domain(combinations_domain),
+        FunctionEntry::exact("nPr", "Combinatorics", "nPr(n, k) ways to arrange k of n items", exact_permutations, permutations).with_arity(2, Some(2)).with_domain(combinations_domain),
+        FunctionEntry::one_to_one("gamma", "Combinatorics", "gamma(x) Γ(x), (x - 1)! for whole x", gamma).with_domain(gamma_domain),
+        FunctionEntry::one_to_one("lgamma", "Combinatorics", "lgamma(x) ln |Γ(x)|, for results too large for gamma", ln_gamma).with_domain(gamma_domain),
+        FunctionEntry::new("if", "Other", "if(c, a, b) a when c is true, otherwise b", Implementation::Conditional, 3, Some(3)),
+        FunctionEntry::multi_to_one("and", "Bitwise", "and(a, b, ...) bitwise and, logical for booleans", bit_and).with_arity(2, None).with_domain(integers).with_integer(programmer::and),
+        FunctionEntry::multi_to_one("or", "Bitwise", "or(a, b, ...) bitwise or, logical for booleans", bit_or).with_arity(2, None).with_domain(integers).with_integer(programmer::or),
+        FunctionEntry::multi_to_one("xor", "Bitwise", "xor(a, b, ...) bitwise exclusive or, logical for booleans", bit_xor).with_arity(2, None).with_domain(integers).with_integer(programmer::xor),
+        FunctionEntry::one_to_one("not", "Bitwise", "not(a) bitwise complement, logical for booleans", bit_not).with_domain(integers).with_integer(programmer::not),
+        FunctionEntry::multi_to_one("shl", "Bitwise", "shl(a, n) a shifted left n bits", shift_left).with_arity(2, Some(2)).with_domain(integer_and_shift).with_integer(programmer::shl),
+        FunctionEntry::multi_to_one("shr", "Bitwise", "shr(a, n) a shifted right n bits", shift_right).with_arity(2, Some(2)).with_domain(integer_and_shift).with_integer(programmer::shr),
+    ];
+    for entry in entries {
+        registry.register(entry);
+    }
+    registry
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::EvalError;
+    use crate::math::AngleMode;
+    use crate::registry::{FunctionEntry, Registry};
+    use crate::tokens::Span;
+    use crate::value::Value;
+
+    #[test]
+    fn aliases_share_an_entry() {
+        let registry = Registry::builtin();
+        assert_eq!(registry.get("avg").unwrap().name, "average");
+        assert_eq!(registry.get("mean").unwrap().name, "average");
+        assert_eq!(registry.get("stdevp").unwrap().name, "std");
+    }
+
+    #[test]
+    fn entries_check_arity_and_domain() {
+        let registry = Registry::builtin();
+        let span = Span::new(0, 1);
+        assert_eq!(registry.get("ln").unwrap().check("ln", &[1., 2.], span), Err(EvalError::ArityMismatch { name: "ln".to_string(), min: 1, max: Some(1), found: 2, span }));
+        assert_eq!(registry.get("shl").unwrap().check("shl", &[1., -1.], span), Err(EvalError::DomainError { name: "shl".to_string(), span }));
+        assert_eq!(registry.get("average").unwrap().check("avg", &[], span), Err(EvalError::ArityMismatch { name: "avg".to_string(), min: 1, max: None, found: 0, span }));
+    }
+
+    #[test]
+    fn registering_replaces_by_name_and_alias() {
+        let mut registry = Registry::new();
+        registry.register(FunctionEntry::one_to_one("double", "Custom", "double(x) twice x", |x| 2. * x).with_aliases(&["twice"]));
+        assert_eq!(registry.get("twice").unwrap().call(vec![4.], AngleMode::Radians), Value::Number(8.));
+        registry.register(FunctionEntry::one_to_one("twice", "Custom", "twice(x) two times x", |x| x + x));
+        assert!(!registry.contains("double"));
+        assert_eq!(registry.help(), "Custom:\n  twice(x) two times x");
+    }
+}
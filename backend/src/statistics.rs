@@ -0,0 +1,214 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::ToPrimitive;
+use crate::error::EvalError;
+use crate::list::{self, flatten};
+use crate::math::{average, decimal_rational, standard_deviation};
+use crate::tokens::Span;
+use crate::value::{NumberMode, Value};
+
+/// The numbers a statistic is taken of, from smallest to largest: exact fractions when every
+/// one is exact, or a whole `Number`, and floating point otherwise.
+enum Sample {
+    Exact(Vec<BigRational>),
+    Float(Vec<f64>),
+}
+
+impl Sample {
+    /// The numbers in `args`, taken out of lists and matrices. Having none is a domain error
+    /// for `name`.
+    fn new(args: Vec<Value>, name: &str, span: Span) -> Result<Sample, EvalError> {
+        let values = numbers(args, name, span)?;
+        let mut float = Vec::new();
+        for value in &values {
+            float.push(value.clone().number(span)?);
+        }
+        match values.iter().map(Value::rational).collect::<Option<Vec<BigRational>>>() {
+            Some(mut exact) => {
+                exact.sort();
+                Ok(Sample::Exact(exact))
+            }
+            None => {
+                float.sort_by(f64::total_cmp);
+                Ok(Sample::Float(float))
+            }
+        }
+    }
+
+    fn percentile(&self, percent: &BigRational, mode: NumberMode) -> Value {
+        match self {
+            Sample::Exact(sorted) => Value::from_rational(exact_percentile(sorted, percent), mode),
+            Sample::Float(sorted) => Value::Number(float_percentile(sorted, percent.to_f64().unwrap_or(f64::NAN))),
+        }
+    }
+}
+
+/// `percentile(v, p)` the value `p` percent of the way from the smallest to the largest
+/// element of `v`, interpolating linearly between neighbours like a spreadsheet's
+/// PERCENTILE.
+pub fn percentile(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let mut args = args.into_iter();
+    let sample = Sample::new(vec![args.next().unwrap()], "percentile", span)?;
+    let percent = args.next().unwrap();
+    let number = percent.clone().number(span)?;
+    let exact = match percent {
+        Value::Number(_) => decimal_rational(number),
+        percent => percent.rational(),
+    };
+    match exact {
+        Some(exact) if (0. ..=100.).contains(&number) => Ok(sample.percentile(&exact, mode)),
+        _ => Err(EvalError::DomainError { name: "percentile".to_string(), span }),
+    }
+}
+
+/// `quartiles(a, b, ...)` the 25th, 50th and 75th percentiles as a list.
+pub fn quartiles(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let sample = Sample::new(args, "quartiles", span)?;
+    Ok(Value::List([25, 50, 75].into_iter().map(|p| sample.percentile(&BigRational::from_integer(BigInt::from(p)), mode)).collect()))
+}
+
+/// `iqr(a, b, ...)` the interquartile range, the 75th percentile less the 25th.
+pub fn iqr(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    Ok(match Sample::new(args, "iqr", span)? {
+        Sample::Exact(sorted) => {
+            let [low, high] = [25, 75].map(|p| exact_percentile(&sorted, &BigRational::from_integer(BigInt::from(p))));
+            Value::from_rational(high - low, mode)
+        }
+        Sample::Float(sorted) => Value::Number(float_percentile(&sorted, 75.) - float_percentile(&sorted, 25.)),
+    })
+}
+
+/// `spread(a, b, ...)` the largest value less the smallest, the statistic usually called the
+/// range.
+pub fn spread(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    largest_less_smallest(args, "spread", mode, span)
+}
+
+/// `range(v)` of a single list or matrix is its statistical range, as `spread(v)`. Numbers
+/// are the bounds of `list::range`.
+pub fn range(args: Vec<Value>, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    match args.as_slice() {
+        [Value::List(_) | Value::Matrix(_)] => largest_less_smallest(args, "range", mode, span),
+        _ => list::range(args, mode, span),
+    }
+}
+
+fn largest_less_smallest(args: Vec<Value>, name: &str, mode: NumberMode, span: Span) -> Result<Value, EvalError> {
+    Ok(match Sample::new(args, name, span)? {
+        Sample::Exact(sorted) => Value::from_rational(&sorted[sorted.len() - 1] - &sorted[0], mode),
+        Sample::Float(sorted) => Value::Number(sorted[sorted.len() - 1] - sorted[0]),
+    })
+}
+
+/// `zscore(a, b, ...)` how many population standard deviations each value is from the mean,
+/// as a list in the same order.
+pub fn zscore(args: Vec<Value>, _: NumberMode, span: Span) -> Result<Value, EvalError> {
+    let mut values = Vec::new();
+    for value in numbers(args, "zscore", span)? {
+        values.push(value.number(span)?);
+    }
+    if values.iter().all(|a| *a == values[0]) {
+        return Err(EvalError::DomainError { name: "zscore".to_string(), span });
+    }
+    let (mean, deviation) = (average(values.clone()), standard_deviation(values.clone()));
+    Ok(Value::List(values.into_iter().map(|a| Value::Number((a - mean) / deviation)).collect()))
+}
+
+/// The elements of the lists and matrices in `args` and the other arguments, at least one.
+fn numbers(args: Vec<Value>, name: &str, span: Span) -> Result<Vec<Value>, EvalError> {
+    let mut values = Vec::new();
+    args.into_iter().for_each(|a| flatten(a, &mut values));
+    match values.is_empty() {
+        true => Err(EvalError::DomainError { name: name.to_string(), span }),
+        false => Ok(values),
+    }
+}
+
+fn exact_percentile(sorted: &[BigRational], percent: &BigRational) -> BigRational {
+    let position = percent * BigInt::from(sorted.len() - 1) / BigInt::from(100);
+    let below = position.floor();
+    let index = below.to_integer().to_usize().unwrap();
+    match sorted.get(index + 1) {
+        Some(next) => &sorted[index] + (position - below) * (next - &sorted[index]),
+        None => sorted[index].clone(),
+    }
+}
+
+fn float_percentile(sorted: &[f64], percent: f64) -> f64 {
+    let position = percent * (sorted.len() - 1) as f64 / 100.;
+    let index = position.floor() as usize;
+    match sorted.get(index + 1) {
+        Some(next) => sorted[index] + position.fract() * (next - sorted[index]),
+        None => sorted[index],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::environment::Environment;
+    use crate::error::EvalError;
+    use crate::eval::tests::show;
+    use crate::statistics::{exact_percentile, float_percentile};
+    use crate::tokens::Span;
+    use crate::value::{NumberMode, Value};
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+
+    /// An environment with `v` set to a list of mean 5 and population standard deviation 2.
+    fn sample() -> Environment<'static> {
+        let mut env = Environment::new();
+        env.set("v", Value::List([2., 4., 4., 4., 5., 5., 7., 9.].into_iter().map(Value::Number).collect()));
+        env
+    }
+
+    #[test]
+    fn percentiles_interpolate_between_neighbours() {
+        let sorted = [1., 2., 4., 8.];
+        assert_eq!([0., 25., 50., 75., 100.].map(|p| float_percentile(&sorted, p)), [1., 1.75, 3., 5., 8.]);
+        let exact: Vec<BigRational> = [1, 2, 4, 8].into_iter().map(|a| BigRational::from_integer(BigInt::from(a))).collect();
+        let ninetieth = exact_percentile(&exact, &BigRational::from_integer(BigInt::from(90)));
+        assert_eq!(ninetieth, BigRational::new(BigInt::from(34), BigInt::from(5)));
+        assert_eq!(float_percentile(&[5.], 50.), 5.);
+    }
+
+    #[test]
+    fn averages_and_dispersion() {
+        let env = sample();
+        assert_eq!(show("median(3, 1, 2, 4) + median(v) + median([5])", &env), Ok("12".to_string()));
+        assert_eq!(show("mode(v) + mode(1, 2, 2, 1, 3)", &env), Ok("5".to_string()));
+        assert_eq!(show("[varp(v), std(v), stdevp(v), var([1, 2, 3, 4]), stdev(1, 3)]", &env), Ok("[4, 2, 2, 1.6666666666666667, 1.4142135623730951]".to_string()));
+        assert_eq!(show("[skew(v), kurtosis(v)]", &env), Ok("[0.65625, -0.21875]".to_string()));
+        assert_eq!(show("zscore(v)", &env), Ok("[-1.5, -0.5, -0.5, -0.5, 0, 0, 1, 2]".to_string()));
+    }
+
+    #[test]
+    fn percentiles_and_spread() {
+        let env = sample();
+        assert_eq!(show("[percentile(v, 0), percentile(v, 50), percentile(v, 100), percentile([1, 2, 4, 8], 25)]", &env), Ok("[2, 4.5, 9, 1.75]".to_string()));
+        assert_eq!(show("quartiles(v) + iqr(v)", &env), Ok("[5.5, 6, 7]".to_string()));
+        assert_eq!(show("spread(v) + spread([[1, -1], [0, 3]]) + spread(1, 3)", &env), Ok("13".to_string()));
+        assert_eq!(show("range(v) + range([[1, -1], [0, 3]]) + range(3)", &env), Ok("[11, 12, 13]".to_string()));
+        assert_eq!(show("range([])", &env), Err(EvalError::DomainError { name: "range".to_string(), span: Span::new(0, 9) }));
+        assert_eq!(show("range(v, 2)", &env), Err(EvalError::TypeMismatch { expected: "number", found: "list", span: Span::new(0, 11) }));
+    }
+
+    #[test]
+    fn statistics_errors() {
+        let env = sample();
+        assert_eq!(show("var(5)", &env), Err(EvalError::DomainError { name: "var".to_string(), span: Span::new(0, 6) }));
+        assert_eq!(show("skew(1, 1)", &env), Err(EvalError::DomainError { name: "skew".to_string(), span: Span::new(0, 10) }));
+        assert_eq!(show("zscore([2, 2])", &env), Err(EvalError::DomainError { name: "zscore".to_string(), span: Span::new(0, 14) }));
+        assert_eq!(show("percentile(v, 101)", &env), Err(EvalError::DomainError { name: "percentile".to_string(), span: Span::new(0, 18) }));
+        assert_eq!(show("percentile([], 50)", &env), Err(EvalError::DomainError { name: "percentile".to_string(), span: Span::new(0, 18) }));
+    }
+
+    #[test]
+    fn statistics_stay_exact_in_rational_and_decimal_modes() {
+        let mut env = Environment::new();
+        env.set_number_mode(NumberMode::Rational);
+        assert_eq!(show("[median(1, 2), var(1, 2, 4), varp(1, 2), mode(1/2, 1/3, 1/2)]", &env), Ok("[3/2, 7/3, 1/4, 1/2]".to_string()));
+        assert_eq!(show("[percentile([1, 2, 4, 8], 90), iqr(1, 2), spread([1/2, 1/3])]", &env), Ok("[34/5, 1/2, 1/6]".to_string()));
+        env.set_number_mode(NumberMode::Decimal { precision: 20 });
+        assert_eq!(show("[median(1, 2), var(1, 2, 3, 4), stdev(1, 2)]", &env), Ok("[1.5, 1.6666666666666666667, 0.7071067811865475244]".to_string()));
+    }
+}
@@ -1,171 +1,455 @@
-use std::collections::VecDeque;
-use regex::Regex;
-use crate::eval::FUNCTIONS;
+use std::collections::{HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use crate::constants::PHYSICS_NAMESPACE;
+use crate::decimal;
+use crate::error::ParseError;
+use crate::registry::Registry;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Token {
-    Number(f64),
+    Number(Literal),
     Plus,
     Minus,
     Times,
     Divide,
     Modulus,
     Exponent,
+    /// `r∠θ`, the complex number of magnitude r at angle θ.
+    Phasor,
     LeftParen,
     RightParen,
+    /// `[`, opening a list literal or an index.
+    LeftBracket,
+    RightBracket,
+    /// `:` between the bounds of a slice, as in `v[1:3]`.
+    Colon,
     FunctionCall(String),
     Comma,
     Help,
     Graph,
     Variable(String),
     Equal,
+    /// A whole number literal in any base, `42` as much as `0x2A`.
+    Integer(BigInt),
+    BitAnd,
+    BitOr,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+    SquareRoot,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    EqualEqual,
+    NotEqual,
+    And,
+    Or,
+    Not,
+    Factorial,
+    DoubleFactorial,
 }
 
-pub fn tokenize(input: String) -> Result<VecDeque<Token>, String> {
-    let plus_re = Regex::new(r"^\+").unwrap();
-    let minus_re = Regex::new(r"^-").unwrap();
-    let times_re = Regex::new(r"^\*").unwrap();
-    let divide_re = Regex::new(r"^/").unwrap();
-    let left_paren_re = Regex::new(r"^\(").unwrap();
-    let right_paren_re = Regex::new(r"^\)").unwrap();
-    let number_re = Regex::new(r"^\d+(\.\d+)?").unwrap();
-    let modulus_re = Regex::new(r"^\%").unwrap();
-    let exponent_re = Regex::new(r"^\^").unwrap();
-    let function_re = Regex::new(r"^([a-z]+)\(").unwrap();
-    let comma_re = Regex::new(r"^,").unwrap();
-    let help_re = Regex::new(r"^help").unwrap();
-    let graph_re = Regex::new(r"^graph\(").unwrap();
-    let variable_re = Regex::new(r"^[a-z]+").unwrap();
-    let eq_re = Regex::new(r"^=").unwrap();
-    let mut input = input.trim();
+/// A number literal: the nearest `f64` for floating point, and the digits as written for the
+/// rational and decimal modes, which an `f64` only holds to about 17 significant digits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Literal {
+    pub value: f64,
+    pub digits: BigDecimal,
+}
+
+impl Literal {
+    /// `None` when the exponent is too large for a `BigDecimal`.
+    pub fn parse(text: &str) -> Option<Literal> {
+        Some(Literal { value: text.parse().ok()?, digits: BigDecimal::from_str(text).ok()? })
+    }
+
+    /// The shortest literal that reads back as `value`, `None` for infinities and NaN.
+    pub fn from_f64(value: f64) -> Option<Literal> {
+        Some(Literal { value, digits: decimal::from_f64(value)? })
+    }
+}
+
+/// Byte offsets into the original input, `start` inclusive and `end` exclusive.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn to(&self, other: Span) -> Span {
+        Span::new(self.start, other.end)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl PartialEq<Token> for SpannedToken {
+    fn eq(&self, other: &Token) -> bool {
+        self.token == *other
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(a) => write!(f, "{}", a.value),
+            Token::Plus => f.write_str("+"),
+            Token::Minus => f.write_str("-"),
+            Token::Times => f.write_str("*"),
+            Token::Divide => f.write_str("/"),
+            Token::Modulus => f.write_str("%"),
+            Token::Exponent => f.write_str("^"),
+            Token::Phasor => f.write_str("∠"),
+            Token::LeftParen => f.write_str("("),
+            Token::RightParen => f.write_str(")"),
+            Token::LeftBracket => f.write_str("["),
+            Token::RightBracket => f.write_str("]"),
+            Token::Colon => f.write_str(":"),
+            Token::FunctionCall(a) => write!(f, "{}(", a),
+            Token::Comma => f.write_str(","),
+            Token::Help => f.write_str("help"),
+            Token::Graph => f.write_str("graph("),
+            Token::Variable(a) => f.write_str(a),
+            Token::Equal => f.write_str("="),
+            Token::Integer(a) => write!(f, "{}", a),
+            Token::BitAnd => f.write_str("&"),
+            Token::BitOr => f.write_str("|"),
+            Token::BitNot => f.write_str("~"),
+            Token::ShiftLeft => f.write_str("<<"),
+            Token::ShiftRight => f.write_str(">>"),
+            Token::SquareRoot => f.write_str("√"),
+            Token::Less => f.write_str("<"),
+            Token::Greater => f.write_str(">"),
+            Token::LessEqual => f.write_str("<="),
+            Token::GreaterEqual => f.write_str(">="),
+            Token::EqualEqual => f.write_str("=="),
+            Token::NotEqual => f.write_str("!="),
+            Token::And => f.write_str("and"),
+            Token::Or => f.write_str("or"),
+            Token::Not => f.write_str("not"),
+            Token::Factorial => f.write_str("!"),
+            Token::DoubleFactorial => f.write_str("!!"),
+        }
+    }
+}
+
+/// Tokenizes with the built-in functions from `Registry::builtin`.
+pub fn tokenize(input: String) -> Result<VecDeque<SpannedToken>, ParseError> {
+    tokenize_with_functions(input, &Registry::builtin(), &HashSet::new())
+}
+
+/// Lexes `name(` as a function call for each function in `registry` and each of
+/// `user_functions`, usually `Environment::user_function_names`.
+pub fn tokenize_with_functions(input: String, registry: &Registry, user_functions: &HashSet<String>) -> Result<VecDeque<SpannedToken>, ParseError> {
+    let source = input.as_str();
     let mut tokens = VecDeque::new();
-    if input.is_empty(){
-        return Err(" ".to_owned())
-    }
-    while !input.is_empty() {
-        if plus_re.is_match(input) {
-            tokens.push_back(Token::Plus);
-            input = &input[1..];
-        } else if minus_re.is_match(input) {
-            tokens.push_back(Token::Minus);
-            input = &input[1..];
-        } else if times_re.is_match(input) {
-            tokens.push_back(Token::Times);
-            input = &input[1..];
-        } else if divide_re.is_match(input) {
-            tokens.push_back(Token::Divide);
-            input = &input[1..];
-        } else if modulus_re.is_match(input) {
-            tokens.push_back(Token::Modulus);
-            input = &input[1..];
-        } else if exponent_re.is_match(input) {
-            tokens.push_back(Token::Exponent);
-            input = &input[1..];
-        } else if left_paren_re.is_match(input) {
-            tokens.push_back(Token::LeftParen);
-            input = &input[1..];
-        } else if right_paren_re.is_match(input) {
-            tokens.push_back(Token::RightParen);
-            input = &input[1..];
-        } else if function_re.is_match(input) && FUNCTIONS.contains(&function_re.captures(input).unwrap().get(1).unwrap().as_str()) {
-            let capture = function_re.captures(input).unwrap();
-            let matching_word = capture.get(1).unwrap().as_str();
-            tokens.push_back(Token::FunctionCall(matching_word.to_string()));
-            let length = matching_word.len() + 1;
-            input = &input[length..];
-        } else if comma_re.is_match(input) {
-            tokens.push_back(Token::Comma);
-            input = &input[1..];
-        } else if help_re.is_match(input) {
-            tokens.push_back(Token::Help);
-            input = &input[4..];
-        } else if graph_re.is_match(input) {
-            tokens.push_back(Token::Graph);
-            input = &input[6..];
-        } else if eq_re.is_match(input) {
-            tokens.push_back(Token::Equal);
-            input = &input[1..];
-        } else if variable_re.is_match(input) {
-            let capture = variable_re.captures(input).unwrap();
-            let matching_word = capture.get(0).unwrap().as_str();
-            tokens.push_back(Token::Variable(matching_word.to_string()));
-            input = &input[matching_word.len()..];
-        } else if number_re.is_match(input) {
-            let value = number_re.captures(input).unwrap()[0].to_string();
-            let length = value.len();
-            let value = value.parse::<f64>().unwrap();
-            tokens.push_back(Token::Number(value));
-            input = &input[length..];
-        } else {
-            return Err(format!("Syntax Error: {}", input))
+    if source.trim().is_empty() {
+        return Err(ParseError::EmptyInput)
+    }
+    let mut start = 0;
+    while let Some(character) = source[start..].chars().next() {
+        let rest = &source[start..];
+        if character.is_whitespace() {
+            start += character.len_utf8();
+            continue;
+        }
+        if let Some(length) = lex_superscript(rest, start, &mut tokens) {
+            start += length;
+            continue;
         }
-        input = input.trim();
+        let (token, length) = match character {
+            '+' => (Token::Plus, 1),
+            '-' => (Token::Minus, 1),
+            '−' => (Token::Minus, '−'.len_utf8()),
+            '*' => (Token::Times, 1),
+            '×' => (Token::Times, '×'.len_utf8()),
+            '/' => (Token::Divide, 1),
+            '÷' => (Token::Divide, '÷'.len_utf8()),
+            '%' => (Token::Modulus, 1),
+            '^' => (Token::Exponent, 1),
+            '∠' => (Token::Phasor, '∠'.len_utf8()),
+            '(' => (Token::LeftParen, 1),
+            ')' => (Token::RightParen, 1),
+            '[' => (Token::LeftBracket, 1),
+            ']' => (Token::RightBracket, 1),
+            ':' => (Token::Colon, 1),
+            ',' => (Token::Comma, 1),
+            '=' if rest.starts_with("==") => (Token::EqualEqual, 2),
+            '=' => (Token::Equal, 1),
+            '!' if rest.starts_with("!=") => (Token::NotEqual, 2),
+            '!' if rest.starts_with("!!") => (Token::DoubleFactorial, 2),
+            '!' => (Token::Factorial, 1),
+            '&' => (Token::BitAnd, 1),
+            '|' => (Token::BitOr, 1),
+            '~' => (Token::BitNot, 1),
+            '<' if rest.starts_with("<<") => (Token::ShiftLeft, 2),
+            '>' if rest.starts_with(">>") => (Token::ShiftRight, 2),
+            '<' if rest.starts_with("<=") => (Token::LessEqual, 2),
+            '>' if rest.starts_with(">=") => (Token::GreaterEqual, 2),
+            '<' => (Token::Less, 1),
+            '>' => (Token::Greater, 1),
+            '≤' => (Token::LessEqual, '≤'.len_utf8()),
+            '≥' => (Token::GreaterEqual, '≥'.len_utf8()),
+            '√' => (Token::SquareRoot, '√'.len_utf8()),
+            'π' => (Token::Variable("π".to_string()), 'π'.len_utf8()),
+            'a'..='z' | 'A'..='Z' | '_' => lex_word(rest, registry, user_functions),
+            '0'..='9' | '.' => match lex_radix(rest) {
+                Some((literal, radix)) => (Token::Integer(BigInt::parse_bytes(&literal.as_bytes()[2..], radix).unwrap()), literal.len()),
+                None => match lex_number(rest) {
+                    Some(literal) if literal.bytes().all(|a| a.is_ascii_digit()) => (Token::Integer(literal.parse().unwrap()), literal.len()),
+                    Some(literal) => match Literal::parse(literal) {
+                        Some(number) => (Token::Number(number), literal.len()),
+                        None => return Err(ParseError::InvalidNumber { span: Span::new(start, start + literal.len()) }),
+                    },
+                    None => return Err(ParseError::UnexpectedCharacter { character, span: Span::new(start, start + 1) }),
+                },
+            },
+            _ => return Err(ParseError::UnexpectedCharacter { character, span: Span::new(start, start + character.len_utf8()) }),
+        };
+        tokens.push_back(SpannedToken { token, span: Span::new(start, start + length) });
+        start += length;
     }
-    return Ok(tokens);
+    Ok(tokens)
+}
+
+/// Lexes an identifier `[A-Za-z_][A-Za-z0-9_]*`, or one in `PHYSICS_NAMESPACE`: a function
+/// directly followed by `(`, `help`, `graph(`, a boolean keyword, or otherwise a variable.
+/// The whole identifier is matched before looking up functions, so `sinc(x)` is never read
+/// as `sin` followed by `c(x)`. `and(`, `or(` and `not(` stay function calls.
+fn lex_word(rest: &str, registry: &Registry, user_functions: &HashSet<String>) -> (Token, usize) {
+    let identifier = |a: &u8| a.is_ascii_alphanumeric() || *a == b'_';
+    let mut length = count_while(rest, 0, identifier);
+    // The physical constants are one word with their namespace, as in `phys.c`.
+    let namespace = PHYSICS_NAMESPACE.len();
+    if length == namespace - 1 && rest.starts_with(PHYSICS_NAMESPACE) && rest.as_bytes().get(namespace).is_some_and(|a| a.is_ascii_alphabetic() || *a == b'_') {
+        length = count_while(rest, namespace, identifier);
+    }
+    let word = &rest[..length];
+    let called = rest[word.len()..].starts_with('(');
+    if called && (registry.contains(word) || user_functions.contains(word)) {
+        (Token::FunctionCall(word.to_string()), word.len() + 1)
+    } else if let Some(keyword) = match word {
+        "and" => Some(Token::And),
+        "or" => Some(Token::Or),
+        "not" => Some(Token::Not),
+        _ => None,
+    } {
+        (keyword, word.len())
+    } else if word == "help" {
+        (Token::Help, 4)
+    } else if called && word == "graph" {
+        (Token::Graph, 6)
+    } else {
+        (Token::Variable(word.to_string()), word.len())
+    }
+}
+
+/// Lexes a run of superscript digits such as `²` or `⁻¹` as `^2` and `^-1`, returning the
+/// number of bytes consumed, or `None` when `rest` does not start with a superscript.
+fn lex_superscript(rest: &str, start: usize, tokens: &mut VecDeque<SpannedToken>) -> Option<usize> {
+    const SUPERSCRIPTS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+    let negative = rest.starts_with('⁻');
+    let sign = if negative { '⁻'.len_utf8() } else { 0 };
+    let mut digits = String::new();
+    let mut end = sign;
+    for character in rest[sign..].chars() {
+        match SUPERSCRIPTS.iter().position(|a| *a == character) {
+            Some(digit) => digits.push(char::from(b'0' + digit as u8)),
+            None => break,
+        }
+        end += character.len_utf8();
+    }
+    if end == sign {
+        return None;
+    }
+    let number: BigInt = digits.parse().ok()?;
+    tokens.push_back(SpannedToken { token: Token::Exponent, span: Span::new(start, start + end) });
+    if negative {
+        tokens.push_back(SpannedToken { token: Token::Minus, span: Span::new(start, start + sign) });
+    }
+    tokens.push_back(SpannedToken { token: Token::Integer(number), span: Span::new(start + sign, start + end) });
+    Some(end)
+}
+
+/// Matches `0x..`, `0b..` and `0o..` literals, returning the literal text and its radix.
+fn lex_radix(rest: &str) -> Option<(&str, u32)> {
+    let bytes = rest.as_bytes();
+    if bytes.len() < 3 || bytes[0] != b'0' {
+        return None;
+    }
+    let (radix, is_digit): (u32, fn(&u8) -> bool) = match bytes[1] {
+        b'x' | b'X' => (16, u8::is_ascii_hexdigit),
+        b'b' | b'B' => (2, |a| matches!(a, b'0' | b'1')),
+        b'o' | b'O' => (8, |a| matches!(a, b'0'..=b'7')),
+        _ => return None,
+    };
+    let end = count_while(rest, 2, is_digit);
+    if end == 2 {
+        return None;
+    }
+    Some((&rest[..end], radix))
+}
+
+/// Matches `digits[.digits]` or `.digits`, then an optional `e[+-]digits` exponent.
+fn lex_number(rest: &str) -> Option<&str> {
+    let bytes = rest.as_bytes();
+    let mut end = count_while(rest, 0, u8::is_ascii_digit);
+    if end > 0 {
+        if bytes.get(end) == Some(&b'.') {
+            end = count_while(rest, end + 1, u8::is_ascii_digit);
+        }
+    } else {
+        end = count_while(rest, 1, u8::is_ascii_digit);
+        if end == 1 {
+            return None;
+        }
+    }
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let mut digits = end + 1;
+        if matches!(bytes.get(digits), Some(b'+' | b'-')) {
+            digits += 1;
+        }
+        let exponent_end = count_while(rest, digits, u8::is_ascii_digit);
+        if exponent_end > digits {
+            end = exponent_end;
+        }
+    }
+    Some(&rest[..end])
+}
+
+fn count_while(rest: &str, from: usize, predicate: fn(&u8) -> bool) -> usize {
+    from + rest.as_bytes()[from.min(rest.len())..].iter().take_while(|a| predicate(a)).count()
 }
 
 #[cfg(test)]
 mod test {
-    use crate::tokens::{Token, tokenize};
+    use num_bigint::BigInt;
+    use std::collections::HashSet;
+    use crate::error::ParseError;
+    use crate::registry::Registry;
+    use crate::tokens::{Literal, Span, Token, tokenize, tokenize_with_functions};
+
+    fn number(value: f64) -> Token {
+        Token::Number(Literal::from_f64(value).unwrap())
+    }
+
+    fn integer(value: u64) -> Token {
+        Token::Integer(BigInt::from(value))
+    }
 
     #[test]
     fn numbers_tokenize_correctly() {
-        assert_eq!(tokenize("123".to_string()).unwrap(), vec![Token::Number(123.0)]);
-        assert_eq!(tokenize("123.456".to_string()).unwrap(), vec![Token::Number(123.456)]);
+        assert_eq!(tokenize("123".to_string()).unwrap(), vec![integer(123)]);
+        assert_eq!(tokenize("123.456".to_string()).unwrap(), vec![number(123.456)]);
+        let Token::Number(long) = &tokenize("1.00000000000000000001".to_string()).unwrap()[0].token else { panic!() };
+        assert_eq!((long.value, long.digits.to_string()), (1., "1.00000000000000000001".to_string()));
+    }
+
+    #[test]
+    fn scientific_notation_tokenizes_correctly() {
+        assert_eq!(tokenize("1e-9".to_string()).unwrap(), vec![number(1e-9)]);
+        assert_eq!(tokenize("6.022e23".to_string()).unwrap(), vec![number(6.022e23)]);
+        assert_eq!(tokenize("1E3".to_string()).unwrap(), vec![number(1000.0)]);
+        assert_eq!(tokenize("2.5e+2".to_string()).unwrap(), vec![number(250.0)]);
+        assert_eq!(tokenize("1e99999999999999999999".to_string()), Err(ParseError::InvalidNumber { span: Span::new(0, 22) }));
+    }
+
+    #[test]
+    fn leading_and_trailing_dots_tokenize_correctly() {
+        assert_eq!(tokenize(".5".to_string()).unwrap(), vec![number(0.5)]);
+        assert_eq!(tokenize("5.".to_string()).unwrap(), vec![number(5.0)]);
+        assert_eq!(tokenize(".5e1".to_string()).unwrap(), vec![number(5.0)]);
+        assert!(tokenize(".".to_string()).is_err());
+    }
+
+    #[test]
+    fn radix_literals_tokenize_correctly() {
+        assert_eq!(tokenize("0xFF".to_string()).unwrap(), vec![integer(255)]);
+        assert_eq!(tokenize("0b1010".to_string()).unwrap(), vec![integer(10)]);
+        assert_eq!(tokenize("0o17".to_string()).unwrap(), vec![integer(15)]);
+        assert_eq!(tokenize("0xFFFFFFFFFFFFFFFF".to_string()).unwrap(), vec![integer(u64::MAX)]);
+        assert_eq!(tokenize("0x1FFFFFFFFFFFFFFFF".to_string()).unwrap(), vec![Token::Integer(BigInt::from(u64::MAX) * 2 + 1)]);
+        assert_eq!(tokenize("18446744073709551617".to_string()).unwrap(), vec![Token::Integer(BigInt::from(u64::MAX) + 2)]);
+        assert_eq!(tokenize("0b12".to_string()).unwrap(), vec![integer(1), integer(2)]);
+    }
+
+    #[test]
+    fn bitwise_operators_tokenize_correctly() {
+        let tokens = tokenize("~1 & 2 | 3 << 4 >> 5".to_string()).unwrap();
+        assert_eq!(tokens, vec![Token::BitNot, integer(1), Token::BitAnd, integer(2), Token::BitOr, integer(3), Token::ShiftLeft, integer(4), Token::ShiftRight, integer(5)]);
+    }
+
+    #[test]
+    fn exponent_needs_digits() {
+        assert_eq!(tokenize("2e".to_string()).unwrap(), vec![integer(2), Token::Variable("e".to_string())]);
+        assert_eq!(tokenize("3ex".to_string()).unwrap(), vec![integer(3), Token::Variable("ex".to_string())]);
     }
 
     #[test]
     fn simple_expression() {
-        assert_eq!(tokenize("1+2".to_string()).unwrap(), vec![Token::Number(1.0), Token::Plus, Token::Number(2.0)])
+        assert_eq!(tokenize("1+2".to_string()).unwrap(), vec![integer(1), Token::Plus, integer(2)])
     }
 
     #[test]
     fn symbols_tokenize_correctly() {
         let input = "+ - * / ( 123 )".to_string();
         let input = tokenize(input).unwrap();
-        assert_eq!(input, vec![Token::Plus, Token::Minus, Token::Times, Token::Divide, Token::LeftParen, Token::Number(123.0), Token::RightParen]);
+        assert_eq!(input, vec![Token::Plus, Token::Minus, Token::Times, Token::Divide, Token::LeftParen, integer(123), Token::RightParen]);
     }
 
     #[test]
     fn negatives_tokenize_correctly() {
         let input = tokenize("-2".to_string()).unwrap();
-        assert_eq!(input, vec![Token::Minus, Token::Number(2.0)]);
+        assert_eq!(input, vec![Token::Minus, integer(2)]);
         let input = tokenize("--2".to_string()).unwrap();
-        assert_eq!(input, vec![Token::Minus, Token::Minus, Token::Number(2.0)]);
+        assert_eq!(input, vec![Token::Minus, Token::Minus, integer(2)]);
         let input = tokenize("3*-2".to_string()).unwrap();
-        assert_eq!(input, vec![Token::Number(3.0), Token::Times, Token::Minus, Token::Number(2.0)]);
+        assert_eq!(input, vec![integer(3), Token::Times, Token::Minus, integer(2)]);
     }
 
     #[test]
     fn carrot_test() {
         let input = "5^5".to_string();
         let input = tokenize(input).unwrap();
-        assert_eq!(input, vec![Token::Number(5.0), Token::Exponent, Token::Number(5.0)])
+        assert_eq!(input, vec![integer(5), Token::Exponent, integer(5)])
     }
 
     #[test]
     fn average_test() {
         let input = "average(1,2,3)".to_string();
         let input = tokenize(input).unwrap();
-        assert_eq!(input, vec![Token::FunctionCall("average".to_string()), Token::Number(1.0), Token::Comma, Token::Number(2.0), Token::Comma, Token::Number(3.0), Token::RightParen])
+        assert_eq!(input, vec![Token::FunctionCall("average".to_string()), integer(1), Token::Comma, integer(2), Token::Comma, integer(3), Token::RightParen])
     }
 
     #[test]
     fn graph_tokenizes_correctly() {
         let tokens = tokenize("graph(y=5x)".to_string()).unwrap();
-        assert_eq!(tokens, vec![Token::Graph, Token::Variable("y".to_string()), Token::Equal, Token::Number(5.0), Token::Variable("x".to_string()), Token::RightParen]);
+        assert_eq!(tokens, vec![Token::Graph, Token::Variable("y".to_string()), Token::Equal, integer(5), Token::Variable("x".to_string()), Token::RightParen]);
     }
 
     #[test]
     fn one_plus_ln() {
         let tokens = tokenize("1+ln(2)".to_string()).unwrap();
-        assert_eq!(tokens, vec![Token::Number(1.), Token::Plus, Token::FunctionCall("ln".to_string()), Token::Number(2.), Token::RightParen]);
+        assert_eq!(tokens, vec![integer(1), Token::Plus, Token::FunctionCall("ln".to_string()), integer(2), Token::RightParen]);
     }
 
     #[test]
     fn tokenizes_y_5x(){
         let tokens = tokenize("graph(y=5x)".to_string()).unwrap();
-        assert_eq!(tokens, vec![Token::Graph, Token::Variable("y".to_string()), Token::Equal, Token::Number(5.0), Token::Variable("x".to_string()), Token::RightParen]);
+        assert_eq!(tokens, vec![Token::Graph, Token::Variable("y".to_string()), Token::Equal, integer(5), Token::Variable("x".to_string()), Token::RightParen]);
     }
 
     #[test]
@@ -173,4 +457,105 @@ mod test {
         let tokens = tokenize("graph(y=sin(x))".to_string()).unwrap();
         assert_eq!(tokens, vec![Token::Graph, Token::Variable("y".to_string()), Token::Equal, Token::FunctionCall("sin".to_string()), Token::Variable("x".to_string()), Token::RightParen, Token::RightParen]);
     }
+
+    #[test]
+    fn tokens_remember_where_they_came_from() {
+        let tokens = tokenize("  12 + ln(x)".to_string()).unwrap();
+        let spans: Vec<Span> = tokens.iter().map(|a| a.span).collect();
+        assert_eq!(spans, vec![Span::new(2, 4), Span::new(5, 6), Span::new(7, 10), Span::new(10, 11), Span::new(11, 12)]);
+    }
+
+    #[test]
+    fn unknown_character_is_an_error() {
+        assert_eq!(tokenize("1 + $".to_string()), Err(ParseError::UnexpectedCharacter { character: '$', span: Span::new(4, 5) }));
+        assert_eq!(tokenize("   ".to_string()), Err(ParseError::EmptyInput));
+    }
+
+    #[test]
+    fn lexes_dense_input() {
+        let tokens = tokenize("2x^2+sin(x)-0x1F<<2,helpz".to_string()).unwrap();
+        assert_eq!(tokens, vec![integer(2), Token::Variable("x".to_string()), Token::Exponent, integer(2), Token::Plus,
+            Token::FunctionCall("sin".to_string()), Token::Variable("x".to_string()), Token::RightParen, Token::Minus, integer(31),
+            Token::ShiftLeft, integer(2), Token::Comma, Token::Variable("helpz".to_string())]);
+        assert_eq!(tokenize("helper = 3, help".to_string()).unwrap(), vec![Token::Variable("helper".to_string()), Token::Equal, integer(3),
+            Token::Comma, Token::Help]);
+        assert_eq!(tokenize("2phys.c phys.5".to_string()).unwrap(), vec![integer(2), Token::Variable("phys.c".to_string()),
+            Token::Variable("phys".to_string()), number(0.5)]);
+        assert_eq!(tokenize("x.c".to_string()), Err(ParseError::UnexpectedCharacter { character: '.', span: Span::new(1, 2) }));
+    }
+
+    #[test]
+    fn non_ascii_digits_are_rejected() {
+        assert_eq!(tokenize("1+\u{0663}".to_string()), Err(ParseError::UnexpectedCharacter { character: '\u{0663}', span: Span::new(2, 4) }));
+    }
+
+    #[test]
+    fn unicode_operators() {
+        assert_eq!(tokenize("6 × 2 ÷ 3 − 1".to_string()).unwrap(), vec![integer(6), Token::Times, integer(2), Token::Divide, integer(3), Token::Minus, integer(1)]);
+        assert_eq!(tokenize("2π".to_string()).unwrap(), vec![integer(2), Token::Variable("π".to_string())]);
+        assert_eq!(tokenize("√x ≤ 1 ≥ 0".to_string()).unwrap(), vec![Token::SquareRoot, Token::Variable("x".to_string()), Token::LessEqual, integer(1), Token::GreaterEqual, integer(0)]);
+        assert_eq!(tokenize("a <= b >= c".to_string()).unwrap(), vec![Token::Variable("a".to_string()), Token::LessEqual, Token::Variable("b".to_string()), Token::GreaterEqual, Token::Variable("c".to_string())]);
+    }
+
+    #[test]
+    fn superscripts_are_exponents() {
+        assert_eq!(tokenize("x²".to_string()).unwrap(), vec![Token::Variable("x".to_string()), Token::Exponent, integer(2)]);
+        assert_eq!(tokenize("2¹⁰+x⁻³".to_string()).unwrap(), vec![integer(2), Token::Exponent, integer(10), Token::Plus,
+            Token::Variable("x".to_string()), Token::Exponent, Token::Minus, integer(3)]);
+        let tokens = tokenize("x⁻³".to_string()).unwrap();
+        assert_eq!(tokens.iter().map(|a| a.span).collect::<Vec<Span>>(), vec![Span::new(0, 1), Span::new(1, 6), Span::new(1, 4), Span::new(4, 6)]);
+        assert_eq!(tokenize("x⁻".to_string()), Err(ParseError::UnexpectedCharacter { character: '⁻', span: Span::new(1, 4) }));
+    }
+
+    #[test]
+    fn identifiers() {
+        let variable = |name: &str| Token::Variable(name.to_string());
+        assert_eq!(tokenize("x1 + v_0 * T - Vmax / _tmp".to_string()).unwrap(), vec![variable("x1"), Token::Plus, variable("v_0"), Token::Times, variable("T"),
+            Token::Minus, variable("Vmax"), Token::Divide, variable("_tmp")]);
+        assert_eq!(tokenize("2x + 3.5y_1".to_string()).unwrap(), vec![integer(2), variable("x"), Token::Plus, number(3.5), variable("y_1")]);
+        assert_eq!(tokenize("2e3x".to_string()).unwrap(), vec![number(2000.0), variable("x")]);
+    }
+
+    #[test]
+    fn identifiers_starting_with_a_function_name() {
+        assert_eq!(tokenize("sinc(x)".to_string()).unwrap(), vec![Token::Variable("sinc".to_string()), Token::LeftParen, Token::Variable("x".to_string()), Token::RightParen]);
+        assert_eq!(tokenize("sin2 + sin(x)".to_string()).unwrap(), vec![Token::Variable("sin2".to_string()), Token::Plus, Token::FunctionCall("sin".to_string()),
+            Token::Variable("x".to_string()), Token::RightParen]);
+        assert_eq!(tokenize("asin(1)".to_string()).unwrap(), vec![Token::FunctionCall("asin".to_string()), integer(1), Token::RightParen]);
+    }
+
+    #[test]
+    fn comparison_and_boolean_operators() {
+        assert_eq!(tokenize("1 < 2 > 3 <= 4 >= 5 == 6 != 7 = 8 << 9".to_string()).unwrap(), vec![integer(1), Token::Less, integer(2), Token::Greater,
+            integer(3), Token::LessEqual, integer(4), Token::GreaterEqual, integer(5), Token::EqualEqual, integer(6),
+            Token::NotEqual, integer(7), Token::Equal, integer(8), Token::ShiftLeft, integer(9)]);
+        assert_eq!(tokenize("not a and b or c".to_string()).unwrap(), vec![Token::Not, Token::Variable("a".to_string()), Token::And, Token::Variable("b".to_string()),
+            Token::Or, Token::Variable("c".to_string())]);
+        assert_eq!(tokenize("and(1, 2) or not (x)".to_string()).unwrap(), vec![Token::FunctionCall("and".to_string()), integer(1), Token::Comma, integer(2),
+            Token::RightParen, Token::Or, Token::Not, Token::LeftParen, Token::Variable("x".to_string()), Token::RightParen]);
+    }
+
+    #[test]
+    fn postfix_operators() {
+        assert_eq!(tokenize("5! + 7!! - 15%".to_string()).unwrap(), vec![integer(5), Token::Factorial, Token::Plus, integer(7), Token::DoubleFactorial,
+            Token::Minus, integer(15), Token::Modulus]);
+        assert_eq!(tokenize("3!!=15".to_string()).unwrap(), vec![integer(3), Token::DoubleFactorial, Token::Equal, integer(15)]);
+        assert_eq!(tokenize("3!=4".to_string()).unwrap(), vec![integer(3), Token::NotEqual, integer(4)]);
+    }
+
+    #[test]
+    fn brackets_and_colons() {
+        assert_eq!(tokenize("[1, 2][0:]".to_string()).unwrap(), vec![Token::LeftBracket, integer(1), Token::Comma, integer(2), Token::RightBracket,
+            Token::LeftBracket, integer(0), Token::Colon, Token::RightBracket]);
+    }
+
+    #[test]
+    fn user_function_names() {
+        let functions = HashSet::from(["f".to_string(), "area".to_string()]);
+        assert_eq!(tokenize_with_functions("f(2) + area(1, 2) + g(3)".to_string(), &Registry::builtin(), &functions).unwrap(), vec![Token::FunctionCall("f".to_string()), integer(2),
+            Token::RightParen, Token::Plus, Token::FunctionCall("area".to_string()), integer(1), Token::Comma, integer(2), Token::RightParen,
+            Token::Plus, Token::Variable("g".to_string()), Token::LeftParen, integer(3), Token::RightParen]);
+        assert_eq!(tokenize_with_functions("f + ff(1)".to_string(), &Registry::builtin(), &functions).unwrap(), vec![Token::Variable("f".to_string()), Token::Plus,
+            Token::Variable("ff".to_string()), Token::LeftParen, integer(1), Token::RightParen]);
+    }
 }
@@ -0,0 +1,271 @@
+use std::fmt::{Display, Formatter};
+use bigdecimal::BigDecimal;
+use num_bigint::BigInt;
+use num_complex::Complex64;
+use num_rational::BigRational;
+use num_traits::{One, ToPrimitive};
+use crate::decimal;
+use crate::error::EvalError;
+use crate::math::{snap, AngleMode};
+use crate::tokens::Span;
+
+/// How `evaluate_ast` represents numbers, chosen per request.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NumberMode {
+    #[default]
+    Float,
+    /// Literals are exact fractions and stay exact through + - * / and integer powers.
+    Rational,
+    /// Literals are decimals, and every operation and elementary function is rounded to
+    /// `precision` significant digits.
+    Decimal { precision: u64 },
+}
+
+/// How complex results are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ComplexForm {
+    /// `3 + 4i`
+    #[default]
+    Rectangular,
+    /// Magnitude and angle in the session's angle mode, `5∠53.13010235415598deg`, which
+    /// reads back in as the same number.
+    Polar,
+}
+
+impl ComplexForm {
+    /// The form named by `rect` or `polar`.
+    pub fn parse(name: &str) -> Option<ComplexForm> {
+        match name {
+            "rect" => Some(ComplexForm::Rectangular),
+            "polar" => Some(ComplexForm::Polar),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ComplexForm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ComplexForm::Rectangular => f.write_str("rectangular"),
+            ComplexForm::Polar => f.write_str("polar"),
+        }
+    }
+}
+
+/// The result of evaluating an expression.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Number(f64),
+    /// An exact whole number too large for `Number` to hold exactly, such as `30!`.
+    Integer(BigInt),
+    /// An exact fraction from rational mode, shown as `1/2`.
+    Rational(BigRational),
+    /// An arbitrary precision decimal from decimal mode.
+    Decimal(BigDecimal),
+    /// A number with a non-zero imaginary part, see `from_complex`.
+    Complex(Complex64),
+    Boolean(bool),
+    /// `[1, 2, 3]`, which arithmetic applies to element by element.
+    List(Vec<Value>),
+    /// Rows of numbers, at least one of each and all the same length, from a list of lists
+    /// such as `[[1, 2], [3, 4]]`. See `from_elements`.
+    Matrix(Vec<Vec<Value>>),
+}
+
+/// Numbers compare by value whether exact or not, so `5! == 120`, and lists by element.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Matrix(a), Value::Matrix(b)) => a == b,
+            (Value::List(_) | Value::Matrix(_), _) | (_, Value::List(_) | Value::Matrix(_)) => false,
+            (Value::Complex(_), _) | (_, Value::Complex(_)) => self.complex().is_some_and(|a| Some(a) == other.complex()),
+            (Value::Number(_), _) | (_, Value::Number(_)) => self.to_f64().is_some_and(|a| Some(a) == other.to_f64()),
+            _ => self.exact().is_some_and(|a| Some(a) == other.exact()),
+        }
+    }
+}
+
+impl Value {
+    /// A complex result, as a `Number` when its imaginary part is zero so `i * i` is -1.
+    pub fn from_complex(z: Complex64) -> Value {
+        if z.im == 0. {
+            Value::Number(z.re)
+        } else {
+            Value::Complex(z)
+        }
+    }
+
+    /// `a` as a literal of the same value is represented in `mode`.
+    pub fn from_rational(a: BigRational, mode: NumberMode) -> Value {
+        match mode {
+            NumberMode::Float => Value::Number(a.to_f64().unwrap_or(f64::NAN)),
+            NumberMode::Rational => Value::Rational(a),
+            NumberMode::Decimal { precision } => {
+                let quotient = decimal::div(&BigDecimal::from(a.numer().clone()), &BigDecimal::from(a.denom().clone()), precision);
+                Value::Decimal(decimal::round(&quotient, precision))
+            }
+        }
+    }
+
+    /// A list, or a matrix when the elements are lists of numbers that are all the same
+    /// length. Multiplication treats the two differently.
+    pub fn from_elements(elements: Vec<Value>) -> Value {
+        let row = |a: &Value| match a {
+            Value::List(row) if !row.is_empty() && row.iter().all(|a| !matches!(a, Value::List(_) | Value::Matrix(_))) => Some(row.len()),
+            _ => None,
+        };
+        let width = elements.first().and_then(row);
+        if width.is_none() || elements.iter().any(|a| row(a) != width) {
+            return Value::List(elements);
+        }
+        Value::Matrix(elements.into_iter().map(|a| match a {
+            Value::List(row) => row,
+            _ => unreachable!("every element is a row"),
+        }).collect())
+    }
+
+    /// Whether the value is or holds floating point, which in rational and decimal mode means
+    /// it had no exact value, as for `ln(2)`, or an exact one too large to keep.
+    pub fn is_approximate(&self) -> bool {
+        match self {
+            Value::Number(_) | Value::Complex(_) => true,
+            Value::List(elements) => elements.iter().any(Value::is_approximate),
+            Value::Matrix(rows) => rows.iter().flatten().any(Value::is_approximate),
+            Value::Integer(_) | Value::Rational(_) | Value::Decimal(_) | Value::Boolean(_) => false,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) | Value::Integer(_) | Value::Rational(_) | Value::Decimal(_) | Value::Complex(_) => "number",
+            Value::Boolean(_) => "boolean",
+            Value::List(_) => "list",
+            Value::Matrix(_) => "matrix",
+        }
+    }
+
+    /// Unwraps a real number, reporting a type error at `span` for anything else. Exact
+    /// integers are rounded to the nearest `f64`.
+    pub fn number(self, span: Span) -> Result<f64, EvalError> {
+        match self.to_f64() {
+            Some(a) => Ok(a),
+            None if matches!(self, Value::Complex(_)) => Err(EvalError::TypeMismatch { expected: "real number", found: "complex number", span }),
+            None => Err(EvalError::TypeMismatch { expected: "number", found: self.type_name(), span }),
+        }
+    }
+
+    /// The value as a complex number, with no imaginary part unless it is `Complex`.
+    pub fn complex(&self) -> Option<Complex64> {
+        match self {
+            Value::Complex(a) => Some(*a),
+            a => a.to_f64().map(Complex64::from),
+        }
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(a) => Some(*a),
+            Value::Integer(a) => Some(a.to_f64().unwrap_or(f64::NAN)),
+            Value::Rational(a) => Some(a.to_f64().unwrap_or(f64::NAN)),
+            Value::Decimal(a) => Some(a.to_f64().unwrap_or(f64::NAN)),
+            Value::Complex(a) if a.im == 0. => Some(a.re),
+            Value::Complex(_) | Value::Boolean(_) | Value::List(_) | Value::Matrix(_) => None,
+        }
+    }
+
+    /// The value as an exact integer, if it is one. Whole `Number`s count when every integer
+    /// up to them is exact in an `f64`.
+    pub fn integer(&self) -> Option<BigInt> {
+        match self {
+            Value::Integer(a) => Some(a.clone()),
+            Value::Rational(a) if a.is_integer() => Some(a.to_integer()),
+            Value::Decimal(a) if a.is_integer() => Some(a.with_scale(0).into_bigint_and_exponent().0),
+            Value::Number(a) if a.fract() == 0. && a.abs() <= 9007199254740992. => Some(BigInt::from(*a as i64)),
+            _ => None,
+        }
+    }
+
+    /// The value as an exact fraction, if it is exact or a whole `Number` that `integer` takes.
+    pub fn rational(&self) -> Option<BigRational> {
+        match self {
+            Value::Rational(a) => Some(a.clone()),
+            Value::Decimal(a) => Some(decimal::to_rational(a)),
+            a => a.integer().map(BigRational::from_integer),
+        }
+    }
+
+    /// The value as a decimal, exact except for fractions, which are rounded to `precision`
+    /// significant digits. Floating point numbers are read as their shortest decimal form.
+    /// `None` past `decimal::MAX_EXPONENT`.
+    pub fn decimal(&self, precision: u64) -> Option<BigDecimal> {
+        match self {
+            Value::Number(a) => decimal::from_f64(*a),
+            Value::Integer(a) => Some(BigDecimal::from(a.clone())).filter(decimal::in_range),
+            Value::Rational(a) => Some(decimal::div(&BigDecimal::from(a.numer().clone()), &BigDecimal::from(a.denom().clone()), precision)).filter(decimal::in_range),
+            Value::Decimal(a) => Some(a.clone()),
+            Value::Complex(_) | Value::Boolean(_) | Value::List(_) | Value::Matrix(_) => None,
+        }
+    }
+
+    /// `rational` for exact values only, never for a `Number`. Decimals count as exact.
+    fn exact(&self) -> Option<BigRational> {
+        match self {
+            Value::Number(_) | Value::Complex(_) | Value::Boolean(_) | Value::List(_) | Value::Matrix(_) => None,
+            a => a.rational(),
+        }
+    }
+
+    /// The value as shown to the user, with complex numbers in `form` and polar angles in
+    /// `angles` units.
+    pub fn format(&self, form: ComplexForm, angles: AngleMode) -> String {
+        match (self, form) {
+            (Value::Complex(a), ComplexForm::Polar) => {
+                let angle = angles.from_radians(a.arg());
+                format!("{}∠{}{}", snap(a.norm(), a.norm()), snap(angle, angle.abs()), angles.suffix())
+            }
+            (Value::List(a), form) => format!("[{}]", a.iter().map(|a| a.format(form, angles)).collect::<Vec<String>>().join(", ")),
+            (Value::Matrix(a), form) => {
+                let rows: Vec<Value> = a.iter().map(|row| Value::List(row.clone())).collect();
+                Value::List(rows).format(form, angles)
+            }
+            (a, _) => a.to_string(),
+        }
+    }
+
+    /// Unwraps a boolean, reporting a type error at `span` for anything else.
+    pub fn boolean(self, span: Span) -> Result<bool, EvalError> {
+        match self {
+            Value::Boolean(a) => Ok(a),
+            a => Err(EvalError::TypeMismatch { expected: "boolean", found: a.type_name(), span }),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(a) => write!(f, "{}", a),
+            Value::Integer(a) => write!(f, "{}", a),
+            Value::Rational(a) if a.denom().is_one() => write!(f, "{}", a.numer()),
+            Value::Rational(a) => write!(f, "{}/{}", a.numer(), a.denom()),
+            Value::Decimal(a) => write!(f, "{}", decimal::format(a)),
+            Value::Complex(a) => {
+                let imaginary = |im: f64| match im {
+                    1. => "i".to_string(),
+                    -1. => "-i".to_string(),
+                    im => format!("{}i", im),
+                };
+                match (a.re, a.im) {
+                    (0., im) => write!(f, "{}", imaginary(im)),
+                    (re, im) if im < 0. => write!(f, "{} - {}", re, imaginary(-im)),
+                    (re, im) => write!(f, "{} + {}", re, imaginary(im)),
+                }
+            }
+            Value::Boolean(a) => write!(f, "{}", a),
+            Value::List(a) => write!(f, "[{}]", a.iter().map(|a| a.to_string()).collect::<Vec<String>>().join(", ")),
+            Value::Matrix(a) => write!(f, "{}", Value::List(a.iter().map(|row| Value::List(row.clone())).collect())),
+        }
+    }
+}
@@ -3,6 +3,9 @@ import './App.css';
 import React from 'react';
 import Graph from './components/Graph/Graph';
 
+// Identifies this page's variables on the server, so `a = 5` is remembered between inputs.
+const SESSION_ID = Math.random().toString(36).slice(2);
+
 function App() {
   const [response_boxes, set_response_boxes] = useState([]);
   const [graph_points, set_graph_points] = useState([]);
@@ -55,7 +58,7 @@ function App() {
             headers: {
                 'Content-Type': 'application/json',
             },
-            body: JSON.stringify({ text: inputText })
+            body: JSON.stringify({ text: inputText, session: SESSION_ID })
         })
         .then(response => {
             if (!response.ok) {